@@ -0,0 +1,86 @@
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Aabb<T: ElemT> {
+    min: Vec3<T>,
+    max: Vec3<T>
+}
+
+impl<T: ElemT> Aabb<T> {
+    pub fn new(min: Vec3<T>, max: Vec3<T>) -> Aabb<T> {
+        Aabb::<T> {
+            min,
+            max
+        }
+    }
+
+    pub fn min(&self) -> &Vec3<T> { &self.min }
+    pub fn max(&self) -> &Vec3<T> { &self.max }
+
+    pub fn centroid(&self) -> Vec3<T> {
+        (&self.min + &self.max) / T::from_f64(2.).unwrap()
+    }
+
+    pub fn surface_area(&self) -> T {
+        let d = &self.max - &self.min;
+        (d.x()*d.y() + d.y()*d.z() + d.z()*d.x()) * T::from_f64(2.).unwrap()
+    }
+
+    pub fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> bool {
+        self.interval(r, t_min, t_max).is_some()
+    }
+
+    // slab test: the part of (t_min, t_max) the ray spends inside the box
+    pub fn interval(&self, r: &Ray<T>, mut t_min: T, mut t_max: T) -> Option<(T, T)> {
+        let origin = r.origin();
+        let direction = r.direction();
+        for a in 0..3 {
+            let inv_d = T::one() / direction[a];
+            let mut t0 = (self.min[a] - origin[a]) * inv_d;
+            let mut t1 = (self.max[a] - origin[a]) * inv_d;
+            if inv_d < T::zero() {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+}
+
+pub fn surrounding_box<T: ElemT>(a: &Aabb<T>, b: &Aabb<T>) -> Aabb<T> {
+    Aabb::new(Vec3::new(a.min.x().min(b.min.x()), a.min.y().min(b.min.y()), a.min.z().min(b.min.z())),
+              Vec3::new(a.max.x().max(b.max.x()), a.max.y().max(b.max.y()), a.max.z().max(b.max.z())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Aabb, surrounding_box};
+    use vec3::Vec3;
+    use ray::Ray;
+
+    #[test]
+    fn test_hit() {
+        let b = Aabb::new(Vec3::<f64>::new(-1., -1., -1.), Vec3::new(1., 1., 1.));
+        assert!(b.hit(&Ray::new(Vec3::new(0., 0., -5.), Vec3::new(0., 0., 1.)), 0., 100.));
+        assert!(!b.hit(&Ray::new(Vec3::new(0., 2., -5.), Vec3::new(0., 0., 1.)), 0., 100.));
+        assert!(!b.hit(&Ray::new(Vec3::new(0., 0., -5.), Vec3::new(0., 0., 1.)), 0., 3.));
+    }
+
+    #[test]
+    fn test_surrounding_box() {
+        let a = Aabb::new(Vec3::<f64>::new(0., 0., 0.), Vec3::new(1., 1., 1.));
+        let b = Aabb::new(Vec3::<f64>::new(-1., 0.5, 0.), Vec3::new(0.5, 2., 3.));
+        let c = surrounding_box(&a, &b);
+        assert_approx_eq!(-1.0, c.min().x());
+        assert_approx_eq!(0.0, c.min().y());
+        assert_approx_eq!(2.0, c.max().y());
+        assert_approx_eq!(3.0, c.max().z());
+        assert_approx_eq!(32.0, c.surface_area());
+    }
+}
@@ -0,0 +1,90 @@
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+use hitable::{HitRecord, Hitable};
+use material::Material;
+use aabb::Aabb;
+use rect::Quad;
+use rng::Sampler;
+
+// An axis-aligned box made of six faces with outward normals, all sharing
+// one material. Each face has its own (u, v) from 0 to 1.
+pub struct AaBox<T: ElemT> {
+    min: Vec3<T>,
+    max: Vec3<T>,
+    faces: Vec<Quad<T>>,
+    material: Box<dyn Material<T>>
+}
+
+impl<T: ElemT> AaBox<T> {
+    // `p0` and `p1` are any two opposite corners
+    pub fn new(p0: &Vec3<T>, p1: &Vec3<T>, mat: Box<dyn Material<T>>) -> AaBox<T> {
+        let min = Vec3::new(p0.x().min(p1.x()), p0.y().min(p1.y()), p0.z().min(p1.z()));
+        let max = Vec3::new(p0.x().max(p1.x()), p0.y().max(p1.y()), p0.z().max(p1.z()));
+        let dx = Vec3::new(max.x() - min.x(), T::zero(), T::zero());
+        let dy = Vec3::new(T::zero(), max.y() - min.y(), T::zero());
+        let dz = Vec3::new(T::zero(), T::zero(), max.z() - min.z());
+        let faces = vec![
+            Quad::new(Vec3::new(min.x(), min.y(), max.z()), dx.clone(), dy.clone()),  // front
+            Quad::new(Vec3::new(max.x(), min.y(), max.z()), -dz.clone(), dy.clone()), // right
+            Quad::new(Vec3::new(max.x(), min.y(), min.z()), -dx.clone(), dy.clone()), // back
+            Quad::new(Vec3::new(min.x(), min.y(), min.z()), dz.clone(), dy.clone()),  // left
+            Quad::new(Vec3::new(min.x(), max.y(), max.z()), dx.clone(), -dz.clone()), // top
+            Quad::new(min.clone(), dx, dz)                                            // bottom
+        ];
+        AaBox { min, max, faces, material: mat }
+    }
+
+    fn area(&self) -> T {
+        self.faces.iter().fold(T::zero(), |sum, f| sum + f.area())
+    }
+}
+
+impl<T: ElemT> Hitable<T> for AaBox<T> {
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        let mut closest: Option<(usize, T, T, T)> = None;
+        let mut t_max = t_max;
+        for (i, face) in self.faces.iter().enumerate() {
+            if let Some((t, u, v)) = face.intersect(r, t_min, t_max) {
+                t_max = t;
+                closest = Some((i, t, u, v));
+            }
+        }
+        let (i, t, u, v) = closest?;
+        Some(HitRecord {
+            t,
+            u,
+            v,
+            p: r.point_at_parameter(t),
+            normal: self.faces[i].normal().clone(),
+            mat_opt: Some(&*self.material)
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        Some(Aabb::new(self.min.clone(), self.max.clone()))
+    }
+
+    // A face is picked in proportion to its area, then a point on it, which
+    // is the same as picking uniformly over the whole surface
+    fn sample(&self, origin: &Vec3<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, T)> {
+        let mut x = T::from_f64(sampler.next_f64()).unwrap() * self.area();
+        let face = self.faces.iter().find(|f| {
+            x -= f.area();
+            x < T::zero()
+        }).unwrap_or(&self.faces[5]);
+        let (p, _) = face.sample(origin, sampler)?;
+        let direction = &p - origin;
+        Some((p, self.pdf(origin, &direction)))
+    }
+
+    fn pdf(&self, origin: &Vec3<T>, direction: &Vec3<T>) -> T {
+        let area = self.area();
+        self.faces.iter().fold(T::zero(), |sum, f| sum + f.pdf(origin, direction) * f.area() / area)
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Hitable<T>>) {
+        if self.material.is_emissive() {
+            lights.push(self);
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use std::sync::Arc;
+
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+use hitable::{HitRecord, Hitable};
+use aabb::{Aabb, surrounding_box};
+use mat4::Mat4;
+use transformed::{hit_transformed, hit_surface_transformed, transmittance_transformed};
+
+// Where an object is at one instant: scaled, rotated about x, y and z in
+// turn (in degrees), then translated, like a scene object's transform
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Keyframe<T: ElemT> {
+    pub time: T,
+    pub scale: Vec3<T>,
+    pub rotate: Vec3<T>,
+    pub translate: Vec3<T>
+}
+
+impl<T: ElemT> Keyframe<T> {
+    pub fn matrix(&self) -> Mat4<T> {
+        Mat4::scale_rotate_translate(&self.scale, &self.rotate, &self.translate)
+    }
+
+    fn lerp(&self, other: &Keyframe<T>, s: T) -> Keyframe<T> {
+        let mix = |a: &Vec3<T>, b: &Vec3<T>| a + &((b - a)*s);
+        Keyframe {
+            time: self.time + (other.time - self.time)*s,
+            scale: mix(&self.scale, &other.scale),
+            rotate: mix(&self.rotate, &other.rotate),
+            translate: mix(&self.translate, &other.translate)
+        }
+    }
+}
+
+// A shared object moving along a path of keyframes. Scale, rotation angles
+// and translation are each interpolated linearly between the keyframes either
+// side of a ray's time, and held at the first and last keyframe outside them.
+// Animated objects aren't sampled as lights; their emission is only picked up
+// by rays that hit them.
+pub struct Animated<T: ElemT> {
+    object: Arc<dyn Hitable<T>>,
+    keyframes: Vec<Keyframe<T>>,
+    bbox: Option<Aabb<T>>
+}
+
+fn transformed_box<T: ElemT>(b: &Aabb<T>, m: &Mat4<T>) -> Aabb<T> {
+    let corners = (0..8).map(|i| {
+        let pick = |bit: usize, a: usize| if i & bit == 0 { b.min()[a] } else { b.max()[a] };
+        m.transform_point(&Vec3::new(pick(1, 0), pick(2, 1), pick(4, 2)))
+    }).collect::<Vec<_>>();
+    let mut min = corners[0].clone();
+    let mut max = corners[0].clone();
+    for c in &corners[1..] {
+        for a in 0..3 {
+            min[a] = min[a].min(c[a]);
+            max[a] = max[a].max(c[a]);
+        }
+    }
+    Aabb::new(min, max)
+}
+
+impl<T: ElemT> Animated<T> {
+    // Keyframes are sorted by time. Panics if there are none, or if any
+    // scales by zero.
+    pub fn new(object: Arc<dyn Hitable<T>>, mut keyframes: Vec<Keyframe<T>>) -> Animated<T> {
+        assert!(!keyframes.is_empty(), "an animation needs at least one keyframe");
+        assert!(keyframes.iter().all(|k| k.scale.x()*k.scale.y()*k.scale.z() != T::zero()),
+                "keyframes can't scale by zero");
+        keyframes.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+        let bbox = object.bounding_box().map(|b| Animated::bound(&b, &keyframes));
+        Animated { object, keyframes, bbox }
+    }
+
+    // While the rotation holds still every corner of the box moves linearly,
+    // so the boxes at the keyframes bound the motion between them. Otherwise
+    // the corners stay within reach of the moving origin: no further than the
+    // largest scaled corner, and scaling is convex so that's largest at one of
+    // the two keyframes.
+    fn bound(b: &Aabb<T>, keyframes: &[Keyframe<T>]) -> Aabb<T> {
+        let mut bbox = transformed_box(b, &keyframes[0].matrix());
+        for pair in keyframes.windows(2) {
+            let (k0, k1) = (&pair[0], &pair[1]);
+            bbox = surrounding_box(&bbox, &transformed_box(b, &k1.matrix()));
+            if (&k0.rotate - &k1.rotate).squared_length() > T::zero() {
+                let reach = |k: &Keyframe<T>| {
+                    let corner = |a: usize| b.min()[a].abs().max(b.max()[a].abs()) * k.scale[a].abs();
+                    Vec3::new(corner(0), corner(1), corner(2)).length()
+                };
+                let r = reach(k0).max(reach(k1));
+                let r = Vec3::new(r, r, r);
+                let swept = surrounding_box(&Aabb::new(&k0.translate - &r, &k0.translate + &r),
+                                            &Aabb::new(&k1.translate - &r, &k1.translate + &r));
+                bbox = surrounding_box(&bbox, &swept);
+            }
+        }
+        bbox
+    }
+
+    pub fn keyframe_at(&self, time: T) -> Keyframe<T> {
+        let frames = &self.keyframes;
+        let last = frames.len() - 1;
+        if time <= frames[0].time {
+            return frames[0].clone();
+        }
+        if time >= frames[last].time {
+            return frames[last].clone();
+        }
+        let i = frames.iter().position(|k| k.time > time).unwrap();
+        let (k0, k1) = (&frames[i-1], &frames[i]);
+        k0.lerp(k1, (time - k0.time) / (k1.time - k0.time))
+    }
+}
+
+impl<T: ElemT> Hitable<T> for Animated<T> {
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        let to_object = self.keyframe_at(r.time()).matrix().inverse()?;
+        hit_transformed(&*self.object, &to_object, r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        self.bbox.clone()
+    }
+
+    fn hit_surface(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        let to_object = self.keyframe_at(r.time()).matrix().inverse()?;
+        hit_surface_transformed(&*self.object, &to_object, r, t_min, t_max)
+    }
+
+    fn transmittance(&self, r: &Ray<T>, t_min: T, t_max: T) -> T {
+        match self.keyframe_at(r.time()).matrix().inverse() {
+            Some(to_object) => transmittance_transformed(&*self.object, &to_object, r, t_min, t_max),
+            None => T::one()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Animated, Keyframe};
+    use std::sync::Arc;
+    use vec3::Vec3;
+    use ray::Ray;
+    use hitable::Hitable;
+    use sphere::Sphere;
+    use trianglemesh::TriangleMesh;
+    use lambertian::Lambertian;
+
+    fn keyframe(time: f64, rotate: Vec3<f64>, translate: Vec3<f64>) -> Keyframe<f64> {
+        Keyframe { time, scale: Vec3::new(1., 1., 1.), rotate, translate }
+    }
+
+    #[test]
+    fn test_keyframes() {
+        let sphere: Arc<dyn Hitable<f64>> = Arc::new(Sphere::new(Vec3::new(0., 0., 0.), 0.5,
+                                                                 Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))));
+        let animated = Animated::new(sphere, vec![keyframe(1., Vec3::default(), Vec3::new(4., 0., 0.)),
+                                                  keyframe(0., Vec3::default(), Vec3::default())]);
+        let k = animated.keyframe_at(0.25);
+        assert_approx_eq!(1.0, k.translate.x());
+        assert_approx_eq!(4.0, animated.keyframe_at(2.).translate.x());
+
+        let at = |time: f64| Ray::with_time(Vec3::new(1., 0., 5.), Vec3::new(0., 0., -1.), time);
+        assert!(animated.hit(&at(0.), 0., 100.).is_none());
+        let rec = animated.hit(&at(0.25), 0., 100.).unwrap();
+        assert_approx_eq!(4.5, rec.t);
+        assert_approx_eq!(1.0, rec.normal.z());
+
+        let bbox = animated.bounding_box().unwrap();
+        assert_approx_eq!(-0.5, bbox.min().x());
+        assert_approx_eq!(4.5, bbox.max().x());
+    }
+
+    #[test]
+    fn test_rotating_bounds() {
+        // a thin triangle off the origin swinging a full half turn about y
+        let mesh: Arc<dyn Hitable<f64>> = Arc::new(TriangleMesh::new(
+            vec![Vec3::new(2., 0., -0.1), Vec3::new(2., 0., 0.1), Vec3::new(2., 1., 0.)], vec![[0, 1, 2]],
+            None, None, Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))));
+        let animated = Animated::new(mesh, vec![keyframe(0., Vec3::default(), Vec3::default()),
+                                                keyframe(1., Vec3::new(0., 180., 0.), Vec3::default())]);
+        let bbox = animated.bounding_box().unwrap();
+        for i in 0..=20 {
+            let time = i as f64 / 20.;
+            let rotate = animated.keyframe_at(time).rotate.y().to_radians();
+            // the triangle sits 2 out along the rotated x axis
+            let (x, z) = (2.*rotate.cos(), -2.*rotate.sin());
+            assert!(bbox.min().x() <= x && x <= bbox.max().x() && bbox.min().z() <= z && z <= bbox.max().z());
+            let r = Ray::with_time(Vec3::new(0., 0.25, 0.), Vec3::new(x, 0., z), time);
+            assert_approx_eq!(1.0, animated.hit(&r, 0., 100.).unwrap().t);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+use rng::Sampler;
+use material::Material;
+use phase::HenyeyGreenstein;
+
+// A homogeneous medium filling all the space between the objects, like haze
+// or murky water. Absorption and scattering are given per unit length for
+// each colour channel, and light scatters by a Henyey-Greenstein phase
+// function with mean cosine `g`.
+pub struct Atmosphere<T: ElemT> {
+    scattering: Vec3<T>,
+    extinction: Vec3<T>,
+    phase: HenyeyGreenstein<T>
+}
+
+impl<T: ElemT> Atmosphere<T> {
+    pub fn new(absorption: Vec3<T>, scattering: Vec3<T>, g: T) -> Atmosphere<T> {
+        Atmosphere {
+            extinction: &absorption + &scattering,
+            scattering,
+            phase: HenyeyGreenstein::new(Vec3::new(T::one(), T::one(), T::one()), g)
+        }
+    }
+
+    pub fn phase(&self) -> &dyn Material<T> {
+        &self.phase
+    }
+
+    // The fraction of light getting through `distance` of it
+    pub fn transmittance(&self, distance: T) -> Vec3<T> {
+        let mut tr = Vec3::default();
+        for c in 0..3 {
+            // also keeps an infinite distance through a clear channel at one
+            tr[c] = if self.extinction[c] > T::zero() { (-self.extinction[c]*distance).exp() } else { T::one() };
+        }
+        tr
+    }
+
+    // Picks where along `r`, before `t_max`, it scatters, if it does. Returns
+    // that and the weight of the choice: the scattering or transmittance
+    // over its probability. The distance is drawn for one channel picked at
+    // random, and the probability is the average over all three, so coloured
+    // media don't get noisy.
+    pub fn sample(&self, r: &Ray<T>, t_max: T, sampler: &mut Sampler) -> (Option<T>, Vec3<T>) {
+        let speed = r.direction().length();
+        let length = if t_max == T::max_value() { T::infinity() } else { t_max * speed };
+        let third = T::from_f64(1./3.).unwrap();
+        let channel = ((sampler.next_f64() * 3.) as usize).min(2);
+        let sigma = self.extinction[channel];
+        if sigma > T::zero() {
+            let distance = -T::from_f64((1. - sampler.next_f64()).ln()).unwrap() / sigma;
+            if distance < length {
+                let tr = self.transmittance(distance);
+                let pdf = (&self.extinction * &tr).dot(&Vec3::new(third, third, third));
+                return (Some(distance / speed), &self.scattering * &tr / pdf);
+            }
+        }
+        let tr = self.transmittance(length);
+        let p = tr.dot(&Vec3::new(third, third, third));
+        (None, if p > T::zero() { tr / p } else { Vec3::default() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Atmosphere;
+    use vec3::Vec3;
+    use ray::Ray;
+    use rng::Sampler;
+
+    #[test]
+    fn test_sample() {
+        let mut sampler = Sampler::new(7);
+        let air = Atmosphere::new(Vec3::new(0.1, 0.2, 0.), Vec3::new(0.2, 0.1, 0.3), 0.);
+        let tr = air.transmittance(2.);
+        assert_approx_eq!((-0.6f64).exp(), tr.x());
+        assert_approx_eq!((-0.6f64).exp(), tr.z());
+        assert_approx_eq!(0.0, air.transmittance(f64::MAX).y());
+
+        // The weights are unbiased: passing a wall 4 away gives the
+        // transmittance on average, and scattering in front of it gives
+        // what's scattered back out of the way, 1 - transmittance times the
+        // single scattering albedo
+        let r = Ray::new(Vec3::new(0., 0., 0.), Vec3::new(0., 0., 2.));
+        let n = 200000;
+        let mut passed = Vec3::default();
+        let mut scattered = Vec3::default();
+        for _ in 0..n {
+            match air.sample(&r, 2., &mut sampler) {
+                (Some(t), w) => {
+                    assert!(t < 2.);
+                    scattered += w;
+                }
+                (None, w) => passed += w
+            }
+        }
+        let expect = air.transmittance(4.);
+        for c in 0..3 {
+            assert_approx_eq!(expect[c], passed[c] / n as f64, 0.01);
+            let albedo = [2./3., 1./3., 1.][c];
+            assert_approx_eq!((1. - expect[c]) * albedo, scattered[c] / n as f64, 0.01);
+        }
+    }
+}
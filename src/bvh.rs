@@ -0,0 +1,274 @@
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+use hitable::{HitRecord, Hitable};
+use aabb::{Aabb, surrounding_box};
+
+const MAX_LEAF_SIZE: usize = 4;
+const NUM_BINS: usize = 12;
+// cost of visiting a node relative to intersecting one object
+const TRAVERSAL_COST: f64 = 0.125;
+
+type ChildrenT<T> = (Box<BvhNode<T>>, Box<BvhNode<T>>);
+
+pub struct BvhNode<T: ElemT> {
+    bbox: Option<Aabb<T>>,
+    children: Option<ChildrenT<T>>,
+    objects: Vec<Box<dyn Hitable<T>>>
+}
+
+struct Primitive<T: ElemT> {
+    object: Box<dyn Hitable<T>>,
+    bbox: Aabb<T>,
+    centroid: Vec3<T>
+}
+
+#[derive(Clone)]
+struct Bin<T: ElemT> {
+    bbox: Option<Aabb<T>>,
+    count: usize
+}
+
+fn union<T: ElemT>(a: Option<Aabb<T>>, b: &Aabb<T>) -> Option<Aabb<T>> {
+    Some(match a {
+        Some(a) => surrounding_box(&a, b),
+        None => b.clone()
+    })
+}
+
+fn area<T: ElemT>(b: &Option<Aabb<T>>) -> f64 {
+    b.as_ref().map_or(0., |b| b.surface_area().to_f64().unwrap())
+}
+
+impl<T: ElemT> BvhNode<T> {
+    pub fn new(list: Vec<Box<dyn Hitable<T>>>) -> BvhNode<T> {
+        let mut prims = Vec::new();
+        let mut unbounded = Vec::new();
+        for object in list {
+            match object.bounding_box() {
+                Some(bbox) => prims.push(Primitive { centroid: bbox.centroid(), bbox, object }),
+                None => unbounded.push(object)
+            }
+        }
+
+        let mut root = BvhNode::build(prims);
+        if !unbounded.is_empty() {
+            // unbounded objects (e.g. planes) can't be culled, so they're
+            // tested at the root on every ray
+            root.bbox = None;
+            root.objects.extend(unbounded);
+        }
+        root
+    }
+
+    fn leaf(prims: Vec<Primitive<T>>, bbox: Option<Aabb<T>>) -> BvhNode<T> {
+        BvhNode::<T> {
+            bbox,
+            children: None,
+            objects: prims.into_iter().map(|p| p.object).collect()
+        }
+    }
+
+    fn interior(left: Vec<Primitive<T>>, right: Vec<Primitive<T>>, bbox: Option<Aabb<T>>) -> BvhNode<T> {
+        BvhNode::<T> {
+            bbox,
+            children: Some((Box::new(BvhNode::build(left)), Box::new(BvhNode::build(right)))),
+            objects: Vec::new()
+        }
+    }
+
+    fn build(mut prims: Vec<Primitive<T>>) -> BvhNode<T> {
+        let n = prims.len();
+        let bbox = prims.iter().fold(None, |b, p| union(b, &p.bbox));
+        if n <= 1 {
+            return BvhNode::leaf(prims, bbox);
+        }
+
+        // split along the axis where the centroids are spread out the most
+        let centroid_bounds = prims.iter()
+            .fold(None, |b, p| union(b, &Aabb::new(p.centroid.clone(), p.centroid.clone())))
+            .unwrap();
+        let extent = centroid_bounds.max() - centroid_bounds.min();
+        let axis = if extent.x() > extent.y() && extent.x() > extent.z() { 0 }
+                   else if extent.y() > extent.z() { 1 }
+                   else { 2 };
+        let (cmin, cextent) = (centroid_bounds.min()[axis], extent[axis]);
+
+        if cextent <= T::zero() {
+            // every centroid coincides, so SAH can't separate them
+            if n <= MAX_LEAF_SIZE {
+                return BvhNode::leaf(prims, bbox);
+            }
+            let right = prims.split_off(n/2);
+            return BvhNode::interior(prims, right, bbox);
+        }
+
+        let bin_of = |p: &Primitive<T>| {
+            let b = ((p.centroid[axis] - cmin) / cextent * T::from_usize(NUM_BINS).unwrap())
+                .to_usize().unwrap_or(0);
+            b.min(NUM_BINS - 1)
+        };
+
+        let mut bins = vec![Bin::<T> { bbox: None, count: 0 }; NUM_BINS];
+        for p in &prims {
+            let bin = &mut bins[bin_of(p)];
+            bin.bbox = union(bin.bbox.take(), &p.bbox);
+            bin.count += 1;
+        }
+
+        // sweep to find the cheapest split between bins
+        let parent_area = area(&bbox);
+        let mut best: Option<(usize, f64)> = None;
+        for split in 1..NUM_BINS {
+            let (left, right) = bins.split_at(split);
+            let sum = |bins: &[Bin<T>]| bins.iter().fold((None, 0), |(b, c), bin| {
+                match bin.bbox {
+                    Some(ref bb) => (union(b, bb), c + bin.count),
+                    None => (b, c)
+                }
+            });
+            let (lbox, lcount) = sum(left);
+            let (rbox, rcount) = sum(right);
+            if lcount == 0 || rcount == 0 {
+                continue;
+            }
+            let cost = TRAVERSAL_COST
+                     + (area(&lbox)*lcount as f64 + area(&rbox)*rcount as f64) / parent_area;
+            if best.is_none_or(|(_, c)| cost < c) {
+                best = Some((split, cost));
+            }
+        }
+
+        let (split, cost) = best.unwrap();
+        if n <= MAX_LEAF_SIZE && cost >= n as f64 {
+            return BvhNode::leaf(prims, bbox);
+        }
+
+        let (left, right) = prims.into_iter().partition(|p| bin_of(p) < split);
+        BvhNode::interior(left, right, bbox)
+    }
+}
+
+impl<T: ElemT> BvhNode<T> {
+    // The nearest hit of `hit` (which is Hitable::hit or hit_surface) on
+    // anything in the tree
+    fn nearest<'a, F>(&'a self, r: &Ray<T>, t_min: T, t_max: T, hit: &F) -> Option<HitRecord<'a, T>>
+        where F: Fn(&'a dyn Hitable<T>, T) -> Option<HitRecord<'a, T>> {
+        if let Some(ref bbox) = self.bbox {
+            if !bbox.hit(r, t_min, t_max) {
+                return None;
+            }
+        }
+
+        let mut ret: Option<HitRecord<T>> = None;
+        let mut closest_so_far = t_max;
+        for h in &self.objects {
+            if let Some(rec) = hit(&**h, closest_so_far) {
+                closest_so_far = rec.t;
+                ret = Some(rec);
+            }
+        }
+        if let Some((ref left, ref right)) = self.children {
+            if let Some(rec) = left.nearest(r, t_min, closest_so_far, hit) {
+                closest_so_far = rec.t;
+                ret = Some(rec);
+            }
+            if let Some(rec) = right.nearest(r, t_min, closest_so_far, hit) {
+                ret = Some(rec);
+            }
+        }
+        ret
+    }
+}
+
+impl<T: ElemT> Hitable<T> for BvhNode<T> {
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        self.nearest(r, t_min, t_max, &|h, t_max| h.hit(r, t_min, t_max))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        self.bbox.clone()
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Hitable<T>>) {
+        for h in &self.objects {
+            h.collect_lights(lights);
+        }
+        if let Some((ref left, ref right)) = self.children {
+            left.collect_lights(lights);
+            right.collect_lights(lights);
+        }
+    }
+
+    fn hit_surface(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        self.nearest(r, t_min, t_max, &|h, t_max| h.hit_surface(r, t_min, t_max))
+    }
+
+    fn transmittance(&self, r: &Ray<T>, t_min: T, t_max: T) -> T {
+        if let Some(ref bbox) = self.bbox {
+            if !bbox.hit(r, t_min, t_max) {
+                return T::one();
+            }
+        }
+        let mut tr = self.objects.iter().fold(T::one(), |tr, h| tr * h.transmittance(r, t_min, t_max));
+        if let Some((ref left, ref right)) = self.children {
+            tr = tr * left.transmittance(r, t_min, t_max) * right.transmittance(r, t_min, t_max);
+        }
+        tr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BvhNode;
+    use vec3::Vec3;
+    use ray::Ray;
+    use hitable::Hitable;
+    use hitablelist::HitableList;
+    use sphere::Sphere;
+    use lambertian::Lambertian;
+    use rng::Sampler;
+
+    fn random_spheres(n: usize, sampler: &mut Sampler) -> Vec<Box<dyn Hitable<f64>>> {
+        let mut list = Vec::<Box<dyn Hitable<f64>>>::new();
+        for _ in 0..n {
+            let center = Vec3::new(sampler.next_f64()*20. - 10., sampler.next_f64()*20. - 10., sampler.next_f64()*20. - 10.);
+            list.push(Box::new(Sphere::new(center, 0.1 + sampler.next_f64(),
+                                           Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))))));
+        }
+        list
+    }
+
+    #[test]
+    fn test_matches_linear_list() {
+        let bvh = BvhNode::new(random_spheres(300, &mut Sampler::new(7)));
+        let list = HitableList::new(random_spheres(300, &mut Sampler::new(7)));
+
+        let mut sampler = Sampler::new(8);
+        for _ in 0..1000 {
+            let origin = Vec3::new(sampler.next_f64()*40. - 20., sampler.next_f64()*40. - 20., sampler.next_f64()*40. - 20.);
+            let direction = Vec3::new(sampler.next_f64() - 0.5, sampler.next_f64() - 0.5, sampler.next_f64() - 0.5);
+            let r = Ray::new(origin, direction);
+            match (bvh.hit(&r, 0.001, 1e9), list.hit(&r, 0.001, 1e9)) {
+                (Some(a), Some(b)) => assert_approx_eq!(a.t, b.t),
+                (None, None) => {},
+                _ => panic!("bvh and list disagree")
+            }
+        }
+    }
+
+    #[test]
+    fn test_hollow_sphere() {
+        // the book's hollow glass ball: a negative radius flips the normals
+        // but mustn't turn the sphere's box inside out and lose it
+        let list: Vec<Box<dyn Hitable<f64>>> = vec![
+            Box::new(Sphere::new(Vec3::new(0., 0., 0.), -1., Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))))),
+            Box::new(Sphere::new(Vec3::new(5., 0., 0.), 0.5, Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))))
+        ];
+        let bvh = BvhNode::new(list);
+        let bbox = bvh.bounding_box().unwrap();
+        assert_eq!(-1., bbox.min().x());
+        let rec = bvh.hit(&Ray::new(Vec3::new(0., 0., 5.), Vec3::new(0., 0., -1.)), 0.001, 1e9).unwrap();
+        assert_approx_eq!(4.0, rec.t);
+        assert_approx_eq!(-1.0, rec.normal.z());
+    }
+}
@@ -1,10 +1,9 @@
-extern crate rand;
-
 use vec3::{ElemT, Vec3};
 use ray::Ray;
+use rng::Sampler;
+use sampling;
 
 use std::f64::consts;
-use rand::Rng;
 
 pub struct Camera<T: ElemT> {
     origin: Vec3<T>,
@@ -13,18 +12,14 @@ pub struct Camera<T: ElemT> {
     vertical: Vec3<T>,
     u: Vec3<T>,
     v: Vec3<T>,
-    lens_radius: T
+    lens_radius: T,
+    time0: T,
+    time1: T
 }
 
-fn random_in_unit_disk<T: ElemT>() -> Vec3<T> {
-    let mut rng = rand::thread_rng();
-    let two = T::from_f64(2.).unwrap();
-    loop {
-        let r1 = T::from_f64(rng.next_f64()).unwrap();
-        let r2 = T::from_f64(rng.next_f64()).unwrap();
-        let p = Vec3::new(r1, r2, T::zero())*two - Vec3::new(T::one(), T::one(), T::zero());
-        if p.dot(&p) < T::one() { return p; }
-    }
+fn random_in_unit_disk<T: ElemT>(sampler: &mut Sampler) -> Vec3<T> {
+    let (x, y) = sampling::concentric_disk(T::from_f64(sampler.next_f64()).unwrap(), T::from_f64(sampler.next_f64()).unwrap());
+    Vec3::new(x, y, T::zero())
 }
 
 impl<T: ElemT> Camera<T> {
@@ -42,21 +37,34 @@ impl<T: ElemT> Camera<T> {
             lower_left_corner: &lookfrom - &u*focus_dist*half_width - &v*focus_dist*half_height - &w*focus_dist,
             horizontal: &u*half_width*focus_dist*two,
             vertical: &v*half_height*focus_dist*two,
-            u: u,
-            v: v,
-            lens_radius: lens_radius,
-            origin: lookfrom
+            u,
+            v,
+            lens_radius,
+            origin: lookfrom,
+            time0: T::zero(),
+            time1: T::zero()
         }
     }
 
-    pub fn get_ray(&self, s: T, t: T) -> Ray<T> {
-        let rd = random_in_unit_disk()*self.lens_radius;
+    // Rays are spread evenly over the time the shutter is open, from `open`
+    // to `close`, so anything moving in that time is blurred
+    pub fn with_shutter(mut self, open: T, close: T) -> Camera<T> {
+        self.time0 = open;
+        self.time1 = close;
+        self
+    }
+
+    pub fn get_ray(&self, s: T, t: T, sampler: &mut Sampler) -> Ray<T> {
+        let rd = random_in_unit_disk(sampler)*self.lens_radius;
         let offset = &self.u * rd.x() + &self.v * rd.y();
-        Ray::<T>::new(&self.origin + &offset,
-                      &self.lower_left_corner
-                          + &self.horizontal*s
-                          + &self.vertical*t
-                          - &self.origin
-                          - offset)
+        let time = if self.time1 > self.time0 { self.time0 + T::from_f64(sampler.next_f64()).unwrap()*(self.time1 - self.time0) }
+                   else { self.time0 };
+        Ray::<T>::with_time(&self.origin + &offset,
+                            &self.lower_left_corner
+                                + &self.horizontal*s
+                                + &self.vertical*t
+                                - &self.origin
+                                - offset,
+                            time)
     }
 }
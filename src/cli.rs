@@ -0,0 +1,182 @@
+use std::fmt;
+use std::thread;
+
+use imagewriter::{OutputFormat, ToneMap};
+
+pub const USAGE: &str = "\
+Usage: rust-raytracer-in-a-weekend [OPTIONS]
+
+Renders a scene and writes the image to stdout or a file. Without --scene the
+random sphere scene from the book is rendered.
+
+Options:
+  -s, --scene FILE        TOML scene description to render
+  -o, --output FILE       write the image to FILE instead of stdout
+  -f, --format FORMAT     image format: ppm, ppm-ascii, png, exr, exr-rgba,
+                          exr-float, exr-float-rgba, hdr or pfm. Defaults to
+                          the output file's extension, or ppm-ascii on stdout
+      --tonemap MODE      gamma or none. Defaults to gamma for 8-bit formats
+                          and none (linear radiance) for exr, hdr and pfm
+  -W, --width N           image width in pixels
+  -H, --height N          image height in pixels
+  -n, --samples N         samples per pixel
+  -d, --max-depth N       maximum number of bounces per path
+      --seed N            seed for the random numbers of each pixel
+                          and sample; the same seed renders the same image
+  -t, --threads N         number of render threads (default: one per core)
+  -h, --help              print this message
+";
+
+// Settings that aren't given stay None so the scene file can supply them
+#[derive(Debug)]
+pub struct Options {
+    pub scene: Option<String>,
+    pub output: Option<String>,
+    pub format: Option<OutputFormat>,
+    pub tone_map: Option<ToneMap>,
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub samples: Option<usize>,
+    pub max_depth: Option<usize>,
+    pub seed: Option<u64>,
+    pub threads: usize
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            scene: None,
+            output: None,
+            format: None,
+            tone_map: None,
+            width: None,
+            height: None,
+            samples: None,
+            max_depth: None,
+            seed: None,
+            threads: thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+        }
+    }
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum CliError {
+    Help,
+    Invalid(String)
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CliError::Help => write!(f, "{}", USAGE),
+            CliError::Invalid(ref msg) => write!(f, "{}", msg)
+        }
+    }
+}
+
+fn invalid<R>(msg: String) -> Result<R, CliError> {
+    Err(CliError::Invalid(msg))
+}
+
+fn positive(opt: &str, value: &str) -> Result<usize, CliError> {
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => invalid(format!("{} expects a positive integer, got '{}'", opt, value))
+    }
+}
+
+// Parses the arguments after the program name. Accepts both `--opt value`
+// and `--opt=value`.
+pub fn parse<I: Iterator<Item = String>>(args: I) -> Result<Options, CliError> {
+    let mut opts = Options::default();
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        let (opt, inline) = match arg.find('=') {
+            Some(i) if arg.starts_with("--") => (arg[..i].to_string(), Some(arg[i+1..].to_string())),
+            _ => (arg.clone(), None)
+        };
+        if opt == "-h" || opt == "--help" {
+            return Err(CliError::Help);
+        }
+
+        let value = match inline.or_else(|| args.next()) {
+            Some(v) => v,
+            None => return invalid(format!("{} expects a value", opt))
+        };
+        match opt.as_str() {
+            "-s" | "--scene" => opts.scene = Some(value),
+            "-o" | "--output" => opts.output = Some(value),
+            "-f" | "--format" => {
+                opts.format = match OutputFormat::from_name(&value) {
+                    Some(f) => Some(f),
+                    None => return invalid(format!("unknown output format '{}'", value))
+                };
+            }
+            "--tonemap" => {
+                opts.tone_map = match ToneMap::from_name(&value) {
+                    Some(t) => Some(t),
+                    None => return invalid(format!("unknown tone mapping '{}'", value))
+                };
+            }
+            "-W" | "--width" => opts.width = Some(positive(&opt, &value)?),
+            "-H" | "--height" => opts.height = Some(positive(&opt, &value)?),
+            "-n" | "--samples" => opts.samples = Some(positive(&opt, &value)?),
+            "-d" | "--max-depth" => opts.max_depth = Some(positive(&opt, &value)?),
+            "-t" | "--threads" => opts.threads = positive(&opt, &value)?,
+            "--seed" => {
+                opts.seed = match value.parse::<u64>() {
+                    Ok(n) => Some(n),
+                    Err(_) => return invalid(format!("--seed expects a non-negative integer, got '{}'", value))
+                };
+            }
+            _ => return invalid(format!("unknown option '{}'", opt))
+        }
+    }
+    Ok(opts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, CliError, Options, OutputFormat, ToneMap};
+    use hdrwriter::ExrPixelType;
+
+    fn parse_str(args: &str) -> Result<Options, CliError> {
+        parse(args.split_whitespace().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_parse() {
+        let opts = parse_str("--scene a.toml -o out.ppm --width=640 -H 480 -n 4 --max-depth 8 --seed 3 -t 2 -f PPM").unwrap();
+        assert_eq!(Some("a.toml".to_string()), opts.scene);
+        assert_eq!(Some("out.ppm".to_string()), opts.output);
+        assert_eq!(Some(OutputFormat::Ppm), opts.format);
+        assert_eq!(Some(640), opts.width);
+        assert_eq!(Some(480), opts.height);
+        assert_eq!(Some(4), opts.samples);
+        assert_eq!(Some(8), opts.max_depth);
+        assert_eq!(Some(3), opts.seed);
+        assert_eq!(2, opts.threads);
+        assert_eq!(None, opts.tone_map);
+
+        let opts = parse_str("-f exr-float-rgba --tonemap gamma").unwrap();
+        assert_eq!(Some(OutputFormat::Exr(ExrPixelType::Float, true)), opts.format);
+        assert_eq!(Some(ToneMap::Gamma), opts.tone_map);
+
+        let opts = parse_str("").unwrap();
+        assert_eq!(None, opts.width);
+        assert!(opts.threads > 0);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(CliError::Help, parse_str("-W 10 --help").unwrap_err());
+        assert!(parse_str("--width 0").is_err());
+        assert!(parse_str("--width -3").is_err());
+        assert!(parse_str("--samples many").is_err());
+        assert!(parse_str("--threads").is_err());
+        assert!(parse_str("--format gif").is_err());
+        assert!(parse_str("--tonemap reinhard").is_err());
+        assert!(parse_str("--frobnicate 1").is_err());
+    }
+}
@@ -0,0 +1,155 @@
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+use rng::Sampler;
+use hitable::HitRecord;
+use material::Material;
+use microfacet::{self, Ggx};
+use onb::Onb;
+
+// Complex indices of refraction (eta, k) of some metals at red, green and
+// blue wavelengths
+pub fn preset<T: ElemT>(name: &str) -> Option<(Vec3<T>, Vec3<T>)> {
+    let (eta, k) = match name {
+        "gold" => ([0.143, 0.374, 1.442], [3.983, 2.385, 1.603]),
+        "silver" => ([0.155, 0.117, 0.138], [4.828, 3.122, 2.147]),
+        "copper" => ([0.200, 0.924, 1.102], [3.912, 2.452, 2.142]),
+        "aluminium" => ([1.657, 0.880, 0.521], [9.224, 6.270, 4.837]),
+        _ => return None
+    };
+    let v = |a: [f64; 3]| Vec3::new(T::from_f64(a[0]).unwrap(), T::from_f64(a[1]).unwrap(), T::from_f64(a[2]).unwrap());
+    Some((v(eta), v(k)))
+}
+
+// A rough metal: a GGX microfacet BRDF with the Fresnel reflectance of a
+// complex index of refraction eta + ik per channel. Light lost to
+// shadowing between microfacets isn't brought back, so very rough metal is
+// a little dark. Below microfacet::SMOOTH_ALPHA it's a perfect mirror.
+#[derive(Clone)]
+pub struct Conductor<T: ElemT> {
+    eta: Vec3<T>,
+    k: Vec3<T>,
+    ggx: Ggx<T>
+}
+
+impl<T: ElemT> Conductor<T> {
+    pub fn new(eta: Vec3<T>, k: Vec3<T>, roughness: T) -> Conductor<T> {
+        Conductor { eta, k, ggx: Ggx::from_roughness(roughness) }
+    }
+
+    // The shading frame, with the normal on the side the ray came from, and
+    // the direction back along the ray in it
+    fn frame(&self, r_in: &Ray<T>, rec: &HitRecord<T>) -> (Onb<T>, Vec3<T>) {
+        let wo = -r_in.direction().unit_vector();
+        let n = if wo.dot(&rec.normal) < T::zero() { -rec.normal.clone() } else { rec.normal.clone() };
+        let onb = Onb::from_w(&n);
+        let wo = onb.to_local(&wo);
+        (onb, wo)
+    }
+
+    // BRDF times cosine, and the pdf of sampling wi, in the local frame
+    fn eval_local(&self, wo: &Vec3<T>, wi: &Vec3<T>) -> (Vec3<T>, T) {
+        if wo.z() <= T::zero() || wi.z() <= T::zero() {
+            return (Vec3::default(), T::zero());
+        }
+        let h = (wo + wi).unit_vector();
+        let four = T::from_f64(4.).unwrap();
+        let d = self.ggx.d(&h);
+        let f = microfacet::fresnel_conductor(wo.dot(&h), &self.eta, &self.k);
+        (f * (d * self.ggx.g(wo, wi) / (four * wo.z())), d * self.ggx.g1(wo) / (four * wo.z()))
+    }
+}
+
+impl<T: ElemT> Material<T> for Conductor<T> {
+    fn scatter(&self, r_in: &Ray<T>, rec: &HitRecord<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, Ray<T>)> {
+        let (onb, wo) = self.frame(r_in, rec);
+        if self.ggx.is_smooth() {
+            let wi = Vec3::new(-wo.x(), -wo.y(), wo.z());
+            let f = microfacet::fresnel_conductor(wo.z(), &self.eta, &self.k);
+            return Some((f, Ray::with_time(rec.p.clone(), onb.local(wi.x(), wi.y(), wi.z()), r_in.time())));
+        }
+        let h = self.ggx.sample_visible(&wo, sampler);
+        let wi = microfacet::reflect(&wo, &h);
+        let (value, pdf) = self.eval_local(&wo, &wi);
+        if pdf <= T::zero() {
+            return None;
+        }
+        Some((value / pdf, Ray::with_time(rec.p.clone(), onb.local(wi.x(), wi.y(), wi.z()), r_in.time())))
+    }
+
+    fn eval(&self, r_in: &Ray<T>, rec: &HitRecord<T>, direction: &Vec3<T>) -> Option<(Vec3<T>, T)> {
+        if self.ggx.is_smooth() {
+            return None;
+        }
+        let (onb, wo) = self.frame(r_in, rec);
+        Some(self.eval_local(&wo, &onb.to_local(&direction.unit_vector())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::{preset, Conductor};
+    use vec3::Vec3;
+    use ray::Ray;
+    use hitable::HitRecord;
+    use material::Material;
+    use rng::Sampler;
+
+    fn record() -> HitRecord<'static, f64> {
+        HitRecord { p: Vec3::new(0., 0., 0.), normal: Vec3::new(0., 0., 1.), ..HitRecord::default() }
+    }
+
+    #[test]
+    fn test_energy() {
+        let mut sampler = Sampler::new(4);
+        let (eta, k) = preset("gold").unwrap();
+        let r = Ray::new(Vec3::new(-1., 0., 1.), Vec3::new(1., 0., -1.));
+        let rec = record();
+        for &roughness in &[0.3, 0.7] {
+            let gold = Conductor::new(eta.clone(), k.clone(), roughness);
+            // the average sampled weight is the reflectance, which is what
+            // integrating eval over the hemisphere gives too
+            let n = 100000;
+            let mut sampled = Vec3::default();
+            for _ in 0..n {
+                if let Some((w, scattered)) = gold.scatter(&r, &rec, &mut sampler) {
+                    assert!(scattered.direction().z() > 0.);
+                    sampled += w;
+                }
+            }
+            sampled /= n as f64;
+            let mut integral = Vec3::default();
+            let mut pdf = 0.;
+            let m = 300;
+            for i in 0..m {
+                let cos = (i as f64 + 0.5) / m as f64;
+                let sin = (1. - cos*cos).sqrt();
+                for j in 0..m {
+                    let phi = 2.*PI * (j as f64 + 0.5) / m as f64;
+                    let (f, p) = gold.eval(&r, &rec, &Vec3::new(sin*phi.cos(), sin*phi.sin(), cos)).unwrap();
+                    integral += f;
+                    pdf += p;
+                }
+            }
+            let dw = 2.*PI / (m*m) as f64;
+            for c in 0..3 {
+                assert_approx_eq!(integral[c] * dw, sampled[c], 0.01);
+                assert!(sampled[c] < 1.);
+            }
+            // no more than all of it lands above the surface
+            assert!(pdf * dw <= 1.01);
+        }
+    }
+
+    #[test]
+    fn test_smooth() {
+        let (eta, k) = preset("silver").unwrap();
+        let mirror = Conductor::new(eta, k, 0.);
+        let r = Ray::new(Vec3::new(-1., 0., 1.), Vec3::new(1., 0., -1.));
+        let (w, scattered) = mirror.scatter(&r, &record(), &mut Sampler::new(0)).unwrap();
+        assert_approx_eq!(1.0, scattered.direction().unit_vector().dot(&Vec3::new(1., 0., 1.).unit_vector()));
+        assert!(w.x() > 0.9);
+        assert!(mirror.eval(&r, &record(), &Vec3::new(0., 0., 1.)).is_none());
+    }
+}
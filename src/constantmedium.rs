@@ -0,0 +1,146 @@
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+use rng;
+use hitable::{HitRecord, Hitable};
+use material::Material;
+use aabb::Aabb;
+
+// Smoke or fog of even density filling a closed `boundary`. A ray passing
+// through hits it at a random distance inside, drawn so that the chance of
+// getting through falls off as exp(-density * length), and scatters there
+// by the phase material (Isotropic or HenyeyGreenstein). The boundary can
+// be concave, and the ray can start inside it. The distance comes from the
+// thread's generator in rng, which the renderer reseeds every sample; call
+// rng::seed before hitting it anywhere else to get the same hits twice.
+pub struct ConstantMedium<T: ElemT> {
+    boundary: Box<dyn Hitable<T>>,
+    density: T,
+    phase: Box<dyn Material<T>>
+}
+
+impl<T: ElemT> ConstantMedium<T> {
+    pub fn new(boundary: Box<dyn Hitable<T>>, density: T, phase: Box<dyn Material<T>>) -> ConstantMedium<T> {
+        ConstantMedium { boundary, density, phase }
+    }
+
+    // The stretches of (t_min, t_max) the ray spends inside the boundary.
+    // Every crossing from behind the ray's origin is counted, so how deep in
+    // it is never has to be guessed.
+    fn inside(&self, r: &Ray<T>, t_min: T, t_max: T) -> Vec<(T, T)> {
+        let mut stretches = Vec::new();
+        let mut depth = 0;
+        let mut start = T::zero();
+        for rec in self.boundary.hit_all(r, -T::max_value(), T::max_value()) {
+            if r.direction().dot(&rec.normal) < T::zero() {
+                if depth == 0 {
+                    start = rec.t;
+                }
+                depth += 1;
+            } else if depth > 0 {
+                depth -= 1;
+                if depth == 0 {
+                    let (a, b) = (start.max(t_min), rec.t.min(t_max));
+                    if a < b {
+                        stretches.push((a, b));
+                    }
+                }
+            }
+        }
+        stretches
+    }
+}
+
+impl<T: ElemT> Hitable<T> for ConstantMedium<T> {
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        let speed = r.direction().length();
+        // how far the ray gets before scattering, if nothing stops it
+        let mut distance = -T::from_f64((1. - rng::next_f64()).ln()).unwrap() / self.density;
+        for (a, b) in self.inside(r, t_min, t_max) {
+            let length = (b - a) * speed;
+            if distance < length {
+                let t = a + distance / speed;
+                return Some(HitRecord {
+                    t,
+                    u: T::zero(),
+                    v: T::zero(),
+                    p: r.point_at_parameter(t),
+                    // arbitrary, phase functions don't use it
+                    normal: Vec3::new(T::one(), T::zero(), T::zero()),
+                    mat_opt: Some(&*self.phase)
+                });
+            }
+            distance -= length;
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        self.boundary.bounding_box()
+    }
+
+    fn hit_surface(&self, _r: &Ray<T>, _t_min: T, _t_max: T) -> Option<HitRecord<'_, T>> {
+        None
+    }
+
+    fn transmittance(&self, r: &Ray<T>, t_min: T, t_max: T) -> T {
+        let speed = r.direction().length();
+        let length = self.inside(r, t_min, t_max).iter().fold(T::zero(), |l, &(a, b)| l + (b - a) * speed);
+        (-self.density * length).exp()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::ConstantMedium;
+    use vec3::Vec3;
+    use ray::Ray;
+    use hitable::Hitable;
+    use sphere::Sphere;
+    use aabox::AaBox;
+    use csg::{Csg, CsgOp};
+    use lambertian::Lambertian;
+    use phase::Isotropic;
+    use texture::ConstantTexture;
+    use rng;
+
+    fn grey() -> Box<Lambertian<f64>> {
+        Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn fog() -> Box<Isotropic<f64>> {
+        Box::new(Isotropic::with_texture(Arc::new(ConstantTexture::new(Vec3::new(1., 1., 1.)))))
+    }
+
+    // The fraction of rays that get through
+    fn transmitted(medium: &ConstantMedium<f64>, r: &Ray<f64>) -> f64 {
+        let n = 100000;
+        (0..n).filter(|_| medium.hit(r, 0.001, 100.).is_none()).count() as f64 / n as f64
+    }
+
+    #[test]
+    fn test_transmittance() {
+        rng::seed(11);
+        let slab = ConstantMedium::new(Box::new(AaBox::new(&Vec3::new(-1., -1., -1.), &Vec3::new(1., 1., 1.), grey())), 0.5, fog());
+        // the length inside is 2 whatever the speed of the ray
+        let r = Ray::new(Vec3::new(0., 0., 5.), Vec3::new(0., 0., -3.));
+        assert_approx_eq!((-1f64).exp(), transmitted(&slab, &r), 0.01);
+        // scattering only happens inside
+        for _ in 0..1000 {
+            if let Some(rec) = slab.hit(&r, 0.001, 100.) {
+                assert!(rec.p.z().abs() <= 1. + 1e-9);
+            }
+        }
+        // starting in the middle there's only half of it left
+        let r = Ray::new(Vec3::new(0., 0., 0.), Vec3::new(0., 0., -1.));
+        assert_approx_eq!((-0.5f64).exp(), transmitted(&slab, &r), 0.01);
+
+        // a hollow shell, which the ray crosses twice
+        let shell = Csg::new(CsgOp::Difference, Box::new(Sphere::new(Vec3::new(0., 0., 0.), 2., grey())),
+                             Box::new(Sphere::new(Vec3::new(0., 0., 0.), 1., grey())));
+        let shell = ConstantMedium::new(Box::new(shell), 1., fog());
+        let r = Ray::new(Vec3::new(0., 0., 5.), Vec3::new(0., 0., -1.));
+        assert_approx_eq!((-2f64).exp(), transmitted(&shell, &r), 0.01);
+    }
+}
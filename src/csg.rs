@@ -0,0 +1,220 @@
+use vec3::ElemT;
+use ray::Ray;
+use hitable::{HitRecord, Hitable};
+use material::Material;
+use aabb::{Aabb, surrounding_box};
+
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    Difference
+}
+
+impl CsgOp {
+    pub fn from_name(name: &str) -> Option<CsgOp> {
+        match name {
+            "union" => Some(CsgOp::Union),
+            "intersection" => Some(CsgOp::Intersection),
+            "difference" => Some(CsgOp::Difference),
+            _ => None
+        }
+    }
+
+    fn inside(self, a: bool, b: bool) -> bool {
+        match self {
+            CsgOp::Union => a || b,
+            CsgOp::Intersection => a && b,
+            CsgOp::Difference => a && !b
+        }
+    }
+}
+
+// A boolean combination of two solids. Both must be closed with outward
+// normals: a crossing where the ray runs against the normal enters the solid.
+// Following every crossing of both along the ray gives the stretches inside
+// the result, and its surface is wherever that changes. Normals are turned to
+// face out of the result, so a dielectric can tell entering from leaving at
+// the new boundaries too. Faces cut by the right-hand solid of a difference
+// take the material of the left-hand one, which is what's left there.
+pub struct Csg<T: ElemT> {
+    op: CsgOp,
+    left: Box<dyn Hitable<T>>,
+    right: Box<dyn Hitable<T>>
+}
+
+impl<T: ElemT> Csg<T> {
+    pub fn new(op: CsgOp, left: Box<dyn Hitable<T>>, right: Box<dyn Hitable<T>>) -> Csg<T> {
+        Csg { op, left, right }
+    }
+}
+
+fn entering<T: ElemT>(r: &Ray<T>, rec: &HitRecord<T>) -> bool {
+    r.direction().dot(&rec.normal) < T::zero()
+}
+
+// How many times over the ray starts inside, judged from the crossings ahead
+// of it: enough that leaving never takes it below zero. This also copes with
+// overlapping pieces, e.g. a list of spheres.
+fn depth_at_start<T: ElemT>(r: &Ray<T>, hits: &[HitRecord<T>]) -> i32 {
+    let mut depth = 0;
+    let mut lowest = 0;
+    for rec in hits {
+        depth += if entering(r, rec) { 1 } else { -1 };
+        lowest = lowest.min(depth);
+    }
+    -lowest
+}
+
+impl<T: ElemT> Hitable<T> for Csg<T> {
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        self.hit_all(r, t_min, t_max).into_iter().next()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        match self.op {
+            CsgOp::Union => Some(surrounding_box(&self.left.bounding_box()?, &self.right.bounding_box()?)),
+            // the result can't reach outside the left solid
+            CsgOp::Intersection | CsgOp::Difference => self.left.bounding_box()
+        }
+    }
+
+    fn hit_all(&self, r: &Ray<T>, t_min: T, t_max: T) -> Vec<HitRecord<'_, T>> {
+        // Whether the ray is inside a solid at t_min depends on crossings
+        // beyond t_max as well, so both are followed all the way
+        let left = self.left.hit_all(r, t_min, T::max_value());
+        let right = self.right.hit_all(r, t_min, T::max_value());
+        let mut depth = [depth_at_start(r, &left), depth_at_start(r, &right)];
+        let mut inside = self.op.inside(depth[0] > 0, depth[1] > 0);
+
+        // the material of the left solid around the ray, for cut faces
+        let mut left_material: Option<&dyn Material<T>> = left.first().and_then(|rec| rec.mat_opt);
+
+        let mut events: Vec<(usize, HitRecord<T>)> = left.into_iter().map(|rec| (0, rec))
+            .chain(right.into_iter().map(|rec| (1, rec)))
+            .collect();
+        events.sort_by(|a, b| a.1.t.partial_cmp(&b.1.t).unwrap());
+
+        let mut hits = Vec::new();
+        for (side, mut rec) in events {
+            if rec.t >= t_max {
+                break;
+            }
+            let enters = entering(r, &rec);
+            depth[side] += if enters { 1 } else { -1 };
+            if side == 0 {
+                left_material = rec.mat_opt;
+            }
+            let now_inside = self.op.inside(depth[0] > 0, depth[1] > 0);
+            if now_inside == inside {
+                continue;
+            }
+            inside = now_inside;
+            // out of the result when leaving it, against the ray when entering
+            if enters != now_inside {
+                rec.normal = -rec.normal;
+            }
+            if self.op == CsgOp::Difference && side == 1 {
+                rec.mat_opt = left_material;
+            }
+            hits.push(rec);
+        }
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Csg, CsgOp};
+    use vec3::Vec3;
+    use rng::Sampler;
+    use ray::Ray;
+    use hitable::Hitable;
+    use sphere::Sphere;
+    use aabox::AaBox;
+    use lambertian::Lambertian;
+    use dielectric::Dielectric;
+
+    fn grey() -> Box<Lambertian<f64>> {
+        Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn sphere(x: f64, radius: f64) -> Box<dyn Hitable<f64>> {
+        Box::new(Sphere::new(Vec3::new(x, 0., 0.), radius, grey()))
+    }
+
+    fn along_x() -> Ray<f64> {
+        Ray::new(Vec3::new(-10., 0., 0.), Vec3::new(1., 0., 0.))
+    }
+
+    // (t, normal x) of every boundary along the x axis
+    fn boundaries(csg: &Csg<f64>) -> Vec<(f64, f64)> {
+        csg.hit_all(&along_x(), 0., 100.).iter().map(|rec| (rec.t, rec.normal.x())).collect()
+    }
+
+    fn assert_boundaries(expected: &[(f64, f64)], csg: &Csg<f64>) {
+        let got = boundaries(csg);
+        assert_eq!(expected.len(), got.len(), "{:?}", got);
+        for (a, b) in expected.iter().zip(&got) {
+            assert_approx_eq!(a.0, b.0);
+            assert_approx_eq!(a.1, b.1);
+        }
+    }
+
+    #[test]
+    fn test_operations() {
+        // spheres over x in [-2, 0] and [-1, 1]
+        let csg = |op| Csg::new(op, sphere(-1., 1.), sphere(0., 1.));
+        assert_boundaries(&[(8., -1.), (11., 1.)], &csg(CsgOp::Union));
+        assert_boundaries(&[(9., -1.), (10., 1.)], &csg(CsgOp::Intersection));
+        // the cut face points back into the hole, out of what's left
+        assert_boundaries(&[(8., -1.), (9., 1.)], &csg(CsgOp::Difference));
+        assert_approx_eq!(9.0, csg(CsgOp::Intersection).hit(&along_x(), 0., 100.).unwrap().t);
+        assert!(csg(CsgOp::Intersection).hit(&along_x(), 0., 8.5).is_none());
+
+        // from inside the result only the way out is left
+        let r = Ray::new(Vec3::new(-1.5, 0., 0.), Vec3::new(1., 0., 0.));
+        let difference = csg(CsgOp::Difference);
+        let rec = difference.hit(&r, 0.001, 100.).unwrap();
+        assert_approx_eq!(0.5, rec.t);
+        assert_approx_eq!(1.0, rec.normal.x());
+    }
+
+    #[test]
+    fn test_nested() {
+        // a box with a spherical bite out of each end, then a sphere put back
+        // in the middle
+        let cube = Box::new(AaBox::new(&Vec3::new(-2., -1., -1.), &Vec3::new(2., 1., 1.), grey()));
+        let bitten = Csg::new(CsgOp::Difference, cube, sphere(-2., 1.));
+        let bitten = Csg::new(CsgOp::Difference, Box::new(bitten), sphere(2., 1.));
+        assert_boundaries(&[(9., -1.), (11., 1.)], &bitten);
+        let filled = Csg::new(CsgOp::Union, Box::new(bitten), sphere(0., 1.5));
+        assert_boundaries(&[(8.5, -1.), (11.5, 1.)], &filled);
+    }
+
+    #[test]
+    fn test_lens() {
+        // a biconvex lens: the overlap of two glass spheres
+        let glass = |x: f64| -> Box<dyn Hitable<f64>> { Box::new(Sphere::new(Vec3::new(x, 0., 0.), 2., Box::new(Dielectric::new(1.5)))) };
+        let lens = Csg::new(CsgOp::Intersection, glass(1.5), glass(-1.5));
+        let r = Ray::new(Vec3::new(-5., 0.3, 0.), Vec3::new(1., 0., 0.));
+        let rec = lens.hit(&r, 0.001, 100.).unwrap();
+        assert!(rec.normal.dot(&r.direction()) < 0.);
+
+        // the refracted ray is inside and finds the far face facing away
+        // from it, so it leaves the glass there
+        let mut sampler = Sampler::new(0);
+        let (_, inner) = (0..20).filter_map(|_| rec.mat_opt.unwrap().scatter(&r, &rec, &mut sampler))
+            .find(|(_, s)| s.direction().x() > 0. && s.direction().y() < 0.).unwrap();
+        let exit = lens.hit(&inner, 0.001, 100.).unwrap();
+        assert!(exit.p.x() > 0.);
+        assert!(exit.normal.dot(&inner.direction()) > 0.);
+        let out = (0..20).filter_map(|_| exit.mat_opt.unwrap().scatter(&inner, &exit, &mut sampler))
+            .find(|(_, s)| s.direction().x() > 0.9).map(|(_, s)| s.direction().unit_vector());
+        // focused back towards the axis
+        assert!(out.unwrap().y() < inner.direction().unit_vector().y());
+    }
+}
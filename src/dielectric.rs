@@ -1,15 +1,13 @@
-extern crate rand;
-
 use vec3::{ElemT, Vec3};
 use ray::Ray;
+use rng::Sampler;
 use hitable::HitRecord;
 use material::Material;
 use metal::reflect;
-
-use rand::Rng;
+use microfacet::fresnel_dielectric;
 
 fn refract<T: ElemT>(v: &Vec3<T>, n: &Vec3<T>, ni_over_nt: T) -> Option<Vec3<T>> {
-    let ref uv = v.unit_vector();
+    let uv = &v.unit_vector();
     let dt = uv.dot(n);
     let descriminant = T::one() - ni_over_nt.powi(2)*(T::one()-dt.powi(2));
     if descriminant > T::zero() {
@@ -19,47 +17,233 @@ fn refract<T: ElemT>(v: &Vec3<T>, n: &Vec3<T>, ni_over_nt: T) -> Option<Vec3<T>>
     }
 }
 
-fn schlick<T: ElemT>(cosine: T, ref_idx: T) -> T {
-    let r0 = ((T::one()-ref_idx)/(T::one()+ref_idx)).powi(5);
-    r0+(T::one()-r0)*(T::one()-cosine).powi(5)
+// Wavelengths in micrometres that the red, green and blue channels stand
+// for when the index of refraction depends on wavelength
+pub const WAVELENGTHS: [f64; 3] = [0.63, 0.54, 0.46];
+
+// An index of refraction, the same at every wavelength or following one of
+// the usual fits to measurements, with the wavelength in micrometres
+#[derive(Clone)]
+#[derive(Debug)]
+pub enum Ior<T: ElemT> {
+    Constant(T),
+    // a + b / wavelength^2
+    Cauchy(T, T),
+    // n^2 = 1 + sum of b[i] wavelength^2 / (wavelength^2 - c[i])
+    Sellmeier([T; 3], [T; 3])
 }
 
+impl<T: ElemT> Ior<T> {
+    // Sellmeier coefficients of some well known glasses
+    pub fn from_name(name: &str) -> Option<Ior<T>> {
+        let (b, c) = match name {
+            // borosilicate crown, the common optical glass
+            "bk7" => ([1.03961212, 0.231792344, 1.01046945], [0.00600069867, 0.0200179144, 103.560653]),
+            // dense flint, strongly dispersive
+            "sf11" => ([1.73759695, 0.313747346, 1.89878101], [0.013188707, 0.0623068142, 155.23629]),
+            "diamond" => ([0.3306, 4.3356, 0.], [0.030625, 0.011236, 0.]),
+            _ => return None
+        };
+        let t = |a: [f64; 3]| [T::from_f64(a[0]).unwrap(), T::from_f64(a[1]).unwrap(), T::from_f64(a[2]).unwrap()];
+        Some(Ior::Sellmeier(t(b), t(c)))
+    }
+
+    pub fn at(&self, wavelength: T) -> T {
+        let l2 = wavelength * wavelength;
+        match *self {
+            Ior::Constant(n) => n,
+            Ior::Cauchy(a, b) => a + b / l2,
+            Ior::Sellmeier(ref b, ref c) => {
+                (0..3).fold(T::one(), |n2, i| n2 + b[i] * l2 / (l2 - c[i])).sqrt()
+            }
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(*self, Ior::Constant(_))
+    }
+}
+
+// Glass, water and the like. Light travelling inside is absorbed by the
+// Beer-Lambert law, falling off as exp(-absorption * distance) for each
+// channel; the distance is from the ray's origin, so that works for rays
+// that were refracted in through the same object. With a dispersive index a
+// path follows one colour channel, picked at random at its first dispersive
+// scatter and weighted three times over to make up for the other two, which
+// splits white light into colours. The Sampler remembers the channel so later
+// dispersive scatters on the same path bend it by the same index.
 #[derive(Clone)]
 pub struct Dielectric<T: ElemT> {
-    ref_idx: T
+    ior: Ior<T>,
+    absorption: Option<Vec3<T>>
 }
 
 impl<T: ElemT> Dielectric<T> {
     pub fn new(ri: T) -> Dielectric<T> {
+        Dielectric::with_ior(Ior::Constant(ri))
+    }
+
+    pub fn with_ior(ior: Ior<T>) -> Dielectric<T> {
         Dielectric::<T> {
-            ref_idx: ri
+            ior,
+            absorption: None
         }
     }
+
+    pub fn with_absorption(mut self, absorption: Vec3<T>) -> Dielectric<T> {
+        self.absorption = Some(absorption);
+        self
+    }
 }
 
 impl<T: ElemT> Material<T> for Dielectric<T> {
-    fn scatter(&self, r_in: &Ray<T>, rec: &HitRecord<T>) -> Option<(Vec3<T>, Ray<T>)> {
-        let attenuation = Vec3::new(T::one(), T::one(), T::one());
+    fn scatter(&self, r_in: &Ray<T>, rec: &HitRecord<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, Ray<T>)> {
+        let (ref_idx, mut attenuation) = if self.ior.is_dispersive() {
+            let (channel, weight) = match sampler.channel() {
+                Some(channel) => (channel, T::one()),
+                None => {
+                    let channel = ((sampler.next_f64() * 3.) as usize).min(2);
+                    sampler.set_channel(channel);
+                    (channel, T::from_f64(3.).unwrap())
+                }
+            };
+            let mut attenuation = Vec3::default();
+            attenuation[channel] = weight;
+            (self.ior.at(T::from_f64(WAVELENGTHS[channel]).unwrap()), attenuation)
+        } else {
+            (self.ior.at(T::one()), Vec3::new(T::one(), T::one(), T::one()))
+        };
         let reflected = reflect(&r_in.direction(), &rec.normal);
-        let (outward_normal, ni_over_nt, cosine) = if r_in.direction().dot(&rec.normal) > T::zero() {
-            (-rec.normal.clone(),
-             self.ref_idx,
-             self.ref_idx*r_in.direction().dot(&rec.normal) / r_in.direction().length())
+        let inside = r_in.direction().dot(&rec.normal) > T::zero();
+        let (outward_normal, ni_over_nt) = if inside {
+            (-rec.normal.clone(), ref_idx)
         }
         else {
-            (rec.normal.clone(),
-             T::one() / self.ref_idx,
-             -r_in.direction().dot(&rec.normal) / r_in.direction().length())
+            (rec.normal.clone(), T::one() / ref_idx)
         };
+        let cosine = -r_in.direction().dot(&outward_normal) / r_in.direction().length();
+        if let (true, Some(ref absorption)) = (inside, &self.absorption) {
+            let distance = rec.t * r_in.direction().length();
+            for c in 0..3 {
+                attenuation[c] *= (-absorption[c] * distance).exp();
+            }
+        }
 
         // TODO: this how the book wrote it...but i think it could be written better...
-        let (refracted_opt, reflect_prob) = if let Some(refracted) = refract(&r_in.direction(), &outward_normal, ni_over_nt) { (Some(refracted), schlick(cosine, self.ref_idx)) }
+        let (refracted_opt, reflect_prob) = if let Some(refracted) = refract(&r_in.direction(), &outward_normal, ni_over_nt) { (Some(refracted), fresnel_dielectric(cosine, T::one() / ni_over_nt)) }
         else { (None, T::one()) };
-        if T::from_f64(rand::thread_rng().next_f64()).unwrap() < reflect_prob {
-            Some((attenuation, Ray::new(rec.p.clone(), reflected.clone())))
+        if T::from_f64(sampler.next_f64()).unwrap() < reflect_prob {
+            Some((attenuation, Ray::with_time(rec.p.clone(), reflected.clone(), r_in.time())))
         }
         else {
-            Some((attenuation, Ray::new(rec.p.clone(), refracted_opt.unwrap().clone())))
+            Some((attenuation, Ray::with_time(rec.p.clone(), refracted_opt.unwrap().clone(), r_in.time())))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Dielectric, Ior, WAVELENGTHS};
+    use vec3::Vec3;
+    use ray::Ray;
+    use hitable::HitRecord;
+    use material::Material;
+    use microfacet::fresnel_dielectric;
+    use rng::Sampler;
+
+    fn record(t: f64) -> HitRecord<'static, f64> {
+        HitRecord { t, p: Vec3::new(0., 0., 0.), normal: Vec3::new(0., 0., 1.), ..HitRecord::default() }
+    }
+
+    // The fraction of rays from `r` that are reflected, and their average
+    // weight
+    fn scatter(glass: &Dielectric<f64>, r: &Ray<f64>, t: f64, sampler: &mut Sampler) -> (f64, Vec3<f64>) {
+        let n = 100000;
+        let mut reflected = 0;
+        let mut weight = Vec3::default();
+        for _ in 0..n {
+            let (w, scattered) = glass.scatter(r, &record(t), sampler).unwrap();
+            if scattered.direction().dot(&r.direction()) < 0. {
+                reflected += 1;
+            }
+            weight += w;
+        }
+        (reflected as f64 / n as f64, weight / n as f64)
+    }
+
+    #[test]
+    fn test_fresnel() {
+        // 4% at normal incidence for glass
+        let mut sampler = Sampler::new(31);
+        let glass = Dielectric::new(1.5);
+        let (reflected, weight) = scatter(&glass, &Ray::new(Vec3::new(0., 0., 1.), Vec3::new(0., 0., -1.)), 1., &mut sampler);
+        assert_approx_eq!(0.04, reflected, 0.003);
+        assert_approx_eq!(1.0, weight.y());
+
+        // from inside, just short of the critical angle at cos 0.745 much of
+        // the light is already reflected, and past it all of it is
+        for &(cos, expected) in &[(0.76, 0.302), (0.75, 0.500), (0.72, 1.0)] {
+            let sin = (1f64 - cos*cos).sqrt();
+            assert_approx_eq!(expected, fresnel_dielectric(cos, 1. / 1.5), 1e-3);
+            let (reflected, _) = scatter(&glass, &Ray::new(Vec3::new(-sin, 0., -cos), Vec3::new(sin, 0., cos)), 1., &mut sampler);
+            assert_approx_eq!(expected, reflected, 0.006);
+        }
+    }
+
+    #[test]
+    fn test_absorption() {
+        let mut sampler = Sampler::new(32);
+        let glass = Dielectric::new(1.5).with_absorption(Vec3::new(0.5, 0., 1.));
+        // leaving after 2 units inside, whichever way it goes next
+        let (_, weight) = scatter(&glass, &Ray::new(Vec3::new(0., 0., -2.), Vec3::new(0., 0., 1.)), 2., &mut sampler);
+        assert_approx_eq!((-1f64).exp(), weight.x());
+        assert_approx_eq!(1.0, weight.y());
+        assert_approx_eq!((-2f64).exp(), weight.z());
+        // nothing is absorbed on the way in
+        let (_, weight) = scatter(&glass, &Ray::new(Vec3::new(0., 0., 1.), Vec3::new(0., 0., -1.)), 1., &mut sampler);
+        assert_approx_eq!(1.0, weight.z());
+    }
+
+    #[test]
+    fn test_dispersion() {
+        // reference indices at the sodium D line
+        let bk7 = Ior::<f64>::from_name("bk7").unwrap();
+        assert_approx_eq!(1.5168, bk7.at(0.5876), 1e-4);
+        assert_approx_eq!(2.4175, Ior::<f64>::from_name("diamond").unwrap().at(0.5893), 1e-3);
+        assert_approx_eq!(1.7847, Ior::<f64>::from_name("sf11").unwrap().at(0.5876), 1e-3);
+        let cauchy = Ior::Cauchy(1.5046, 0.0042);
+        assert_approx_eq!(1.5046 + 0.0042 / 0.25, cauchy.at(0.5f64));
+        // blue bends more than red
+        assert!(bk7.at(WAVELENGTHS[2]) > bk7.at(WAVELENGTHS[0]));
+        assert!(!Ior::Constant(1.5).is_dispersive() && bk7.is_dispersive());
+
+        let prism = Dielectric::with_ior(Ior::from_name("sf11").unwrap());
+        let r = Ray::new(Vec3::new(-1., 0., 1.), Vec3::new(1., 0., -1.));
+        let rec = record(1.);
+        // each path carries one channel, and on average all of them
+        let (mut red, mut blue) = (0., 0.);
+        let n = 30000;
+        let mut weight = Vec3::default();
+        for k in 0..n {
+            let mut sampler = Sampler::for_sample(33, 0, k);
+            let (w, scattered) = prism.scatter(&r, &rec, &mut sampler).unwrap();
+            assert_eq!(1, (0..3).filter(|&c| w[c] > 0.).count());
+            // and keeps it through later scatters, which don't weight it again
+            let channel = sampler.channel().unwrap();
+            let (w2, _) = prism.scatter(&r, &rec, &mut sampler).unwrap();
+            assert_eq!(1.0, w2[channel]);
+            assert_eq!(0.0, w2[(channel + 1) % 3] + w2[(channel + 2) % 3]);
+            let d = scattered.direction().unit_vector();
+            if d.z() < 0. {
+                if w.x() > 0. { red = d.x(); } else if w.z() > 0. { blue = d.x(); }
+            }
+            weight += w;
+        }
+        for c in 0..3 {
+            assert_approx_eq!(1.0, weight[c] / n as f64, 0.05);
         }
+        // sin of the refracted angle is sin(45) / n
+        assert_approx_eq!(0.5f64.sqrt() / prism.ior.at(WAVELENGTHS[0]), red);
+        assert!(blue < red);
     }
 }
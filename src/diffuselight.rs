@@ -0,0 +1,36 @@
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+use rng::Sampler;
+use hitable::HitRecord;
+use material::Material;
+
+// Emits the same radiance in every direction, from both sides of the
+// surface, and doesn't scatter anything
+#[derive(Clone)]
+pub struct DiffuseLight<T: ElemT> {
+    emit: Vec3<T>
+}
+
+impl<T: ElemT> DiffuseLight<T> {
+    pub fn new(emit: Vec3<T>) -> DiffuseLight<T> {
+        DiffuseLight::<T> {
+            emit
+        }
+    }
+}
+
+impl<T: ElemT> Material<T> for DiffuseLight<T> {
+    #[allow(unused_variables)]
+    fn scatter(&self, r_in: &Ray<T>, rec: &HitRecord<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, Ray<T>)> {
+        None
+    }
+
+    #[allow(unused_variables)]
+    fn emitted(&self, r_in: &Ray<T>, rec: &HitRecord<T>) -> Vec3<T> {
+        self.emit.clone()
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
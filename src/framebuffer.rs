@@ -0,0 +1,58 @@
+use vec3::{ElemT, Vec3};
+
+// Accumulates radiance samples per pixel. Pixels are addressed in image
+// order: (0, 0) is the top-left corner.
+pub struct Framebuffer<T: ElemT> {
+    width: usize,
+    height: usize,
+    sums: Vec<Vec3<T>>,
+    counts: Vec<usize>
+}
+
+impl<T: ElemT> Framebuffer<T> {
+    pub fn new(width: usize, height: usize) -> Framebuffer<T> {
+        Framebuffer::<T> {
+            width,
+            height,
+            sums: vec![Vec3::default(); width*height],
+            counts: vec![0; width*height]
+        }
+    }
+
+    pub fn width(&self) -> usize { self.width }
+    pub fn height(&self) -> usize { self.height }
+
+    // Adds `n` samples whose radiance sums to `sum`
+    pub fn add_samples(&mut self, x: usize, y: usize, sum: Vec3<T>, n: usize) {
+        let idx = y*self.width + x;
+        self.sums[idx] += sum;
+        self.counts[idx] += n;
+    }
+
+    // The mean of the samples taken at (x, y), black if there are none
+    pub fn pixel(&self, x: usize, y: usize) -> Vec3<T> {
+        let idx = y*self.width + x;
+        match self.counts[idx] {
+            0 => Vec3::default(),
+            n => &self.sums[idx] / T::from_usize(n).unwrap()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Framebuffer;
+    use vec3::Vec3;
+
+    #[test]
+    fn test_accumulate() {
+        let mut fb = Framebuffer::<f64>::new(2, 2);
+        fb.add_samples(1, 0, Vec3::new(1., 2., 3.), 2);
+        fb.add_samples(1, 0, Vec3::new(3., 2., 1.), 2);
+        let p = fb.pixel(1, 0);
+        assert_approx_eq!(1.0, p.x());
+        assert_approx_eq!(1.0, p.y());
+        assert_approx_eq!(1.0, p.z());
+        assert_approx_eq!(0.0, fb.pixel(0, 1).x());
+    }
+}
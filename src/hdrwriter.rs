@@ -0,0 +1,344 @@
+use std::io;
+use std::io::Write;
+
+use vec3::ElemT;
+use framebuffer::Framebuffer;
+use imagewriter::{ImageWriter, ToneMap};
+
+// Linear (or tone mapped, if asked for) RGB of one pixel as f32
+fn rgb_f32<T: ElemT>(fb: &Framebuffer<T>, x: usize, y: usize, tone_map: ToneMap) -> [f32; 3] {
+    let col = tone_map.apply(fb.pixel(x, y));
+    [col.r().to_f32().unwrap(), col.g().to_f32().unwrap(), col.b().to_f32().unwrap()]
+}
+
+/////////////
+// OpenEXR //
+/////////////
+
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum ExrPixelType {
+    Half,
+    Float
+}
+
+// IEEE 754 binary32 -> binary16, rounding to nearest even
+pub fn f32_to_half(f: f32) -> u16 {
+    let x = f.to_bits();
+    let sign = ((x >> 16) & 0x8000) as u16;
+    let exp = ((x >> 23) & 0xff) as i32;
+    let mant = x & 0x7f_ffff;
+
+    if exp == 0xff { // inf or nan
+        return sign | 0x7c00 | if mant != 0 { 0x200 } else { 0 };
+    }
+    let e = exp - 127 + 15;
+    if e >= 0x1f { // too big, becomes inf
+        return sign | 0x7c00;
+    }
+    if e <= 0 { // subnormal half, or underflow to zero
+        if e < -10 {
+            return sign;
+        }
+        let m = mant | 0x80_0000;
+        let shift = (14 - e) as u32;
+        let mut h = m >> shift;
+        let rem = m & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        if rem > halfway || (rem == halfway && (h & 1) == 1) {
+            h += 1;
+        }
+        return sign | h as u16;
+    }
+
+    let mut h = ((e as u32) << 10) | (mant >> 13);
+    let rem = mant & 0x1fff;
+    // a carry out of the mantissa correctly bumps the exponent
+    if rem > 0x1000 || (rem == 0x1000 && (h & 1) == 1) {
+        h += 1;
+    }
+    sign | h as u16
+}
+
+fn write_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+// Uncompressed single-part scanline OpenEXR
+pub struct ExrWriter {
+    pub pixel_type: ExrPixelType,
+    pub alpha: bool,
+    pub tone_map: ToneMap
+}
+
+impl ExrWriter {
+    // Channels have to be listed (and stored) in alphabetical order
+    fn channels(&self) -> &'static [&'static str] {
+        if self.alpha { &["A", "B", "G", "R"] } else { &["B", "G", "R"] }
+    }
+
+    fn header(&self, width: usize, height: usize) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01]); // magic
+        header.extend_from_slice(&[2, 0, 0, 0]); // version 2, scanline image
+
+        let pixel_type: i32 = match self.pixel_type {
+            ExrPixelType::Half => 1,
+            ExrPixelType::Float => 2
+        };
+        let mut chlist = Vec::new();
+        for name in self.channels() {
+            chlist.extend_from_slice(name.as_bytes());
+            chlist.push(0);
+            chlist.extend_from_slice(&pixel_type.to_le_bytes());
+            chlist.extend_from_slice(&[0, 0, 0, 0]); // pLinear + reserved
+            chlist.extend_from_slice(&1i32.to_le_bytes()); // x sampling
+            chlist.extend_from_slice(&1i32.to_le_bytes()); // y sampling
+        }
+        chlist.push(0);
+        write_attribute(&mut header, "channels", "chlist", &chlist);
+        write_attribute(&mut header, "compression", "compression", &[0]);
+
+        let mut window = Vec::new();
+        for v in &[0i32, 0, width as i32 - 1, height as i32 - 1] {
+            window.extend_from_slice(&v.to_le_bytes());
+        }
+        write_attribute(&mut header, "dataWindow", "box2i", &window);
+        write_attribute(&mut header, "displayWindow", "box2i", &window);
+        write_attribute(&mut header, "lineOrder", "lineOrder", &[0]); // increasing y
+        write_attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+        write_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+        write_attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+        header.push(0);
+        header
+    }
+
+    fn bytes_per_sample(&self) -> usize {
+        match self.pixel_type {
+            ExrPixelType::Half => 2,
+            ExrPixelType::Float => 4
+        }
+    }
+}
+
+impl<T: ElemT> ImageWriter<T> for ExrWriter {
+    fn write(&self, fb: &Framebuffer<T>, out: &mut dyn Write) -> io::Result<()> {
+        let (width, height) = (fb.width(), fb.height());
+        let header = self.header(width, height);
+        out.write_all(&header)?;
+
+        // one chunk per scanline: y, byte count, then each channel's samples
+        let line_bytes = width * self.channels().len() * self.bytes_per_sample();
+        let first_chunk = header.len() + height*8;
+        for y in 0..height {
+            let offset = (first_chunk + y*(8 + line_bytes)) as u64;
+            out.write_all(&offset.to_le_bytes())?;
+        }
+
+        let mut line = Vec::with_capacity(line_bytes);
+        for y in 0..height {
+            let pixels: Vec<[f32; 3]> = (0..width).map(|x| rgb_f32(fb, x, y, self.tone_map)).collect();
+            line.clear();
+            for name in self.channels() {
+                for p in &pixels {
+                    let v = match *name {
+                        "R" => p[0],
+                        "G" => p[1],
+                        "B" => p[2],
+                        _ => 1. // alpha, every pixel is fully covered
+                    };
+                    match self.pixel_type {
+                        ExrPixelType::Half => line.extend_from_slice(&f32_to_half(v).to_le_bytes()),
+                        ExrPixelType::Float => line.extend_from_slice(&v.to_le_bytes())
+                    }
+                }
+            }
+            out.write_all(&(y as i32).to_le_bytes())?;
+            out.write_all(&(line_bytes as i32).to_le_bytes())?;
+            out.write_all(&line)?;
+        }
+        Ok(())
+    }
+}
+
+//////////////
+// Radiance //
+//////////////
+
+// Shared-exponent encoding used by Radiance .hdr files
+pub fn to_rgbe(rgb: [f32; 3]) -> [u8; 4] {
+    let [r, g, b] = [rgb[0].max(0.), rgb[1].max(0.), rgb[2].max(0.)];
+    let v = r.max(g).max(b);
+    if v < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    // v = m * 2^e with m in [0.5, 1)
+    let e = (v.log2().floor() as i32 + 1).min(127);
+    let scale = 2f32.powi(8 - e);
+    let quantize = |c: f32| (c*scale).min(255.) as u8;
+    [quantize(r), quantize(g), quantize(b), (e + 128) as u8]
+}
+
+fn run_length(data: &[u8], start: usize) -> usize {
+    let mut n = 1;
+    while start + n < data.len() && n < 127 && data[start + n] == data[start] {
+        n += 1;
+    }
+    n
+}
+
+// Adaptive run-length encoding of one component of a scanline
+fn write_rle(out: &mut dyn Write, data: &[u8]) -> io::Result<()> {
+    let mut i = 0;
+    while i < data.len() {
+        let run = run_length(data, i);
+        if run >= 4 {
+            out.write_all(&[128 + run as u8, data[i]])?;
+            i += run;
+            continue;
+        }
+        let start = i;
+        while i < data.len() && i - start < 128 && run_length(data, i) < 4 {
+            i += 1;
+        }
+        out.write_all(&[(i - start) as u8])?;
+        out.write_all(&data[start..i])?;
+    }
+    Ok(())
+}
+
+pub struct RgbeWriter {
+    pub tone_map: ToneMap
+}
+
+impl<T: ElemT> ImageWriter<T> for RgbeWriter {
+    fn write(&self, fb: &Framebuffer<T>, out: &mut dyn Write) -> io::Result<()> {
+        let (width, height) = (fb.width(), fb.height());
+        write!(out, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width)?;
+
+        // run-length encoding can only describe scanlines in this range
+        let rle = (8..0x8000).contains(&width);
+        for y in 0..height {
+            let line: Vec<[u8; 4]> = (0..width).map(|x| to_rgbe(rgb_f32(fb, x, y, self.tone_map))).collect();
+            if rle {
+                out.write_all(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8])?;
+                for c in 0..4 {
+                    let component: Vec<u8> = line.iter().map(|p| p[c]).collect();
+                    write_rle(out, &component)?;
+                }
+            } else {
+                for p in &line {
+                    out.write_all(p)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/////////
+// PFM //
+/////////
+
+pub struct PfmWriter {
+    pub tone_map: ToneMap
+}
+
+impl<T: ElemT> ImageWriter<T> for PfmWriter {
+    fn write(&self, fb: &Framebuffer<T>, out: &mut dyn Write) -> io::Result<()> {
+        // a negative scale means little-endian
+        write!(out, "PF\n{} {}\n-1.0\n", fb.width(), fb.height())?;
+        // rows are stored bottom to top
+        for y in (0..fb.height()).rev() {
+            for x in 0..fb.width() {
+                for c in &rgb_f32(fb, x, y, self.tone_map) {
+                    out.write_all(&c.to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{f32_to_half, to_rgbe, write_rle, ExrPixelType, ExrWriter, PfmWriter, RgbeWriter};
+    use vec3::Vec3;
+    use framebuffer::Framebuffer;
+    use imagewriter::{ImageWriter, ToneMap};
+
+    fn test_image(width: usize, height: usize) -> Framebuffer<f64> {
+        let mut fb = Framebuffer::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                fb.add_samples(x, y, Vec3::new(x as f64, y as f64, 100.), 1);
+            }
+        }
+        fb
+    }
+
+    #[test]
+    fn test_half() {
+        assert_eq!(0x3c00, f32_to_half(1.));
+        assert_eq!(0x3800, f32_to_half(0.5));
+        assert_eq!(0xc000, f32_to_half(-2.));
+        assert_eq!(0x7bff, f32_to_half(65504.));
+        assert_eq!(0x7c00, f32_to_half(1e6));
+        assert_eq!(0x0001, f32_to_half(2f32.powi(-24)));
+        assert_eq!(0x0000, f32_to_half(2f32.powi(-26)));
+        assert_eq!(0x3c00, f32_to_half(1. + 2f32.powi(-11))); // ties to even
+        assert_eq!(0x3c02, f32_to_half(1. + 3.*2f32.powi(-11)));
+    }
+
+    #[test]
+    fn test_exr_layout() {
+        for &(pixel_type, alpha, bytes, channels) in &[(ExrPixelType::Half, false, 2, 3),
+                                                       (ExrPixelType::Float, true, 4, 4)] {
+            let writer = ExrWriter { pixel_type, alpha, tone_map: ToneMap::None };
+            let header_len = writer.header(5, 3).len();
+            let mut out = Vec::new();
+            writer.write(&test_image(5, 3), &mut out).unwrap();
+
+            assert_eq!(&[0x76, 0x2f, 0x31, 0x01], &out[..4]);
+            assert_eq!(header_len + 3*8 + 3*(8 + 5*channels*bytes), out.len());
+            // the offset table points at the first chunk, which starts with y = 0
+            let first = u64::from_le_bytes([out[header_len], out[header_len+1], out[header_len+2], out[header_len+3],
+                                            out[header_len+4], out[header_len+5], out[header_len+6], out[header_len+7]]);
+            assert_eq!((header_len + 3*8) as u64, first);
+            assert_eq!(&[0, 0, 0, 0], &out[first as usize..first as usize + 4]);
+        }
+    }
+
+    #[test]
+    fn test_rgbe() {
+        assert_eq!([128, 128, 128, 129], to_rgbe([1., 1., 1.]));
+        assert_eq!([128, 64, 0, 130], to_rgbe([2., 1., 0.]));
+        assert_eq!([0, 0, 0, 0], to_rgbe([0., 0., 0.]));
+
+        let mut out = Vec::new();
+        write_rle(&mut out, &[7, 7, 7, 7, 7, 1, 2, 3]).unwrap();
+        assert_eq!(vec![133, 7, 3, 1, 2, 3], out);
+
+        let mut out = Vec::new();
+        RgbeWriter { tone_map: ToneMap::None }.write(&test_image(16, 2), &mut out).unwrap();
+        assert!(out.starts_with(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 16\n\x02\x02\x00\x10"));
+    }
+
+    #[test]
+    fn test_pfm() {
+        let mut out = Vec::new();
+        PfmWriter { tone_map: ToneMap::None }.write(&test_image(4, 2), &mut out).unwrap();
+        let header = b"PF\n4 2\n-1.0\n";
+        assert_eq!(header.len() + 4*2*3*4, out.len());
+        // first stored row is the bottom one (y = 1)
+        let g = f32::from_le_bytes([out[header.len()+4], out[header.len()+5], out[header.len()+6], out[header.len()+7]]);
+        assert_approx_eq!(1.0, g);
+    }
+}
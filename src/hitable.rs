@@ -1,17 +1,124 @@
+use std::sync::Arc;
+
 use vec3::{ElemT, Vec3};
 use ray::Ray;
+use rng::Sampler;
 use material::Material;
+use aabb::Aabb;
 
 #[derive(Clone)]
 #[derive(Default)]
 pub struct HitRecord<'a, T: ElemT + 'a> {
     pub t: T,
+    pub u: T,
+    pub v: T,
     pub p: Vec3<T>,
     pub normal: Vec3<T>,
-    pub mat_opt: Option<&'a Material<T>>
+    pub mat_opt: Option<&'a dyn Material<T>>
 }
 
-pub trait Hitable<T>
+// Send + Sync so a scene can be shared between render threads
+pub trait Hitable<T>: Send + Sync
     where T: ElemT {
-    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<T>>;
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>>;
+    // None if the object is unbounded
+    fn bounding_box(&self) -> Option<Aabb<T>>;
+
+    // Light sampling. `sample` picks a point on the surface as seen from
+    // `origin` and returns it with the pdf of that choice, measured in solid
+    // angle at `origin`. `pdf` is the same density for any direction, zero if
+    // the ray along it misses. Only emitters need these.
+    #[allow(unused_variables)]
+    fn sample(&self, origin: &Vec3<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, T)> {
+        None
+    }
+
+    #[allow(unused_variables)]
+    fn pdf(&self, origin: &Vec3<T>, direction: &Vec3<T>) -> T {
+        T::zero()
+    }
+
+    // Adds every emissive primitive in this object to `lights`
+    #[allow(unused_variables)]
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Hitable<T>>) {}
+
+    // Every place the ray crosses the surface in (t_min, t_max), nearest
+    // first, for CSG. By default they're found one at a time with hit().
+    fn hit_all(&self, r: &Ray<T>, t_min: T, t_max: T) -> Vec<HitRecord<'_, T>> {
+        let mut hits = Vec::new();
+        let mut t = t_min;
+        let eps = T::from_f64(1e-9).unwrap();
+        while let Some(rec) = self.hit(r, t, t_max) {
+            // a little past this hit so it isn't found again
+            t = rec.t + eps*(T::one() + rec.t.abs());
+            hits.push(rec);
+            if hits.len() >= MAX_HITS {
+                break;
+            }
+        }
+        hits
+    }
+
+    // For shadow rays, which need the light at the end and how much of it
+    // gets there: hit_surface is hit() passing through media, and
+    // transmittance is the fraction of light that media let through along
+    // the ray in (t_min, t_max). Objects holding others pass both on.
+    fn hit_surface(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        self.hit(r, t_min, t_max)
+    }
+
+    #[allow(unused_variables)]
+    fn transmittance(&self, r: &Ray<T>, t_min: T, t_max: T) -> T {
+        T::one()
+    }
+}
+
+// Stops hit_all going round forever on a surface it keeps finding
+const MAX_HITS: usize = 64;
+
+// Lets one object be shared, e.g. between several instances
+impl<T: ElemT> Hitable<T> for Arc<dyn Hitable<T>> {
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        (**self).hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        (**self).bounding_box()
+    }
+
+    fn sample(&self, origin: &Vec3<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, T)> {
+        (**self).sample(origin, sampler)
+    }
+
+    fn pdf(&self, origin: &Vec3<T>, direction: &Vec3<T>) -> T {
+        (**self).pdf(origin, direction)
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Hitable<T>>) {
+        (**self).collect_lights(lights)
+    }
+
+    fn hit_all(&self, r: &Ray<T>, t_min: T, t_max: T) -> Vec<HitRecord<'_, T>> {
+        (**self).hit_all(r, t_min, t_max)
+    }
+
+    fn hit_surface(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        (**self).hit_surface(r, t_min, t_max)
+    }
+
+    fn transmittance(&self, r: &Ray<T>, t_min: T, t_max: T) -> T {
+        (**self).transmittance(r, t_min, t_max)
+    }
+}
+
+// Converts a pdf with respect to surface area at `p` into one with respect to
+// solid angle at `origin`
+pub fn area_to_solid_angle<T: ElemT>(pdf_area: T, origin: &Vec3<T>, p: &Vec3<T>, normal: &Vec3<T>) -> T {
+    let d = p - origin;
+    let dist_squared = d.squared_length();
+    let cosine = (d.dot(normal) / (dist_squared.sqrt() * normal.length())).abs();
+    if cosine <= T::zero() {
+        return T::zero();
+    }
+    pdf_area * dist_squared / cosine
 }
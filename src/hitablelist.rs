@@ -1,8 +1,9 @@
 use vec3::ElemT;
 use ray::Ray;
 use hitable::{HitRecord, Hitable};
+use aabb::{Aabb, surrounding_box};
 
-type ListT<T> = Vec<Box<Hitable<T>>>;
+type ListT<T> = Vec<Box<dyn Hitable<T>>>;
 
 #[derive(Default)]
 pub struct HitableList<T>
@@ -11,7 +12,7 @@ pub struct HitableList<T>
 }
 
 impl<T: ElemT> HitableList<T> {
-    pub fn new(v: Vec<Box<Hitable<T>>>) -> HitableList<T> {
+    pub fn new(v: Vec<Box<dyn Hitable<T>>>) -> HitableList<T> {
         HitableList::<T> {
             list: v
         }
@@ -19,15 +20,49 @@ impl<T: ElemT> HitableList<T> {
 }
 
 impl<T: ElemT> Hitable<T> for HitableList<T> {
-    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<T>> {
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
         let mut ret: Option<HitRecord<T>> = None;
         let mut closest_so_far = t_max;
-        for ref h in &self.list {
-            if let Some(rec) = (*h).hit(&r, t_min, closest_so_far) {
+        for h in &self.list {
+            if let Some(rec) = h.hit(r, t_min, closest_so_far) {
                 closest_so_far = rec.t;
                 ret = Some(rec);
             }
         }
         ret
     }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        let mut ret: Option<Aabb<T>> = None;
+        for h in &self.list {
+            let bbox = h.bounding_box()?;
+            ret = Some(match ret {
+                Some(b) => surrounding_box(&b, &bbox),
+                None => bbox
+            });
+        }
+        ret
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Hitable<T>>) {
+        for h in &self.list {
+            h.collect_lights(lights);
+        }
+    }
+
+    fn hit_surface(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        let mut ret: Option<HitRecord<T>> = None;
+        let mut closest_so_far = t_max;
+        for h in &self.list {
+            if let Some(rec) = h.hit_surface(r, t_min, closest_so_far) {
+                closest_so_far = rec.t;
+                ret = Some(rec);
+            }
+        }
+        ret
+    }
+
+    fn transmittance(&self, r: &Ray<T>, t_min: T, t_max: T) -> T {
+        self.list.iter().fold(T::one(), |tr, h| tr * h.transmittance(r, t_min, t_max))
+    }
 }
@@ -0,0 +1,339 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use jpeg_decoder;
+use png;
+
+use vec3::{ElemT, Vec3};
+use texture::Texture;
+
+#[derive(Debug)]
+pub enum TextureError {
+    Io(String, io::Error),
+    Decode(String, String)
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TextureError::Io(ref file, ref err) => write!(f, "{}: {}", file, err),
+            TextureError::Decode(ref file, ref msg) => write!(f, "{}: {}", file, msg)
+        }
+    }
+}
+
+fn decode_error<R>(file: &str, msg: String) -> Result<R, TextureError> {
+    Err(TextureError::Decode(file.to_string(), msg))
+}
+
+// What lookups outside [0, 1] map to
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror
+}
+
+impl WrapMode {
+    pub fn from_name(name: &str) -> Option<WrapMode> {
+        match name.to_lowercase().as_str() {
+            "repeat" => Some(WrapMode::Repeat),
+            "clamp" => Some(WrapMode::Clamp),
+            "mirror" => Some(WrapMode::Mirror),
+            _ => None
+        }
+    }
+
+    fn apply(&self, i: i64, n: usize) -> usize {
+        let n = n as i64;
+        let i = match *self {
+            WrapMode::Repeat => i.rem_euclid(n),
+            WrapMode::Clamp => i.max(0).min(n - 1),
+            WrapMode::Mirror => {
+                let m = i.rem_euclid(2*n);
+                if m < n { m } else { 2*n - 1 - m }
+            }
+        };
+        i as usize
+    }
+}
+
+// An image in linear RGB, looked up with bilinear filtering. (u, v) = (0, 0)
+// is the bottom left corner and (1, 1) the top right.
+pub struct ImageTexture<T: ElemT> {
+    width: usize,
+    height: usize,
+    texels: Vec<Vec3<T>>, // row by row, top row first
+    wrap: WrapMode
+}
+
+impl<T: ElemT> ImageTexture<T> {
+    pub fn new(width: usize, height: usize, texels: Vec<Vec3<T>>, wrap: WrapMode) -> ImageTexture<T> {
+        assert!(width > 0 && height > 0 && texels.len() == width*height, "texture size doesn't match its texels");
+        ImageTexture { width, height, texels, wrap }
+    }
+
+    // Loads a PNG, JPEG or Radiance HDR file, going by the extension. 8-bit
+    // images are taken to be sRGB and converted to linear.
+    pub fn load(path: &Path, wrap: WrapMode) -> Result<ImageTexture<T>, TextureError> {
+        let name = path.display().to_string();
+        let file = File::open(path).map_err(|e| TextureError::Io(name.clone(), e))?;
+        let reader = BufReader::new(file);
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+        let (width, height, texels) = match ext.as_str() {
+            "png" => decode_png(reader, &name)?,
+            "jpg" | "jpeg" => decode_jpeg(reader, &name)?,
+            "hdr" => decode_rgbe(reader, &name)?,
+            _ => return decode_error(&name, "unknown image type, expected .png, .jpg or .hdr".to_string())
+        };
+        Ok(ImageTexture::new(width, height, texels, wrap))
+    }
+
+    fn texel(&self, x: i64, y: i64) -> &Vec3<T> {
+        let x = self.wrap.apply(x, self.width);
+        let y = self.wrap.apply(y, self.height);
+        &self.texels[y*self.width + x]
+    }
+}
+
+impl<T: ElemT> Texture<T> for ImageTexture<T> {
+    #[allow(unused_variables)]
+    fn value(&self, u: T, v: T, p: &Vec3<T>) -> Vec3<T> {
+        // texel centres sit at half-integer coordinates
+        let x = u.to_f64().unwrap()*(self.width as f64) - 0.5;
+        let y = (1. - v.to_f64().unwrap())*(self.height as f64) - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let fx = T::from_f64(x - x0).unwrap();
+        let fy = T::from_f64(y - y0).unwrap();
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = self.texel(x0, y0)*(T::one() - fx) + self.texel(x0 + 1, y0)*fx;
+        let bottom = self.texel(x0, y0 + 1)*(T::one() - fx) + self.texel(x0 + 1, y0 + 1)*fx;
+        top*(T::one() - fy) + bottom*fy
+    }
+}
+
+//////////////
+// Decoders //
+//////////////
+
+type Decoded<T> = (usize, usize, Vec<Vec3<T>>);
+
+fn srgb_to_linear(c: u8) -> f64 {
+    let c = f64::from(c) / 255.;
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+// 8-bit samples with `channels` per pixel; grey is spread to RGB and alpha
+// is dropped
+fn from_srgb8<T: ElemT>(data: &[u8], channels: usize) -> Vec<Vec3<T>> {
+    let t = |c: u8| T::from_f64(srgb_to_linear(c)).unwrap();
+    data.chunks(channels).map(|px| match channels {
+        1 | 2 => Vec3::new(t(px[0]), t(px[0]), t(px[0])),
+        _ => Vec3::new(t(px[0]), t(px[1]), t(px[2]))
+    }).collect()
+}
+
+fn decode_png<T: ElemT, R: Read>(reader: R, file: &str) -> Result<Decoded<T>, TextureError> {
+    let mut decoder = png::Decoder::new(reader);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| TextureError::Decode(file.to_string(), e.to_string()))?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).map_err(|e| TextureError::Decode(file.to_string(), e.to_string()))?;
+    let channels = info.color_type.samples();
+    let texels = from_srgb8(&data[..info.buffer_size()], channels);
+    Ok((info.width as usize, info.height as usize, texels))
+}
+
+fn decode_jpeg<T: ElemT, R: Read>(reader: R, file: &str) -> Result<Decoded<T>, TextureError> {
+    let mut decoder = jpeg_decoder::Decoder::new(reader);
+    let data = decoder.decode().map_err(|e| TextureError::Decode(file.to_string(), e.to_string()))?;
+    let info = decoder.info().unwrap();
+    let channels = match info.pixel_format {
+        jpeg_decoder::PixelFormat::L8 => 1,
+        jpeg_decoder::PixelFormat::RGB24 => 3,
+        f => return decode_error(file, format!("unsupported JPEG pixel format {:?}", f))
+    };
+    Ok((info.width as usize, info.height as usize, from_srgb8(&data, channels)))
+}
+
+fn from_rgbe(rgbe: &[u8]) -> [f64; 3] {
+    if rgbe[3] == 0 {
+        return [0., 0., 0.];
+    }
+    // inverse of hdrwriter::to_rgbe, sampling the middle of each bucket
+    let scale = 2f64.powi(i32::from(rgbe[3]) - 136);
+    [(f64::from(rgbe[0]) + 0.5)*scale, (f64::from(rgbe[1]) + 0.5)*scale, (f64::from(rgbe[2]) + 0.5)*scale]
+}
+
+// Radiance .hdr with the usual -Y h +X w orientation, flat or new-style
+// run-length encoded scanlines
+fn decode_rgbe<T: ElemT, R: BufRead>(mut reader: R, file: &str) -> Result<Decoded<T>, TextureError> {
+    let io_error = |e| TextureError::Io(file.to_string(), e);
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(io_error)?;
+    if !line.starts_with("#?") {
+        return decode_error(file, "not a Radiance HDR file".to_string());
+    }
+    // header lines up to a blank one, then the resolution
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).map_err(io_error)? == 0 {
+            return decode_error(file, "unexpected end of header".to_string());
+        }
+        let l = line.trim();
+        if l.starts_with("FORMAT=") && l != "FORMAT=32-bit_rle_rgbe" {
+            return decode_error(file, format!("unsupported {}", l));
+        }
+        if l.is_empty() {
+            break;
+        }
+    }
+    line.clear();
+    reader.read_line(&mut line).map_err(io_error)?;
+    let (width, height) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", h, "+X", w] => match (w.parse::<usize>(), h.parse::<usize>()) {
+            (Ok(w), Ok(h)) if w > 0 && h > 0 => (w, h),
+            _ => return decode_error(file, format!("bad resolution '{}'", line.trim()))
+        },
+        _ => return decode_error(file, format!("unsupported orientation '{}'", line.trim()))
+    };
+
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).map_err(io_error)?;
+    let truncated = || decode_error(file, "truncated pixel data".to_string());
+    let mut pos = 0;
+    let mut texels = Vec::with_capacity(width*height);
+    let mut scanline = vec![0u8; width*4];
+    for _ in 0..height {
+        let rle = (8..0x8000).contains(&width) && data.len() >= pos + 4
+            && data[pos] == 2 && data[pos+1] == 2 && data[pos+2] & 0x80 == 0;
+        if rle {
+            if (usize::from(data[pos+2]) << 8 | usize::from(data[pos+3])) != width {
+                return decode_error(file, "scanline width mismatch".to_string());
+            }
+            pos += 4;
+            // each component is stored separately
+            for c in 0..4 {
+                let mut x = 0;
+                while x < width {
+                    let count = match data.get(pos) { Some(&n) => usize::from(n), None => return truncated() };
+                    pos += 1;
+                    if count > 128 {
+                        let n = count - 128;
+                        let value = match data.get(pos) { Some(&v) => v, None => return truncated() };
+                        pos += 1;
+                        if x + n > width { return truncated(); }
+                        for i in 0..n { scanline[(x + i)*4 + c] = value; }
+                        x += n;
+                    } else {
+                        if count == 0 || x + count > width || pos + count > data.len() { return truncated(); }
+                        for i in 0..count { scanline[(x + i)*4 + c] = data[pos + i]; }
+                        pos += count;
+                        x += count;
+                    }
+                }
+            }
+        } else {
+            if pos + width*4 > data.len() { return truncated(); }
+            scanline.copy_from_slice(&data[pos..pos + width*4]);
+            pos += width*4;
+        }
+        for px in scanline.chunks(4) {
+            let [r, g, b] = from_rgbe(px);
+            texels.push(Vec3::new(T::from_f64(r).unwrap(), T::from_f64(g).unwrap(), T::from_f64(b).unwrap()));
+        }
+    }
+    Ok((width, height, texels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_png, decode_rgbe, ImageTexture, WrapMode};
+    use std::io::Cursor;
+    use vec3::Vec3;
+    use texture::Texture;
+    use framebuffer::Framebuffer;
+    use imagewriter::{ImageWriter, PngWriter, ToneMap};
+    use hdrwriter::RgbeWriter;
+
+    // 2x2: black, white on the top row; red, green on the bottom
+    fn texture(wrap: WrapMode) -> ImageTexture<f64> {
+        ImageTexture::new(2, 2, vec![Vec3::new(0., 0., 0.), Vec3::new(1., 1., 1.),
+                                     Vec3::new(1., 0., 0.), Vec3::new(0., 1., 0.)], wrap)
+    }
+
+    #[test]
+    fn test_bilinear() {
+        let tex = texture(WrapMode::Clamp);
+        let at = |u: f64, v: f64| tex.value(u, v, &Vec3::default());
+        // texel centres
+        assert_approx_eq!(1.0, at(0.75, 0.75).y());
+        assert_approx_eq!(1.0, at(0.25, 0.25).x());
+        // halfway between the two top texels
+        assert_approx_eq!(0.5, at(0.5, 0.75).x());
+        // the middle averages all four
+        let c = at(0.5, 0.5);
+        assert_approx_eq!(0.5, c.x());
+        assert_approx_eq!(0.5, c.y());
+        assert_approx_eq!(0.25, c.z());
+    }
+
+    #[test]
+    fn test_wrap() {
+        let at = |wrap, u: f64| texture(wrap).value(u, 0.75, &Vec3::default()).x();
+        assert_approx_eq!(1.0, at(WrapMode::Clamp, 1.75));
+        assert_approx_eq!(0.0, at(WrapMode::Repeat, 1.25));
+        assert_approx_eq!(1.0, at(WrapMode::Mirror, 1.25));
+        assert_approx_eq!(0.0, at(WrapMode::Mirror, -0.25));
+        assert_approx_eq!(1.0, at(WrapMode::Mirror, -0.75));
+        // repeat blends across the seam
+        assert_approx_eq!(0.5, at(WrapMode::Repeat, 0.));
+        assert_approx_eq!(0.0, at(WrapMode::Clamp, 0.));
+    }
+
+    fn framebuffer() -> Framebuffer<f64> {
+        let mut fb = Framebuffer::new(9, 3);
+        for y in 0..3 {
+            for x in 0..9 {
+                fb.add_samples(x, y, Vec3::new(x as f64 / 8., y as f64 / 2., 20.), 1);
+            }
+        }
+        fb
+    }
+
+    #[test]
+    fn test_decode_png() {
+        let fb = framebuffer();
+        let mut data = Vec::new();
+        PngWriter { tone_map: ToneMap::None }.write(&fb, &mut data).unwrap();
+        let (width, height, texels) = decode_png::<f64, _>(Cursor::new(data), "test.png").unwrap();
+        assert_eq!((9, 3), (width, height));
+        assert_approx_eq!(1.0, texels[8].x());
+        assert_approx_eq!(0.0, texels[0].x());
+        // 127/255 in sRGB is about 21% linear
+        assert_approx_eq!(0.2122, texels[9].y(), 1e-3);
+    }
+
+    #[test]
+    fn test_decode_rgbe() {
+        let fb = framebuffer();
+        let mut data = Vec::new();
+        RgbeWriter { tone_map: ToneMap::None }.write(&fb, &mut data).unwrap();
+        let (width, height, texels) = decode_rgbe::<f64, _>(Cursor::new(data), "test.hdr").unwrap();
+        assert_eq!((9, 3), (width, height));
+        for y in 0..3 {
+            for x in 0..9 {
+                let (a, b) = (fb.pixel(x, y), &texels[y*9 + x]);
+                // within RGBE's precision: 8 bits relative to the largest component
+                assert!((&a - b).length() < 0.2, "{} vs {}", a, b);
+            }
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use png;
+
+use vec3::{ElemT, Vec3};
+use framebuffer::Framebuffer;
+use hdrwriter::{ExrPixelType, ExrWriter, PfmWriter, RgbeWriter};
+
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum OutputFormat {
+    Ppm,
+    PpmAscii,
+    Png,
+    Exr(ExrPixelType, bool), // pixel type, alpha channel
+    Rgbe,
+    Pfm
+}
+
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum ToneMap {
+    None,
+    Gamma // gamma 2, as in the book
+}
+
+impl ToneMap {
+    pub fn from_name(name: &str) -> Option<ToneMap> {
+        match name.to_lowercase().as_str() {
+            "none" | "linear" => Some(ToneMap::None),
+            "gamma" => Some(ToneMap::Gamma),
+            _ => None
+        }
+    }
+
+    pub fn apply<T: ElemT>(&self, col: Vec3<T>) -> Vec3<T> {
+        match *self {
+            ToneMap::None => col,
+            ToneMap::Gamma => Vec3::new(col.r().max(T::zero()).sqrt(),
+                                        col.g().max(T::zero()).sqrt(),
+                                        col.b().max(T::zero()).sqrt())
+        }
+    }
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        match name.to_lowercase().as_str() {
+            "ppm" | "p6" => Some(OutputFormat::Ppm),
+            "ppm-ascii" | "p3" => Some(OutputFormat::PpmAscii),
+            "png" => Some(OutputFormat::Png),
+            "exr" => Some(OutputFormat::Exr(ExrPixelType::Half, false)),
+            "exr-rgba" => Some(OutputFormat::Exr(ExrPixelType::Half, true)),
+            "exr-float" => Some(OutputFormat::Exr(ExrPixelType::Float, false)),
+            "exr-float-rgba" => Some(OutputFormat::Exr(ExrPixelType::Float, true)),
+            "hdr" | "rgbe" => Some(OutputFormat::Rgbe),
+            "pfm" => Some(OutputFormat::Pfm),
+            _ => None
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<OutputFormat> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "ppm" => Some(OutputFormat::Ppm),
+            "png" => Some(OutputFormat::Png),
+            "exr" => Some(OutputFormat::Exr(ExrPixelType::Half, false)),
+            "hdr" => Some(OutputFormat::Rgbe),
+            "pfm" => Some(OutputFormat::Pfm),
+            _ => None
+        }
+    }
+
+    // 8-bit formats are gamma corrected by default, float formats keep
+    // linear radiance
+    pub fn default_tone_map(&self) -> ToneMap {
+        match *self {
+            OutputFormat::Ppm | OutputFormat::PpmAscii | OutputFormat::Png => ToneMap::Gamma,
+            _ => ToneMap::None
+        }
+    }
+
+    pub fn writer<T: ElemT>(&self, tone_map: ToneMap) -> Box<dyn ImageWriter<T>> {
+        match *self {
+            OutputFormat::Ppm => Box::new(PpmWriter { tone_map }),
+            OutputFormat::PpmAscii => Box::new(PpmAsciiWriter { tone_map }),
+            OutputFormat::Png => Box::new(PngWriter { tone_map }),
+            OutputFormat::Exr(pixel_type, alpha) => Box::new(ExrWriter { pixel_type, alpha, tone_map }),
+            OutputFormat::Rgbe => Box::new(RgbeWriter { tone_map }),
+            OutputFormat::Pfm => Box::new(PfmWriter { tone_map })
+        }
+    }
+}
+
+pub trait ImageWriter<T: ElemT> {
+    fn write(&self, fb: &Framebuffer<T>, out: &mut dyn Write) -> io::Result<()>;
+
+    fn write_file(&self, fb: &Framebuffer<T>, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write(fb, &mut out)?;
+        out.flush()
+    }
+}
+
+// Tone map and clamp to 8 bits
+pub fn to_rgb8<T: ElemT>(fb: &Framebuffer<T>, x: usize, y: usize, tone_map: ToneMap) -> [u8; 3] {
+    let col = tone_map.apply(fb.pixel(x, y));
+    let quantize = |c: T| {
+        let c = c.to_f64().unwrap().max(0.);
+        (255.99*c).min(255.) as u8
+    };
+    [quantize(col.r()), quantize(col.g()), quantize(col.b())]
+}
+
+fn rgb8_data<T: ElemT>(fb: &Framebuffer<T>, tone_map: ToneMap) -> Vec<u8> {
+    let mut data = Vec::with_capacity(fb.width()*fb.height()*3);
+    for y in 0..fb.height() {
+        for x in 0..fb.width() {
+            data.extend_from_slice(&to_rgb8(fb, x, y, tone_map));
+        }
+    }
+    data
+}
+
+// Binary PPM (P6)
+pub struct PpmWriter {
+    pub tone_map: ToneMap
+}
+
+impl<T: ElemT> ImageWriter<T> for PpmWriter {
+    fn write(&self, fb: &Framebuffer<T>, out: &mut dyn Write) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", fb.width(), fb.height())?;
+        out.write_all(&rgb8_data(fb, self.tone_map))
+    }
+}
+
+// ASCII PPM (P3)
+pub struct PpmAsciiWriter {
+    pub tone_map: ToneMap
+}
+
+impl<T: ElemT> ImageWriter<T> for PpmAsciiWriter {
+    fn write(&self, fb: &Framebuffer<T>, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "P3\n{} {}\n255", fb.width(), fb.height())?;
+        for y in 0..fb.height() {
+            for x in 0..fb.width() {
+                let [r, g, b] = to_rgb8(fb, x, y, self.tone_map);
+                writeln!(out, "{} {} {}", r, g, b)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct PngWriter {
+    pub tone_map: ToneMap
+}
+
+impl<T: ElemT> ImageWriter<T> for PngWriter {
+    fn write(&self, fb: &Framebuffer<T>, out: &mut dyn Write) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(out, fb.width() as u32, fb.height() as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&rgb8_data(fb, self.tone_map))?;
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ImageWriter, OutputFormat, PngWriter, PpmAsciiWriter, PpmWriter, ToneMap};
+    use hdrwriter::ExrPixelType;
+    use std::path::Path;
+    use vec3::Vec3;
+    use framebuffer::Framebuffer;
+
+    fn test_image() -> Framebuffer<f64> {
+        let mut fb = Framebuffer::new(2, 1);
+        fb.add_samples(0, 0, Vec3::new(1., 0.25, 0.), 1);
+        fb.add_samples(1, 0, Vec3::new(4., -1., 0.), 1); // out of range gets clamped
+        fb
+    }
+
+    #[test]
+    fn test_ppm() {
+        let mut out = Vec::new();
+        PpmWriter { tone_map: ToneMap::Gamma }.write(&test_image(), &mut out).unwrap();
+        assert_eq!(b"P6\n2 1\n255\n\xff\x7f\x00\xff\x00\x00".to_vec(), out);
+
+        let mut out = Vec::new();
+        PpmAsciiWriter { tone_map: ToneMap::Gamma }.write(&test_image(), &mut out).unwrap();
+        assert_eq!("P3\n2 1\n255\n255 127 0\n255 0 0\n", String::from_utf8(out).unwrap());
+
+        let mut out = Vec::new();
+        PpmAsciiWriter { tone_map: ToneMap::None }.write(&test_image(), &mut out).unwrap();
+        assert_eq!("P3\n2 1\n255\n255 63 0\n255 0 0\n", String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn test_png() {
+        let mut out = Vec::new();
+        PngWriter { tone_map: ToneMap::Gamma }.write(&test_image(), &mut out).unwrap();
+        assert_eq!(b"\x89PNG\r\n\x1a\n".to_vec(), out[..8].to_vec());
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Some(OutputFormat::Ppm), OutputFormat::from_path(Path::new("out.ppm")));
+        assert_eq!(Some(OutputFormat::Png), OutputFormat::from_path(Path::new("dir/OUT.PNG")));
+        assert_eq!(Some(OutputFormat::Exr(ExrPixelType::Half, false)), OutputFormat::from_path(Path::new("out.exr")));
+        assert_eq!(Some(OutputFormat::Rgbe), OutputFormat::from_path(Path::new("out.hdr")));
+        assert_eq!(Some(OutputFormat::Pfm), OutputFormat::from_path(Path::new("out.pfm")));
+        assert_eq!(None, OutputFormat::from_path(Path::new("out.gif")));
+        assert_eq!(None, OutputFormat::from_path(Path::new("out")));
+    }
+}
@@ -1,47 +1,93 @@
-extern crate rand;
+use std::sync::Arc;
 
 use vec3::{ElemT, Vec3};
 use ray::Ray;
+use rng::Sampler;
 use hitable::HitRecord;
 use material::Material;
-
-use rand::Rng;
+use onb::Onb;
+use sampling;
+use texture::{ConstantTexture, Texture};
 
 #[derive(Clone)]
 pub struct Lambertian<T: ElemT> {
-    albedo: Vec3<T>
+    albedo: Arc<dyn Texture<T>>
 }
 
 impl<T: ElemT> Lambertian<T> {
     pub fn new(a: Vec3<T>) -> Lambertian<T> {
+        Lambertian::with_texture(Arc::new(ConstantTexture::new(a)))
+    }
+
+    pub fn with_texture(albedo: Arc<dyn Texture<T>>) -> Lambertian<T> {
         Lambertian::<T> {
-            albedo: a
+            albedo
         }
     }
 }
 
-// TODO: could we make the random number generator generic (f32, f64, etc)?
-// TODO: put this somewhere else so metal can also use it
-pub fn random_in_unit_sphere<T: ElemT>() -> Vec3<T> {
-    let mut rng = rand::thread_rng();
-
-    let mut p;
-    loop {
-        let r1 = T::from_f64(rng.next_f64()).unwrap();
-        let r2 = T::from_f64(rng.next_f64()).unwrap();
-        let r3 = T::from_f64(rng.next_f64()).unwrap();
-        let two = T::from_f64(2.).unwrap();
-        p = Vec3::<T>::new(r1, r2, r3)*two
-                - Vec3::new(T::one(), T::one(), T::one());
-        if p.squared_length() >= T::one() { break; }
-    }
-    p
+// Normal on the side the ray came from
+fn facing_normal<T: ElemT>(r_in: &Ray<T>, rec: &HitRecord<T>) -> Vec3<T> {
+    if r_in.direction().dot(&rec.normal) > T::zero() { -rec.normal.clone() } else { rec.normal.clone() }
+}
+
+// Cosine weighted direction in the hemisphere around `n`
+fn random_cosine_direction<T: ElemT>(n: &Vec3<T>, sampler: &mut Sampler) -> Vec3<T> {
+    let t = |x: f64| T::from_f64(x).unwrap();
+    let w = sampling::cosine_hemisphere(t(sampler.next_f64()), t(sampler.next_f64()));
+    Onb::from_w(n).local(w.x(), w.y(), w.z())
 }
 
 impl<T: ElemT> Material<T> for Lambertian<T> {
-    #[allow(unused_variables)]
-    fn scatter(&self, r_in: &Ray<T>, rec: &HitRecord<T>) -> Option<(Vec3<T>, Ray<T>)> {
-        let target = &rec.p + &rec.normal + &random_in_unit_sphere();
-        Some((self.albedo.clone(), Ray::new(rec.p.clone(), &target-&rec.p)))
+    // Importance samples the cosine term, so the weight is just the albedo
+    fn scatter(&self, r_in: &Ray<T>, rec: &HitRecord<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, Ray<T>)> {
+        let n = facing_normal(r_in, rec);
+        Some((self.albedo.value(rec.u, rec.v, &rec.p), Ray::with_time(rec.p.clone(), random_cosine_direction(&n, sampler), r_in.time())))
+    }
+
+    fn eval(&self, r_in: &Ray<T>, rec: &HitRecord<T>, direction: &Vec3<T>) -> Option<(Vec3<T>, T)> {
+        let n = facing_normal(r_in, rec).unit_vector();
+        let pdf = sampling::cosine_hemisphere_pdf(n.dot(&direction.unit_vector()));
+        Some((self.albedo.value(rec.u, rec.v, &rec.p)*pdf, pdf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::Lambertian;
+    use vec3::Vec3;
+    use ray::Ray;
+    use hitable::HitRecord;
+    use material::Material;
+    use rng::Sampler;
+
+    #[test]
+    fn test_cosine() {
+        let mut sampler = Sampler::new(42);
+        let rec = HitRecord { p: Vec3::new(0., 0., 0.), normal: Vec3::new(0., 0., 1.), ..HitRecord::default() };
+        let diffuse = Lambertian::<f64>::new(Vec3::new(0.5, 0.5, 0.5));
+        // from either side, scattered back into the side the ray came from
+        // with the cosine distribution eval describes
+        for &z in &[1., -1.] {
+            let r = Ray::new(Vec3::new(0.3, 0., z), Vec3::new(-0.3, 0., -z));
+            let n = 100000;
+            let mut mean_cos = 0.;
+            for _ in 0..n {
+                let (w, scattered) = diffuse.scatter(&r, &rec, &mut sampler).unwrap();
+                assert_approx_eq!(0.5, w.x());
+                let d = scattered.direction();
+                assert_approx_eq!(1.0, d.length());
+                let cos = d.z() * z;
+                assert!(cos >= 0.);
+                mean_cos += cos;
+            }
+            assert_approx_eq!(2. / 3., mean_cos / n as f64, 0.005);
+            let (f, pdf) = diffuse.eval(&r, &rec, &Vec3::new(0., 0., z)).unwrap();
+            assert_approx_eq!(1. / PI, pdf);
+            assert_approx_eq!(0.5 / PI, f.x());
+            assert_eq!(0.0, diffuse.eval(&r, &rec, &Vec3::new(0., 0., -z)).unwrap().1);
+        }
     }
 }
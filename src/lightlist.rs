@@ -0,0 +1,106 @@
+use vec3::{ElemT, Vec3};
+use hitable::Hitable;
+use rng::Sampler;
+
+// Every emitter in a scene, for next-event estimation. A light is picked
+// uniformly and then asked for a point on itself; the pdf of the resulting
+// direction is the average over all lights, since any of them could have
+// produced it.
+pub struct LightList<'a, T: ElemT + 'a> {
+    lights: Vec<&'a dyn Hitable<T>>
+}
+
+impl<'a, T: ElemT> LightList<'a, T> {
+    pub fn new(world: &'a dyn Hitable<T>) -> LightList<'a, T> {
+        let mut lights = Vec::new();
+        world.collect_lights(&mut lights);
+        LightList { lights }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    // Direction from `origin` towards a point on one of the lights, with its
+    // solid angle pdf
+    pub fn sample(&self, origin: &Vec3<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, T)> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let n = self.lights.len();
+        let i = ((sampler.next_f64() * n as f64) as usize).min(n - 1);
+        let (p, pdf) = self.lights[i].sample(origin, sampler)?;
+        let direction = p - origin;
+        if n == 1 {
+            return Some((direction, pdf));
+        }
+        let pdf = self.pdf(origin, &direction);
+        Some((direction, pdf))
+    }
+
+    pub fn pdf(&self, origin: &Vec3<T>, direction: &Vec3<T>) -> T {
+        if self.lights.is_empty() {
+            return T::zero();
+        }
+        let sum = self.lights.iter().fold(T::zero(), |sum, l| sum + l.pdf(origin, direction));
+        sum / T::from_usize(self.lights.len()).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LightList;
+    use vec3::Vec3;
+    use hitable::Hitable;
+    use bvh::BvhNode;
+    use sphere::Sphere;
+    use triangle::Triangle;
+    use lambertian::Lambertian;
+    use diffuselight::DiffuseLight;
+    use rng::Sampler;
+    use sampling;
+
+    fn light() -> Box<DiffuseLight<f64>> {
+        Box::new(DiffuseLight::new(Vec3::new(1., 1., 1.)))
+    }
+
+    #[test]
+    fn test_collect_lights() {
+        let list: Vec<Box<dyn Hitable<f64>>> = vec![
+            Box::new(Sphere::new(Vec3::new(0., 0., 0.), 1., Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))))),
+            Box::new(Sphere::new(Vec3::new(0., 5., 0.), 1., light())),
+            Box::new(Triangle::new(Vec3::new(0., 0., 3.), Vec3::new(1., 0., 3.), Vec3::new(0., 1., 3.), light()))
+        ];
+        let world = BvhNode::new(list);
+        assert_eq!(2, LightList::new(&world).lights.len());
+    }
+
+    // Integrating the pdf over all directions with uniform sphere samples
+    // should give one, for each kind of light and for the mixture
+    #[test]
+    fn test_pdf_integrates_to_one() {
+        let list: Vec<Box<dyn Hitable<f64>>> = vec![
+            Box::new(Sphere::new(Vec3::new(0., 3., 0.), 1., light())),
+            Box::new(Triangle::new(Vec3::new(-1., -1., -2.), Vec3::new(1., -1., -2.), Vec3::new(0., 1., -2.), light()))
+        ];
+        let world = BvhNode::new(list);
+        let lights = LightList::new(&world);
+        let origin = Vec3::new(0.2, 0.1, 0.);
+
+        let mut sampler = Sampler::new(1);
+        let n = 200000;
+        let mut sum = 0.;
+        for _ in 0..n {
+            let d = sampling::uniform_sphere(sampler.next_f64(), sampler.next_f64());
+            sum += lights.pdf(&origin, &d);
+        }
+        let integral = sum * 4.*::std::f64::consts::PI / n as f64;
+        assert!((integral - 1.).abs() < 0.02, "integral {}", integral);
+
+        // sampled directions must have the pdf the list reports for them
+        for _ in 0..100 {
+            let (d, pdf) = lights.sample(&origin, &mut sampler).unwrap();
+            assert_approx_eq!(pdf, lights.pdf(&origin, &d), 1e-9);
+        }
+    }
+}
@@ -1,130 +1,130 @@
 #[allow(unused_imports)]
 #[macro_use]
 extern crate assert_approx_eq;
+extern crate jpeg_decoder;
 extern crate num;
 extern crate num_traits;
+extern crate png;
 extern crate rand;
+extern crate toml;
 
 mod vec3;
+mod mat4;
 mod ray;
 mod hitable;
 mod sphere;
+mod movingsphere;
+mod rect;
+mod aabox;
+mod plane;
+mod roots;
+mod quadric;
+mod torus;
+mod csg;
+mod sdf;
+mod constantmedium;
+mod voxelgrid;
+mod volume;
 mod hitablelist;
+mod aabb;
+mod bvh;
+mod transformed;
+mod animated;
+mod triangle;
+mod trianglemesh;
+mod objloader;
 mod camera;
 mod material;
 mod lambertian;
 mod metal;
 mod dielectric;
+mod microfacet;
+mod conductor;
+mod roughdielectric;
+mod principled;
+mod diffuselight;
+mod phase;
+mod onb;
+mod sampling;
+mod texture;
+mod imagetexture;
+mod noise;
+mod lightlist;
+mod atmosphere;
+mod rng;
+mod scene;
+mod render;
+mod cli;
+mod framebuffer;
+mod imagewriter;
+mod hdrwriter;
 
-use std::f64;
-use rand::Rng;
+use std::env;
+use std::io;
+use std::path::Path;
+use std::process;
 
-type Vec3 = vec3::Vec3<f64>;
-type Ray = ray::Ray<f64>;
-type Camera = camera::Camera<f64>;
-type Sphere = sphere::Sphere<f64>;
-type Hitable = hitable::Hitable<f64>;
-type HitableList = hitablelist::HitableList<f64>;
-type Lambertian = lambertian::Lambertian<f64>;
-type Metal = metal::Metal<f64>;
-type Dielectric = dielectric::Dielectric<f64>;
+use cli::CliError;
+use imagewriter::OutputFormat;
 
-fn color(r: &Ray, world: &Hitable, depth: i32) -> Vec3 {
-    if let Some(rec) = world.hit(&r, 0.001, f64::MAX) {
-        if depth >= 50 { // stop recursion
-            return Vec3::new(0., 0., 0.);
-        }
+type Scene = scene::Scene<f64>;
 
-        if let Some((attenuated, scattered)) = rec.clone().mat_opt.unwrap().scatter(r, &rec) {
-            attenuated * color(&scattered, world, depth+1)
+fn main() {
+    let opts = match cli::parse(env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(CliError::Help) => {
+            print!("{}", cli::USAGE);
+            return;
         }
-        else {
-            Vec3::new(0., 0., 0.)
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            process::exit(2);
         }
-    }
-    else {
-        let unit_direction = r.direction().unit_vector();
-        let t = 0.5*(unit_direction.y() + 1.);
-        return (1.-t)*Vec3::new(1., 1., 1.) + t*Vec3::new(0.5, 0.7, 1.0);
-    }
-}
-
-fn random_scene() -> HitableList {
-    let mut rng = rand::thread_rng();
+    };
 
-    let mut list = Vec::<Box<Hitable>>::new();
-    list.push(Box::new(Sphere::new(Vec3::new(0., -1000., 0.), 1000., Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))))));
-    for a in -11..12 { // TODO: better way to write inclusive?
-        for b in -11..12 {
-            let a = a as f64;
-            let b = b as f64;
-            let choose_mat = rng.next_f64();
-            let center = Vec3::new(a+0.9*rng.next_f64(), 0.2, b+0.9*rng.next_f64());
-            if (&center - Vec3::new(4., 0.2, 0.)).length() > 0.9 {
-                if choose_mat < 0.8 { // diffuse
-                    let r1 = rng.next_f64();
-                    let r2 = rng.next_f64();
-                    let r3 = rng.next_f64();
-                    let r4 = rng.next_f64();
-                    let r5 = rng.next_f64();
-                    let r6 = rng.next_f64();
-                    list.push(Box::new(Sphere::new(center, 0.2, Box::new(Lambertian::new(Vec3::new(r1*r2, r3*r4, r5*r6))))));
-                }
-                else if choose_mat < 0.95 { // metal
-                    let r1 = rng.next_f64();
-                    let r2 = rng.next_f64();
-                    let r3 = rng.next_f64();
-                    let r4 = rng.next_f64();
-                    list.push(Box::new(Sphere::new(center, 0.2, Box::new(Metal::new(Vec3::new(0.5*(1. + r1), 0.5*(1.+r2), 0.5*(1.+r3)), 0.5*r4)))));
-                }
-                else { // glass
-                    list.push(Box::new(Sphere::new(center, 0.2, Box::new(Dielectric::new(1.5)))));
-                }
+    let mut scene: Scene = match opts.scene {
+        Some(ref file) => match scene::load_scene(Path::new(file)) {
+            Ok(scene) => scene,
+            Err(e) => {
+                eprintln!("error loading scene: {}", e);
+                process::exit(1);
             }
-        }
-    }
-    list.push(Box::new(Sphere::new(Vec3::new(0., 1., 0.), 1., Box::new(Dielectric::new(1.5)))));
-    list.push(Box::new(Sphere::new(Vec3::new(-4., 1., 0.), 1., Box::new(Lambertian::new(Vec3::new(0.4, 0.2, 0.1)))))); 
-    list.push(Box::new(Sphere::new(Vec3::new(4., 1., 0.), 1., Box::new(Metal::new(Vec3::new(0.7, 0.6, 0.5), 0.))))); 
+        },
+        None => scene::random_scene()
+    };
 
-    HitableList::new(list)
-}
-
-fn main() {
-	let nx = 1200;
-	let ny = 800;
-    let ns = 10;
-
-    println!("P3\n {} {} \n255", nx, ny);
+    // command-line settings override the scene file
+    let settings = &mut scene.render;
+    settings.width = opts.width.unwrap_or(settings.width);
+    settings.height = opts.height.unwrap_or(settings.height);
+    settings.samples = opts.samples.unwrap_or(settings.samples);
+    settings.max_depth = opts.max_depth.unwrap_or(settings.max_depth);
+    settings.seed = opts.seed.unwrap_or(settings.seed);
+    let (nx, ny) = (settings.width, settings.height);
 
-    let world = random_scene();
-
-    let lookfrom = Vec3::new(13.,2.,3.);
-    let lookat = Vec3::new(0.,0.,0.);
-    // let dist_to_focus = (&lookfrom-&lookat).length();
-    let dist_to_focus = 10.;
-    let aperture = 0.1;
-    let cam = Camera::new(lookfrom, lookat,
-                          Vec3::new(0., 1., 0.),
-                          20., (nx as f64) / (ny as f64),
-                          aperture, dist_to_focus);
-
-    let mut rng = rand::thread_rng();
-    for j in (0..ny).rev() {
-        for i in 0..nx {
-            let mut col = Vec3::default();
-            for _ in 0..ns {
-                let u = (i as f64 + rng.next_f64()) / (nx as f64);
-                let v = (j as f64 + rng.next_f64()) / (ny as f64);
-                let r = cam.get_ray(u, v);
-                col += color(&r, &world, 0);
+    // pick the format before rendering so a bad file name fails fast
+    let format = match (opts.format, opts.output.as_ref()) {
+        (Some(format), _) => format,
+        (None, None) => OutputFormat::PpmAscii,
+        (None, Some(file)) => match OutputFormat::from_path(Path::new(file)) {
+            Some(format) => format,
+            None => {
+                eprintln!("error: can't tell the image format from '{}', use --format", file);
+                process::exit(2);
             }
-            col /= ns as f64;
-            col = Vec3::new(col.x().sqrt(), col.y().sqrt(), col.z().sqrt());
-            let (ir, ig, ib) = ((255.99*col.x()) as i32,
-                                (255.99*col.y()) as i32,
-                                (255.99*col.z()) as i32);
-            println!("{} {} {}", ir, ig, ib);
         }
+    };
+
+    let cam = scene.camera.build((nx as f64) / (ny as f64));
+    let fb = render::render(&scene.world, &scene.background, scene.atmosphere.as_ref(), &cam, &scene.render, opts.threads);
+
+    let writer = format.writer(opts.tone_map.unwrap_or(format.default_tone_map()));
+    let result = match opts.output {
+        Some(ref file) => writer.write_file(&fb, Path::new(file)),
+        None => writer.write(&fb, &mut io::stdout().lock())
+    };
+    if let Err(e) = result {
+        eprintln!("error writing image: {}", e);
+        process::exit(1);
     }
 }
@@ -0,0 +1,243 @@
+use std::ops::*;
+
+use vec3::{ElemT, Vec3};
+
+// Row-major 4x4 matrix for affine transforms of column vectors. Points are
+// (x, y, z, 1) and directions (x, y, z, 0).
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct Mat4<T: ElemT> {
+    m: [[T; 4]; 4]
+}
+
+impl<T: ElemT> Mat4<T> {
+    pub fn new(m: [[T; 4]; 4]) -> Mat4<T> {
+        Mat4 { m }
+    }
+
+    pub fn identity() -> Mat4<T> {
+        let mut m = [[T::zero(); 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = T::one();
+        }
+        Mat4 { m }
+    }
+
+    pub fn translation(v: &Vec3<T>) -> Mat4<T> {
+        let mut r = Mat4::identity();
+        r.m[0][3] = v.x();
+        r.m[1][3] = v.y();
+        r.m[2][3] = v.z();
+        r
+    }
+
+    pub fn scaling(v: &Vec3<T>) -> Mat4<T> {
+        let mut r = Mat4::identity();
+        r.m[0][0] = v.x();
+        r.m[1][1] = v.y();
+        r.m[2][2] = v.z();
+        r
+    }
+
+    // Counter-clockwise rotation by `degrees` about `axis`, looking down the
+    // axis towards the origin (Rodrigues' formula)
+    pub fn rotation(axis: &Vec3<T>, degrees: T) -> Mat4<T> {
+        let a = axis.unit_vector();
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        let theta = degrees.to_radians();
+        let (s, c) = (theta.sin(), theta.cos());
+        let t = T::one() - c;
+        let mut r = Mat4::identity();
+        r.m[0][0] = t*x*x + c;
+        r.m[0][1] = t*x*y - s*z;
+        r.m[0][2] = t*x*z + s*y;
+        r.m[1][0] = t*x*y + s*z;
+        r.m[1][1] = t*y*y + c;
+        r.m[1][2] = t*y*z - s*x;
+        r.m[2][0] = t*x*z - s*y;
+        r.m[2][1] = t*y*z + s*x;
+        r.m[2][2] = t*z*z + c;
+        r
+    }
+
+    // Scales, then rotates about x, y and z in turn (`rotate` in degrees),
+    // then translates; how scenes place objects
+    pub fn scale_rotate_translate(scale: &Vec3<T>, rotate: &Vec3<T>, translate: &Vec3<T>) -> Mat4<T> {
+        let mut m = Mat4::scaling(scale);
+        for a in 0..3 {
+            let mut axis = Vec3::default();
+            axis[a] = T::one();
+            m = &Mat4::rotation(&axis, rotate[a]) * &m;
+        }
+        &Mat4::translation(translate) * &m
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> T {
+        self.m[row][col]
+    }
+
+    pub fn transpose(&self) -> Mat4<T> {
+        let mut r = Mat4::identity();
+        for i in 0..4 {
+            for j in 0..4 {
+                r.m[i][j] = self.m[j][i];
+            }
+        }
+        r
+    }
+
+    // Gauss-Jordan elimination with partial pivoting. None if singular,
+    // which is when a pivot is down at rounding error next to the largest
+    // entry in its column, so tiny but valid scales still invert.
+    pub fn inverse(&self) -> Option<Mat4<T>> {
+        let mut a = self.m;
+        let mut inv = Mat4::identity().m;
+        for col in 0..4 {
+            let largest = (0..4).fold(T::zero(), |m, i| m.max(self.m[i][col].abs()));
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().partial_cmp(&a[j][col].abs()).unwrap()).unwrap();
+            if a[pivot][col].abs() <= T::epsilon() * T::from_f64(4.).unwrap() * largest {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let d = a[col][col];
+            for j in 0..4 {
+                a[col][j] /= d;
+                inv[col][j] /= d;
+            }
+            for i in 0..4 {
+                if i != col {
+                    let f = a[i][col];
+                    for j in 0..4 {
+                        a[i][j] -= f*a[col][j];
+                        inv[i][j] -= f*inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Mat4 { m: inv })
+    }
+
+    // Determinant of the upper 3x3 (linear) part
+    pub fn det3(&self) -> T {
+        let m = &self.m;
+        m[0][0]*(m[1][1]*m[2][2] - m[1][2]*m[2][1])
+            - m[0][1]*(m[1][0]*m[2][2] - m[1][2]*m[2][0])
+            + m[0][2]*(m[1][0]*m[2][1] - m[1][1]*m[2][0])
+    }
+
+    pub fn transform_point(&self, p: &Vec3<T>) -> Vec3<T> {
+        let m = &self.m;
+        let row = |i: usize| m[i][0]*p.x() + m[i][1]*p.y() + m[i][2]*p.z() + m[i][3];
+        let w = row(3);
+        let (x, y, z) = (row(0), row(1), row(2));
+        if w == T::one() { Vec3::new(x, y, z) } else { Vec3::new(x/w, y/w, z/w) }
+    }
+
+    pub fn transform_vector(&self, v: &Vec3<T>) -> Vec3<T> {
+        let m = &self.m;
+        let row = |i: usize| m[i][0]*v.x() + m[i][1]*v.y() + m[i][2]*v.z();
+        Vec3::new(row(0), row(1), row(2))
+    }
+
+    // Normals transform by the inverse transpose; this matrix is taken to
+    // already be the inverse, so only the transpose is applied here
+    pub fn transform_normal_by_inverse(&self, n: &Vec3<T>) -> Vec3<T> {
+        let m = &self.m;
+        let col = |j: usize| m[0][j]*n.x() + m[1][j]*n.y() + m[2][j]*n.z();
+        Vec3::new(col(0), col(1), col(2))
+    }
+}
+
+impl<T: ElemT> Default for Mat4<T> {
+    fn default() -> Mat4<T> {
+        Mat4::identity()
+    }
+}
+
+// Composition: (a * b) applies b first, then a
+impl<'a, T: ElemT> Mul<&'a Mat4<T>> for &'a Mat4<T> {
+    type Output = Mat4<T>;
+    fn mul(self, rhs: &Mat4<T>) -> Mat4<T> {
+        let mut r = [[T::zero(); 4]; 4];
+        for (i, row) in r.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                for k in 0..4 {
+                    *x += self.m[i][k]*rhs.m[k][j];
+                }
+            }
+        }
+        Mat4 { m: r }
+    }
+}
+
+impl<T: ElemT> Mul for Mat4<T> {
+    type Output = Mat4<T>;
+    fn mul(self, rhs: Mat4<T>) -> Mat4<T> {
+        &self * &rhs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mat4;
+    use vec3::Vec3;
+
+    fn assert_vec_eq(a: &Vec3<f64>, b: &Vec3<f64>) {
+        assert_approx_eq!(a.x(), b.x());
+        assert_approx_eq!(a.y(), b.y());
+        assert_approx_eq!(a.z(), b.z());
+    }
+
+    #[test]
+    fn test_transforms() {
+        let t = Mat4::translation(&Vec3::new(1., 2., 3.));
+        assert_vec_eq(&Vec3::new(1., 2., 3.), &t.transform_point(&Vec3::default()));
+        assert_vec_eq(&Vec3::new(1., 0., 0.), &t.transform_vector(&Vec3::new(1., 0., 0.)));
+
+        let r = Mat4::rotation(&Vec3::new(0., 0., 1.), 90.);
+        assert_vec_eq(&Vec3::new(0., 1., 0.), &r.transform_vector(&Vec3::new(1., 0., 0.)));
+
+        // scale, then rotate, then translate
+        let m = &t * &(&r * &Mat4::scaling(&Vec3::new(2., 2., 2.)));
+        assert_vec_eq(&Vec3::new(1., 4., 3.), &m.transform_point(&Vec3::new(1., 0., 0.)));
+        assert_approx_eq!(8.0, m.det3());
+
+        let srt = Mat4::scale_rotate_translate(&Vec3::new(2., 2., 2.), &Vec3::new(0., 0., 90.), &Vec3::new(1., 2., 3.));
+        assert_vec_eq(&Vec3::new(1., 4., 3.), &srt.transform_point(&Vec3::new(1., 0., 0.)));
+        // x first: +y goes to +z, then y turns that +z to +x
+        let r = Mat4::scale_rotate_translate(&Vec3::new(1., 1., 1.), &Vec3::new(90., 90., 0.), &Vec3::default());
+        assert_vec_eq(&Vec3::new(1., 0., 0.), &r.transform_vector(&Vec3::new(0., 1., 0.)));
+    }
+
+    #[test]
+    fn test_inverse() {
+        let m = &Mat4::translation(&Vec3::new(1., -2., 0.5))
+            * &(&Mat4::rotation(&Vec3::new(1., 1., 0.), 30.) * &Mat4::scaling(&Vec3::new(1., 3., 0.5)));
+        let id = &m * &m.inverse().unwrap();
+        for i in 0..4 {
+            for j in 0..4 {
+                assert_approx_eq!(if i == j { 1f64 } else { 0. }, id.get(i, j));
+            }
+        }
+        assert!(Mat4::scaling(&Vec3::new(1., 0., 1.)).inverse().is_none());
+        // a column that is another's multiple
+        let m = Mat4::new([[1., 2., 0., 0.], [1e-3, 2e-3, 0., 0.], [0., 0., 1., 0.], [0., 0., 0., 1f64]]);
+        assert!(m.inverse().is_none());
+        // small scales are fine, however small
+        let tiny = Mat4::<f64>::scaling(&Vec3::new(1e-17, 1e-17, 1e-17)).inverse().unwrap();
+        assert_approx_eq!(1e17f64, tiny.get(0, 0), 1.);
+        assert!(Mat4::<f32>::scaling(&Vec3::new(1e-8, 1e-8, 1e-8)).inverse().is_some());
+    }
+
+    #[test]
+    fn test_normal() {
+        // squashing a 45 degree slope in y makes its normal steeper
+        let m = Mat4::scaling(&Vec3::new(1., 0.5, 1.));
+        let n = m.inverse().unwrap().transform_normal_by_inverse(&Vec3::new(1., 1., 0.));
+        let tangent = m.transform_vector(&Vec3::new(1., -1., 0.));
+        assert_approx_eq!(0f64, n.dot(&tangent));
+    }
+}
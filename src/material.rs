@@ -1,7 +1,27 @@
 use vec3::{ElemT, Vec3};
 use ray::Ray;
+use rng::Sampler;
 use hitable::HitRecord;
 
-pub trait Material<T: ElemT> {
-    fn scatter(&self, r_in: &Ray<T>, rec: &HitRecord<T>) -> Option<(Vec3<T>, Ray<T>)>;
+pub trait Material<T: ElemT>: Send + Sync {
+    fn scatter(&self, r_in: &Ray<T>, rec: &HitRecord<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, Ray<T>)>;
+
+    // Light given off at the hit point, on top of whatever is scattered
+    #[allow(unused_variables)]
+    fn emitted(&self, r_in: &Ray<T>, rec: &HitRecord<T>) -> Vec3<T> {
+        Vec3::default()
+    }
+
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    // For light sampling: BSDF times cosine for light leaving along
+    // `direction`, and the pdf scatter() has of picking that direction. None
+    // for materials that scatter into a few directions only (mirrors, glass),
+    // which direct light sampling can't help with.
+    #[allow(unused_variables)]
+    fn eval(&self, r_in: &Ray<T>, rec: &HitRecord<T>, direction: &Vec3<T>) -> Option<(Vec3<T>, T)> {
+        None
+    }
 }
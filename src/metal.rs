@@ -1,21 +1,27 @@
-extern crate rand;
+use std::sync::Arc;
 
 use vec3::{ElemT, Vec3};
 use ray::Ray;
 use hitable::HitRecord;
 use material::Material;
-use lambertian::random_in_unit_sphere;
+use rng::Sampler;
+use sampling;
+use texture::{ConstantTexture, Texture};
 
 #[derive(Clone)]
 pub struct Metal<T: ElemT> {
-    albedo: Vec3<T>,
+    albedo: Arc<dyn Texture<T>>,
     fuzz: Option<T>
 }
 
 impl<T: ElemT> Metal<T> {
     pub fn new(a: Vec3<T>, fuzz: T) -> Metal<T> {
+        Metal::with_texture(Arc::new(ConstantTexture::new(a)), fuzz)
+    }
+
+    pub fn with_texture(albedo: Arc<dyn Texture<T>>, fuzz: T) -> Metal<T> {
         Metal::<T> {
-            albedo: a,
+            albedo,
             fuzz: if fuzz > T::one() { Some(T::one()) }
                   else if fuzz <= T::zero() { None }
                   else { Some(fuzz) }
@@ -28,10 +34,58 @@ pub fn reflect<T: ElemT>(v: &Vec3<T>, n: &Vec3<T>) -> Vec3<T> {
     v - n*(v.dot(n)*two)
 }
 
+// A point drawn uniformly from inside the unit sphere
+fn random_in_unit_sphere<T: ElemT>(sampler: &mut Sampler) -> Vec3<T> {
+    let t = |x: f64| T::from_f64(x).unwrap();
+    sampling::uniform_ball(t(sampler.next_f64()), t(sampler.next_f64()), t(sampler.next_f64()))
+}
+
 impl<T: ElemT> Material<T> for Metal<T> {
-    fn scatter(&self, r_in: &Ray<T>, rec: &HitRecord<T>) -> Option<(Vec3<T>, Ray<T>)> {
-        let reflected = reflect(&r_in.direction().unit_vector(), &rec.normal);
-        let (attenuation, scattered) = (self.albedo.clone(), Ray::new(rec.p.clone(), &reflected + &random_in_unit_sphere()*self.fuzz.unwrap_or(T::zero())));
+    // The mirror direction, perturbed by up to `fuzz` in any direction
+    fn scatter(&self, r_in: &Ray<T>, rec: &HitRecord<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, Ray<T>)> {
+        let mut reflected = reflect(&r_in.direction().unit_vector(), &rec.normal);
+        if let Some(fuzz) = self.fuzz {
+            reflected += random_in_unit_sphere(sampler) * fuzz;
+        }
+        let (attenuation, scattered) = (self.albedo.value(rec.u, rec.v, &rec.p), Ray::with_time(rec.p.clone(), reflected, r_in.time()));
         if scattered.direction().dot(&rec.normal) > T::zero() { Some((attenuation, scattered)) } else { None }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Metal;
+    use vec3::Vec3;
+    use ray::Ray;
+    use hitable::HitRecord;
+    use material::Material;
+    use rng::Sampler;
+
+    #[test]
+    fn test_fuzz() {
+        let mut sampler = Sampler::new(41);
+        let rec = HitRecord { p: Vec3::new(0., 0., 0.), normal: Vec3::new(0., 0., 1.), ..HitRecord::default() };
+        let r = Ray::new(Vec3::new(-1., 0., 1.), Vec3::new(1., 0., -1.));
+        let mirror = Vec3::new(1., 0., 1.).unit_vector();
+        let metal = Metal::<f64>::new(Vec3::new(0.8, 0.8, 0.8), 0.5);
+        let n = 10000;
+        let mut mean = Vec3::default();
+        for _ in 0..n {
+            // no further from the mirror direction than the fuzz, and never
+            // into the surface
+            let (w, scattered) = metal.scatter(&r, &rec, &mut sampler).unwrap();
+            let d = scattered.direction();
+            assert!((&d - &mirror).length() < 0.5);
+            assert!(d.z() > 0.);
+            assert_approx_eq!(0.8, w.x());
+            mean += d;
+        }
+        // spread evenly around it
+        mean /= n as f64;
+        assert_approx_eq!(0.0, mean.y(), 0.01);
+        assert_approx_eq!(1.0, mean.unit_vector().dot(&mirror), 1e-4);
+
+        let (_, scattered) = Metal::new(Vec3::new(1., 1., 1.), 0.).scatter(&r, &rec, &mut sampler).unwrap();
+        assert_approx_eq!(1.0, scattered.direction().dot(&mirror));
+    }
+}
@@ -0,0 +1,236 @@
+use std::f64::consts::PI;
+
+use vec3::{ElemT, Vec3};
+use rng::Sampler;
+
+// Microfacet models treat a rough surface as lots of tiny mirrors whose
+// normals `h` follow a distribution D. Everything here works in the local
+// frame of the shading normal, which is the z axis, with directions pointing
+// away from the surface.
+
+// Below this alpha a surface is smooth enough to be treated as a perfect
+// mirror, as D gets too spiky to evaluate
+pub const SMOOTH_ALPHA: f64 = 1e-3;
+
+// The GGX (Trowbridge-Reitz) distribution, with roughness alpha_x and
+// alpha_y along the x and y axes of the local frame
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Ggx<T: ElemT> {
+    alpha_x: T,
+    alpha_y: T
+}
+
+impl<T: ElemT> Ggx<T> {
+    pub fn new(alpha: T) -> Ggx<T> {
+        Ggx::anisotropic(alpha, alpha)
+    }
+
+    pub fn anisotropic(alpha_x: T, alpha_y: T) -> Ggx<T> {
+        let min = T::from_f64(1e-4).unwrap();
+        Ggx { alpha_x: alpha_x.max(min), alpha_y: alpha_y.max(min) }
+    }
+
+    // The usual perceptual mapping, alpha = roughness^2
+    pub fn from_roughness(roughness: T) -> Ggx<T> {
+        Ggx::new(roughness * roughness)
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < T::from_f64(SMOOTH_ALPHA).unwrap()
+    }
+
+    // Density of microfacet normals per unit projected area
+    pub fn d(&self, h: &Vec3<T>) -> T {
+        if h.z() <= T::zero() {
+            return T::zero();
+        }
+        let (x, y) = (h.x() / self.alpha_x, h.y() / self.alpha_y);
+        let e = x*x + y*y + h.z()*h.z();
+        T::one() / (T::from_f64(PI).unwrap() * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    // Smith's auxiliary function: the microfacet area seen from `w` that's
+    // hidden behind other microfacets, relative to what's seen
+    fn lambda(&self, w: &Vec3<T>) -> T {
+        let z2 = w.z() * w.z();
+        if z2 == T::zero() {
+            return T::max_value();
+        }
+        let (x, y) = (self.alpha_x * w.x(), self.alpha_y * w.y());
+        let half = T::from_f64(0.5).unwrap();
+        ((T::one() + (x*x + y*y) / z2).sqrt() - T::one()) * half
+    }
+
+    // The fraction of microfacets facing `w` that `w` can see
+    pub fn g1(&self, w: &Vec3<T>) -> T {
+        T::one() / (T::one() + self.lambda(w))
+    }
+
+    // The fraction visible from both `wo` and `wi`, height correlated
+    pub fn g(&self, wo: &Vec3<T>, wi: &Vec3<T>) -> T {
+        T::one() / (T::one() + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // A microfacet normal drawn from those visible from `wo` (which has to
+    // be above the surface), after Heitz, "Sampling the GGX Distribution of
+    // Visible Normals" (2018)
+    pub fn sample_visible(&self, wo: &Vec3<T>, sampler: &mut Sampler) -> Vec3<T> {
+        let t = |x: f64| T::from_f64(x).unwrap();
+        // stretch to the hemisphere configuration
+        let vh = Vec3::new(self.alpha_x * wo.x(), self.alpha_y * wo.y(), wo.z()).unit_vector();
+        let len2 = vh.x()*vh.x() + vh.y()*vh.y();
+        let t1 = if len2 > T::zero() { Vec3::new(-vh.y(), vh.x(), T::zero()) / len2.sqrt() }
+                 else { Vec3::new(T::one(), T::zero(), T::zero()) };
+        let t2 = vh.cross(&t1);
+        // a point on the projected disk, squeezed onto the visible half
+        let r = t(sampler.next_f64().sqrt());
+        let phi = t(2.*PI*sampler.next_f64());
+        let p1 = r * phi.cos();
+        let s = t(0.5) * (T::one() + vh.z());
+        let p2 = (T::one() - s) * (T::one() - p1*p1).sqrt() + s * r * phi.sin();
+        let nh = &t1*p1 + &t2*p2 + &vh*(T::one() - p1*p1 - p2*p2).max(T::zero()).sqrt();
+        // and back
+        Vec3::new(self.alpha_x * nh.x(), self.alpha_y * nh.y(), nh.z().max(T::zero())).unit_vector()
+    }
+
+    // The density sample_visible has of picking `h`
+    pub fn pdf_visible(&self, wo: &Vec3<T>, h: &Vec3<T>) -> T {
+        if wo.z() <= T::zero() {
+            return T::zero();
+        }
+        self.g1(wo) * wo.dot(h).max(T::zero()) * self.d(h) / wo.z()
+    }
+}
+
+// The fraction of unpolarised light reflected at the boundary of a
+// dielectric, where `cos_i` is on the side the light comes from and `eta` is
+// the index on the far side over the near one. 1 for total internal
+// reflection.
+pub fn fresnel_dielectric<T: ElemT>(cos_i: T, eta: T) -> T {
+    let cos_i = cos_i.abs().min(T::one());
+    let sin2_t = (T::one() - cos_i*cos_i) / (eta*eta);
+    if sin2_t >= T::one() {
+        return T::one();
+    }
+    let cos_t = (T::one() - sin2_t).sqrt();
+    let rs = (cos_i - eta*cos_t) / (cos_i + eta*cos_t);
+    let rp = (eta*cos_i - cos_t) / (eta*cos_i + cos_t);
+    (rs*rs + rp*rp) * T::from_f64(0.5).unwrap()
+}
+
+// The same for a conductor, whose index eta + ik is complex, per channel
+pub fn fresnel_conductor<T: ElemT>(cos_i: T, eta: &Vec3<T>, k: &Vec3<T>) -> Vec3<T> {
+    let cos_i = cos_i.abs().min(T::one());
+    let (two, half) = (T::from_f64(2.).unwrap(), T::from_f64(0.5).unwrap());
+    let cos2 = cos_i * cos_i;
+    let sin2 = T::one() - cos2;
+    let mut f = Vec3::default();
+    for c in 0..3 {
+        let (eta2, k2) = (eta[c]*eta[c], k[c]*k[c]);
+        let t0 = eta2 - k2 - sin2;
+        let a2_plus_b2 = (t0*t0 + two*two*eta2*k2).sqrt();
+        let a = ((a2_plus_b2 + t0) * half).max(T::zero()).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let t2 = two * cos_i * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        f[c] = (rs + rp) * half;
+    }
+    f
+}
+
+// `w` mirrored about `h`, both pointing away from the surface
+pub fn reflect<T: ElemT>(w: &Vec3<T>, h: &Vec3<T>) -> Vec3<T> {
+    h * (w.dot(h) * T::from_f64(2.).unwrap()) - w
+}
+
+// `w` refracted through a boundary with normal `h` on its side, into a
+// medium whose index relative to w's is `eta`. None for total internal
+// reflection.
+pub fn refract<T: ElemT>(w: &Vec3<T>, h: &Vec3<T>, eta: T) -> Option<Vec3<T>> {
+    let cos_i = w.dot(h);
+    let sin2_t = (T::one() - cos_i*cos_i).max(T::zero()) / (eta*eta);
+    if sin2_t >= T::one() {
+        return None;
+    }
+    let cos_t = (T::one() - sin2_t).sqrt();
+    Some(w * (-T::one() / eta) + h * (cos_i / eta - cos_t))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::{fresnel_conductor, fresnel_dielectric, refract, reflect, Ggx};
+    use vec3::Vec3;
+    use rng::Sampler;
+
+    // Midpoint sums over the hemisphere in cos theta and phi
+    fn hemisphere<F: FnMut(&Vec3<f64>) -> f64>(mut f: F) -> f64 {
+        let n = 400;
+        let mut sum = 0.;
+        for i in 0..n {
+            let cos = (i as f64 + 0.5) / n as f64;
+            let sin = (1. - cos*cos).sqrt();
+            for j in 0..n {
+                let phi = 2.*PI * (j as f64 + 0.5) / n as f64;
+                sum += f(&Vec3::new(sin*phi.cos(), sin*phi.sin(), cos));
+            }
+        }
+        sum * 2.*PI / (n*n) as f64
+    }
+
+    #[test]
+    fn test_distribution() {
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        for ggx in &[Ggx::new(0.5), Ggx::anisotropic(0.2, 0.6)] {
+            // projected microfacet area is the macro surface's
+            assert_approx_eq!(1.0, hemisphere(|h| ggx.d(h) * h.z()), 0.01);
+            // the visible normals' projected area is as seen from wo
+            assert_approx_eq!(1.0, hemisphere(|h| ggx.pdf_visible(&wo, h)), 0.01);
+        }
+
+        // the samples follow pdf_visible: the average of h.z under it
+        let mut sampler = Sampler::new(9);
+        let ggx = Ggx::new(0.5);
+        let expect = hemisphere(|h| ggx.pdf_visible(&wo, h) * h.z());
+        let n = 100000;
+        let mean = (0..n).map(|_| ggx.sample_visible(&wo, &mut sampler).z()).sum::<f64>() / n as f64;
+        assert_approx_eq!(expect, mean, 0.005);
+        assert_approx_eq!(1.0, Ggx::<f64>::new(0.3).g1(&Vec3::new(0., 0., 1.)));
+    }
+
+    #[test]
+    fn test_fresnel() {
+        // 4% at normal incidence for glass, from either side
+        assert_approx_eq!(0.04, fresnel_dielectric(1f64, 1.5));
+        assert_approx_eq!(0.04, fresnel_dielectric(1f64, 1. / 1.5));
+        assert_approx_eq!(1.0, fresnel_dielectric(0f64, 1.5));
+        // beyond the critical angle going out
+        assert_approx_eq!(1.0, fresnel_dielectric(0.5f64, 1. / 1.5));
+        // with no extinction a conductor is a dielectric
+        let f = fresnel_conductor(0.7, &Vec3::new(1.5, 1.5, 1.5), &Vec3::new(0., 0., 0.));
+        assert_approx_eq!(fresnel_dielectric(0.7f64, 1.5), f.x());
+        // gold is redder than it is blue
+        let gold = fresnel_conductor(1., &Vec3::new(0.143, 0.374, 1.442), &Vec3::new(3.983, 2.385, 1.603));
+        assert!(gold.x() > 0.9 && gold.z() < 0.4);
+    }
+
+    #[test]
+    fn test_reflect_refract() {
+        let h = Vec3::<f64>::new(0., 0., 1.);
+        let w = Vec3::new(0.6, 0., 0.8);
+        let r = reflect(&w, &h);
+        assert_approx_eq!(-0.6, r.x());
+        assert_approx_eq!(0.8, r.z());
+        // Snell's law, and straight through when the index doesn't change
+        let t = refract(&w, &h, 1.5).unwrap();
+        assert_approx_eq!(1.0, t.length());
+        assert_approx_eq!(0.6, -t.x() * 1.5);
+        assert_approx_eq!(-0.8, refract(&w, &h, 1.).unwrap().z());
+        assert!(refract(&w, &h, 0.5).is_none());
+    }
+}
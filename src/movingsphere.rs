@@ -0,0 +1,94 @@
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+use hitable::{HitRecord, Hitable};
+use material::Material;
+use aabb::{Aabb, surrounding_box};
+use sphere::sphere_uv;
+
+// A sphere whose centre moves in a straight line from `center0` at `time0`
+// to `center1` at `time1`. It stays put outside that interval, so the
+// bounding box holds whatever the camera's shutter is.
+pub struct MovingSphere<T: ElemT> {
+    center0: Vec3<T>,
+    center1: Vec3<T>,
+    time0: T,
+    time1: T,
+    radius: T,
+    material: Box<dyn Material<T>>
+}
+
+impl<T: ElemT> MovingSphere<T> {
+    pub fn new(center0: Vec3<T>, center1: Vec3<T>, time0: T, time1: T, radius: T, material: Box<dyn Material<T>>) -> MovingSphere<T> {
+        MovingSphere {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material
+        }
+    }
+
+    pub fn center(&self, time: T) -> Vec3<T> {
+        if self.time1 <= self.time0 {
+            return self.center0.clone();
+        }
+        let s = ((time - self.time0) / (self.time1 - self.time0)).max(T::zero()).min(T::one());
+        &self.center0 + (&self.center1 - &self.center0)*s
+    }
+}
+
+impl<T: ElemT> Hitable<T> for MovingSphere<T> {
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        let center = self.center(r.time());
+        let oc = r.origin() - &center;
+        let a = r.direction().dot(&r.direction());
+        let b = oc.dot(&r.direction());
+        let c = oc.dot(&oc) - self.radius*self.radius;
+        let d = b*b - a*c;
+        if d <= T::zero() {
+            return None;
+        }
+        for &temp in &[(-b - d.sqrt())/a, (-b + d.sqrt())/a] {
+            if temp < t_max && temp > t_min {
+                let p = r.point_at_parameter(temp);
+                let normal = &(&p - &center) / self.radius;
+                let (u, v) = sphere_uv(&normal);
+                return Some(HitRecord { t: temp, u, v, p, normal, mat_opt: Some(&*self.material) });
+            }
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        let r = Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs());
+        let box0 = Aabb::new(&self.center0 - &r, &self.center0 + &r);
+        let box1 = Aabb::new(&self.center1 - &r, &self.center1 + &r);
+        Some(surrounding_box(&box0, &box1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MovingSphere;
+    use vec3::Vec3;
+    use ray::Ray;
+    use hitable::Hitable;
+    use lambertian::Lambertian;
+
+    #[test]
+    fn test_hit_over_time() {
+        let sphere = MovingSphere::new(Vec3::new(0., 0., 0.), Vec3::new(2., 0., 0.), 0., 1., 0.5,
+                                       Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))));
+        let at = |time: f64| Ray::with_time(Vec3::new(1., 0., 5.), Vec3::new(0., 0., -1.), time);
+        assert!(sphere.hit(&at(0.), 0., 100.).is_none());
+        assert_approx_eq!(4.5, sphere.hit(&at(0.5), 0., 100.).unwrap().t);
+        assert!(sphere.hit(&at(1.), 0., 100.).is_none());
+        // held at the end points outside the interval
+        assert_approx_eq!(2.0, sphere.center(3.).x());
+
+        let bbox = sphere.bounding_box().unwrap();
+        assert_approx_eq!(-0.5, bbox.min().x());
+        assert_approx_eq!(2.5, bbox.max().x());
+    }
+}
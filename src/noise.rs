@@ -0,0 +1,227 @@
+use rand::Rng;
+
+use vec3::{ElemT, Vec3};
+use texture::Texture;
+use rng;
+
+const POINT_COUNT: usize = 256;
+
+// Gradient ("improved") Perlin noise. The gradients and permutations come
+// from `seed` alone, so the same seed always gives the same noise.
+pub struct Perlin {
+    ranvec: Vec<[f64; 3]>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>
+}
+
+// Ken Perlin's quintic 6t^5 - 15t^4 + 10t^3, whose first and second
+// derivatives vanish at both ends so the noise has no creases at the lattice
+fn fade(t: f64) -> f64 {
+    t*t*t*(t*(t*6. - 15.) + 10.)
+}
+
+fn permutation<R: Rng>(rng: &mut R) -> Vec<usize> {
+    let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+    for i in (1..POINT_COUNT).rev() {
+        let target = rng.gen_range(0, i + 1);
+        p.swap(i, target);
+    }
+    p
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Perlin {
+        let mut rng = rng::generator(seed);
+        let ranvec = (0..POINT_COUNT).map(|_| {
+            // random unit vectors, by rejection from the cube
+            loop {
+                let v = [2.*rng.next_f64() - 1., 2.*rng.next_f64() - 1., 2.*rng.next_f64() - 1.];
+                let len2 = v[0]*v[0] + v[1]*v[1] + v[2]*v[2];
+                if len2 > 1e-4 && len2 <= 1. {
+                    let len = len2.sqrt();
+                    break [v[0]/len, v[1]/len, v[2]/len];
+                }
+            }
+        }).collect();
+        let perm_x = permutation(&mut rng);
+        let perm_y = permutation(&mut rng);
+        let perm_z = permutation(&mut rng);
+        Perlin { ranvec, perm_x, perm_y, perm_z }
+    }
+
+    // Roughly in [-1, 1], zero at every lattice point
+    pub fn noise(&self, p: [f64; 3]) -> f64 {
+        let (fx, fy, fz) = (p[0].floor(), p[1].floor(), p[2].floor());
+        let (u, v, w) = (p[0] - fx, p[1] - fy, p[2] - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+        let wrap = |x: i64| (x & (POINT_COUNT as i64 - 1)) as usize;
+
+        let (uu, vv, ww) = (fade(u), fade(v), fade(w));
+        let mut accum = 0.;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let g = &self.ranvec[self.perm_x[wrap(i + di)] ^ self.perm_y[wrap(j + dj)] ^ self.perm_z[wrap(k + dk)]];
+                    let (a, b, c) = (di as f64, dj as f64, dk as f64);
+                    let weight = [u - a, v - b, w - c];
+                    accum += (a*uu + (1. - a)*(1. - uu))
+                           * (b*vv + (1. - b)*(1. - vv))
+                           * (c*ww + (1. - c)*(1. - ww))
+                           * (g[0]*weight[0] + g[1]*weight[1] + g[2]*weight[2]);
+                }
+            }
+        }
+        accum
+    }
+
+    // Sum of |noise| over octaves, each twice the frequency and half the
+    // amplitude of the previous one
+    pub fn turbulence(&self, p: [f64; 3], octaves: usize) -> f64 {
+        let (mut accum, mut weight, mut freq) = (0., 1., 1.);
+        for _ in 0..octaves {
+            accum += weight*self.noise([p[0]*freq, p[1]*freq, p[2]*freq]).abs();
+            weight *= 0.5;
+            freq *= 2.;
+        }
+        accum
+    }
+
+    // Fractional Brownian motion: like turbulence but keeping the sign
+    pub fn fbm(&self, p: [f64; 3], octaves: usize) -> f64 {
+        let (mut accum, mut weight, mut freq) = (0., 1., 1.);
+        for _ in 0..octaves {
+            accum += weight*self.noise([p[0]*freq, p[1]*freq, p[2]*freq]);
+            weight *= 0.5;
+            freq *= 2.;
+        }
+        accum
+    }
+}
+
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum NoisePattern {
+    Perlin,
+    Turbulence,
+    Fbm,
+    Marble, // veins along z, distorted by turbulence
+    Wood    // rings around the y axis, distorted by turbulence
+}
+
+impl NoisePattern {
+    pub fn from_name(name: &str) -> Option<NoisePattern> {
+        match name {
+            "perlin" => Some(NoisePattern::Perlin),
+            "turbulence" => Some(NoisePattern::Turbulence),
+            "fbm" => Some(NoisePattern::Fbm),
+            "marble" => Some(NoisePattern::Marble),
+            "wood" => Some(NoisePattern::Wood),
+            _ => None
+        }
+    }
+}
+
+// Solid texture blending between two colours by a noise pattern in [0, 1]
+pub struct NoiseTexture<T: ElemT> {
+    noise: Perlin,
+    pattern: NoisePattern,
+    scale: f64,
+    octaves: usize,
+    low: Vec3<T>,
+    high: Vec3<T>
+}
+
+impl<T: ElemT> NoiseTexture<T> {
+    pub fn new(seed: u64, pattern: NoisePattern, scale: T, octaves: usize, low: Vec3<T>, high: Vec3<T>) -> NoiseTexture<T> {
+        NoiseTexture {
+            noise: Perlin::new(seed),
+            scale: scale.to_f64().unwrap(),
+            pattern,
+            octaves,
+            low,
+            high
+        }
+    }
+
+    fn pattern_value(&self, p: &Vec3<T>) -> f64 {
+        let p = [p.x().to_f64().unwrap()*self.scale,
+                 p.y().to_f64().unwrap()*self.scale,
+                 p.z().to_f64().unwrap()*self.scale];
+        let value = match self.pattern {
+            NoisePattern::Perlin => 0.5*(1. + self.noise.noise(p)),
+            NoisePattern::Turbulence => self.noise.turbulence(p, self.octaves),
+            NoisePattern::Fbm => 0.5*(1. + self.noise.fbm(p, self.octaves)),
+            NoisePattern::Marble => 0.5*(1. + (p[2] + 10.*self.noise.turbulence(p, self.octaves)).sin()),
+            NoisePattern::Wood => {
+                let rings = 4.*(p[0]*p[0] + p[2]*p[2]).sqrt() + 2.*self.noise.turbulence(p, self.octaves);
+                rings - rings.floor()
+            }
+        };
+        value.clamp(0., 1.)
+    }
+}
+
+impl<T: ElemT> Texture<T> for NoiseTexture<T> {
+    #[allow(unused_variables)]
+    fn value(&self, u: T, v: T, p: &Vec3<T>) -> Vec3<T> {
+        let t = T::from_f64(self.pattern_value(p)).unwrap();
+        &self.low*(T::one() - t) + &self.high*t
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fade, NoisePattern, NoiseTexture, Perlin};
+    use vec3::Vec3;
+    use texture::Texture;
+
+    #[test]
+    fn test_deterministic() {
+        let (a, b, c) = (Perlin::new(7), Perlin::new(7), Perlin::new(8));
+        let p = [1.3, -2.7, 0.45];
+        assert_eq!(a.noise(p), b.noise(p));
+        assert_eq!(a.fbm(p, 5), b.fbm(p, 5));
+        assert!(a.noise(p) != c.noise(p));
+    }
+
+    #[test]
+    fn test_fade() {
+        assert_eq!(0., fade(0.));
+        assert_eq!(1., fade(1.));
+        assert_approx_eq!(0.5, fade(0.5));
+        // flat to second order at the ends, where the cubic is only flat to first
+        assert!(fade(0.01) < 1e-5 && 1. - fade(0.99) < 1e-5);
+    }
+
+    #[test]
+    fn test_noise_range() {
+        let perlin = Perlin::new(0);
+        // zero on the lattice
+        assert_eq!(0., perlin.noise([3., -1., 5.]));
+        let mut nonzero = 0;
+        for i in 0..1000 {
+            let x = i as f64 * 0.0173;
+            let n = perlin.noise([x, 2.*x + 0.3, 0.7 - x]);
+            assert!(n.abs() <= 1.1, "{}", n);
+            if n.abs() > 1e-3 { nonzero += 1; }
+        }
+        assert!(nonzero > 900);
+    }
+
+    #[test]
+    fn test_patterns_in_range() {
+        let (black, white) = (Vec3::new(0., 0., 0.), Vec3::new(1., 1., 1.));
+        for &pattern in &[NoisePattern::Perlin, NoisePattern::Turbulence, NoisePattern::Fbm,
+                          NoisePattern::Marble, NoisePattern::Wood] {
+            let tex = NoiseTexture::new(1, pattern, 4., 7, black.clone(), white.clone());
+            for i in 0..100 {
+                let x = i as f64 * 0.137;
+                let c = tex.value(0., 0., &Vec3::new(x, -x, 0.5*x));
+                assert!(c.x() >= 0. && c.x() <= 1.);
+            }
+        }
+    }
+}
@@ -0,0 +1,502 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use vec3::{ElemT, Vec3};
+use hitable::Hitable;
+use hitablelist::HitableList;
+use material::Material;
+use lambertian::Lambertian;
+use metal::Metal;
+use dielectric::Dielectric;
+use diffuselight::DiffuseLight;
+use principled::{self, Principled};
+use texture::ConstantTexture;
+use trianglemesh::TriangleMesh;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(String, io::Error),
+    Parse { file: String, line: usize, msg: String }
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ObjError::Io(ref file, ref err) => write!(f, "{}: {}", file, err),
+            ObjError::Parse { ref file, line, ref msg } => write!(f, "{}:{}: {}", file, line, msg)
+        }
+    }
+}
+
+fn parse_error<R>(file: &str, line: usize, msg: String) -> Result<R, ObjError> {
+    Err(ObjError::Parse { file: file.to_string(), line, msg })
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, ObjError> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| ObjError::Io(path.display().to_string(), e))
+}
+
+// Parses exactly `N` floats out of the rest of a line
+fn parse_floats<'a, I, const N: usize>(args: I, file: &str, line: usize, key: &str) -> Result<[f64; N], ObjError>
+    where I: Iterator<Item = &'a str> {
+    let args: Vec<&str> = args.collect();
+    if args.len() < N {
+        return parse_error(file, line, format!("'{}' expects {} numbers, found {}", key, N, args.len()));
+    }
+    let mut ret = [0.; N];
+    for (i, a) in args.iter().take(N).enumerate() {
+        ret[i] = match a.parse::<f64>() {
+            Ok(x) => x,
+            Err(_) => return parse_error(file, line, format!("'{}' has invalid number '{}'", key, a))
+        };
+    }
+    Ok(ret)
+}
+
+/////////
+// MTL //
+/////////
+
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum MaterialKind {
+    Diffuse,
+    Glossy,
+    Glass,
+    Emissive,
+    Principled
+}
+
+// The physically based extension to MTL written by Blender and others
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct PbrParams {
+    pub roughness: f64,
+    pub metallic: f64,
+    pub sheen: f64,
+    pub clearcoat: f64,
+    pub clearcoat_roughness: f64,
+    pub anisotropic: f64
+}
+
+impl Default for PbrParams {
+    fn default() -> PbrParams {
+        PbrParams {
+            roughness: 0.5,
+            metallic: 0.,
+            sheen: 0.,
+            clearcoat: 0.,
+            clearcoat_roughness: 0.1,
+            anisotropic: 0.
+        }
+    }
+}
+
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct MtlMaterial {
+    pub kd: [f64; 3],
+    pub ks: [f64; 3],
+    pub ke: [f64; 3],
+    pub ns: f64,
+    pub ni: f64,
+    pub d: f64,
+    // set by any of Pr, Pm, Ps, Pc, Pcr or aniso
+    pub pbr: Option<PbrParams>
+}
+
+impl Default for MtlMaterial {
+    fn default() -> MtlMaterial {
+        MtlMaterial {
+            kd: [0.5, 0.5, 0.5],
+            ks: [0., 0., 0.],
+            ke: [0., 0., 0.],
+            ns: 0.,
+            ni: 1.5,
+            d: 1.,
+            pbr: None
+        }
+    }
+}
+
+fn luminance(c: &[f64; 3]) -> f64 {
+    0.2126*c[0] + 0.7152*c[1] + 0.0722*c[2]
+}
+
+impl MtlMaterial {
+    // MTL describes Phong-style materials, so we pick whichever of our
+    // materials is closest: anything see-through is glass, anything whose
+    // specular colour outweighs its diffuse colour is metal, the rest diffuse.
+    // Anything with an emissive colour (Ke) is a light, whatever else it has,
+    // and anything with PBR parameters is Principled.
+    pub fn kind(&self) -> MaterialKind {
+        if luminance(&self.ke) > 0. {
+            MaterialKind::Emissive
+        } else if self.pbr.is_some() {
+            MaterialKind::Principled
+        } else if self.d < 1. {
+            MaterialKind::Glass
+        } else if luminance(&self.ks) > luminance(&self.kd) {
+            MaterialKind::Glossy
+        } else {
+            MaterialKind::Diffuse
+        }
+    }
+
+    // Maps the Phong exponent onto Metal's fuzz; a higher exponent is a
+    // tighter highlight and therefore less fuzz.
+    pub fn fuzz(&self) -> f64 {
+        (2. / (self.ns.max(0.) + 2.)).sqrt()
+    }
+
+    pub fn to_material<T: ElemT>(&self) -> Box<dyn Material<T>> {
+        let color = |c: &[f64; 3]| Vec3::new(T::from_f64(c[0]).unwrap(),
+                                             T::from_f64(c[1]).unwrap(),
+                                             T::from_f64(c[2]).unwrap());
+        match self.kind() {
+            MaterialKind::Glass => Box::new(Dielectric::new(T::from_f64(self.ni).unwrap())),
+            MaterialKind::Glossy => Box::new(Metal::new(color(&self.ks), T::from_f64(self.fuzz()).unwrap())),
+            MaterialKind::Diffuse => Box::new(Lambertian::new(color(&self.kd))),
+            MaterialKind::Emissive => Box::new(DiffuseLight::new(color(&self.ke))),
+            MaterialKind::Principled => {
+                let pbr = self.pbr.clone().unwrap_or_default();
+                let grey = |x: f64| principled::grey(T::from_f64(x.clamp(0., 1.)).unwrap());
+                let mut m = Principled::new(Arc::new(ConstantTexture::new(color(&self.kd))));
+                m.metallic = grey(pbr.metallic);
+                m.roughness = grey(pbr.roughness);
+                // Ks holds the specular level
+                m.specular = grey(luminance(&self.ks));
+                m.sheen = grey(pbr.sheen);
+                m.clearcoat = grey(pbr.clearcoat);
+                m.clearcoat_roughness = grey(pbr.clearcoat_roughness);
+                m.anisotropic = grey(pbr.anisotropic);
+                // dissolve becomes transmission
+                m.transmission = grey(1. - self.d);
+                m.ior = T::from_f64(self.ni).unwrap();
+                Box::new(m)
+            }
+        }
+    }
+}
+
+pub fn parse_mtl<R: BufRead>(reader: R, file: &str) -> Result<HashMap<String, MtlMaterial>, ObjError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (i, line) in reader.lines().enumerate() {
+        let lineno = i + 1;
+        let line = line.map_err(|e| ObjError::Io(file.to_string(), e))?;
+        let mut args = line.split_whitespace();
+        let key = match args.next() {
+            Some(k) if !k.starts_with('#') => k,
+            _ => continue
+        };
+
+        if key == "newmtl" {
+            let name = match args.next() {
+                Some(n) => n.to_string(),
+                None => return parse_error(file, lineno, "'newmtl' expects a name".to_string())
+            };
+            if let Some((name, mtl)) = current.take() {
+                materials.insert(name, mtl);
+            }
+            current = Some((name, MtlMaterial::default()));
+            continue;
+        }
+
+        let mtl = match current {
+            Some((_, ref mut mtl)) => mtl,
+            None => return parse_error(file, lineno, format!("'{}' before any 'newmtl'", key))
+        };
+        match key {
+            "Kd" => mtl.kd = parse_floats::<_, 3>(args, file, lineno, key)?,
+            "Ks" => mtl.ks = parse_floats::<_, 3>(args, file, lineno, key)?,
+            "Ke" => mtl.ke = parse_floats::<_, 3>(args, file, lineno, key)?,
+            "Ns" => mtl.ns = parse_floats::<_, 1>(args, file, lineno, key)?[0],
+            "Ni" => mtl.ni = parse_floats::<_, 1>(args, file, lineno, key)?[0],
+            "d" => mtl.d = parse_floats::<_, 1>(args, file, lineno, key)?[0],
+            "Tr" => mtl.d = 1. - parse_floats::<_, 1>(args, file, lineno, key)?[0],
+            "Pr" | "Pm" | "Ps" | "Pc" | "Pcr" | "aniso" => {
+                let x = parse_floats::<_, 1>(args, file, lineno, key)?[0];
+                let pbr = mtl.pbr.get_or_insert_with(PbrParams::default);
+                match key {
+                    "Pr" => pbr.roughness = x,
+                    "Pm" => pbr.metallic = x,
+                    "Ps" => pbr.sheen = x,
+                    "Pc" => pbr.clearcoat = x,
+                    "Pcr" => pbr.clearcoat_roughness = x,
+                    _ => pbr.anisotropic = x
+                }
+            }
+            _ => {} // everything else (Ka, illum, maps, ...) is ignored
+        }
+    }
+    if let Some((name, mtl)) = current.take() {
+        materials.insert(name, mtl);
+    }
+    Ok(materials)
+}
+
+/////////
+// OBJ //
+/////////
+
+// (position, uv, normal) indices of one face corner
+type VertexRef = (usize, Option<usize>, Option<usize>);
+
+// A run of faces sharing one material, built up into a TriangleMesh
+#[derive(Default)]
+struct Group {
+    material: Option<String>,
+    positions: Vec<Vec3<f64>>,
+    normals: Vec<Option<Vec3<f64>>>,
+    uvs: Vec<Option<(f64, f64)>>,
+    indices: Vec<[usize; 3]>,
+    // corner -> mesh vertex, so shared corners become shared vertices
+    vertex_map: HashMap<VertexRef, usize>
+}
+
+// Resolves a 1-based (or negative, relative) OBJ index into a 0-based one
+fn resolve_index(s: &str, count: usize, file: &str, line: usize) -> Result<usize, ObjError> {
+    let idx = match s.parse::<i64>() {
+        Ok(i) => i,
+        Err(_) => return parse_error(file, line, format!("invalid index '{}'", s))
+    };
+    let resolved = if idx > 0 { idx - 1 } else { count as i64 + idx };
+    if idx == 0 || resolved < 0 || resolved >= count as i64 {
+        return parse_error(file, line, format!("index {} out of range (have {})", idx, count));
+    }
+    Ok(resolved as usize)
+}
+
+struct ObjParser<'a> {
+    file: &'a str,
+    positions: Vec<Vec3<f64>>,
+    normals: Vec<Vec3<f64>>,
+    uvs: Vec<(f64, f64)>,
+    groups: Vec<Group>
+}
+
+impl<'a> ObjParser<'a> {
+    fn parse_vertex_ref(&self, s: &str, line: usize) -> Result<VertexRef, ObjError> {
+        let mut parts = s.split('/');
+        let v = resolve_index(parts.next().unwrap_or(""), self.positions.len(), self.file, line)?;
+        let vt = match parts.next() {
+            Some("") | None => None,
+            Some(p) => Some(resolve_index(p, self.uvs.len(), self.file, line)?)
+        };
+        let vn = match parts.next() {
+            Some("") | None => None,
+            Some(p) => Some(resolve_index(p, self.normals.len(), self.file, line)?)
+        };
+        if parts.next().is_some() {
+            return parse_error(self.file, line, format!("invalid face vertex '{}'", s));
+        }
+        Ok((v, vt, vn))
+    }
+
+    fn mesh_vertex(&mut self, key: VertexRef) -> usize {
+        let group = self.groups.last_mut().unwrap();
+        if let Some(&idx) = group.vertex_map.get(&key) {
+            return idx;
+        }
+        let (v, vt, vn) = key;
+        let (uvs, normals) = (&self.uvs, &self.normals);
+        let idx = group.positions.len();
+        group.positions.push(self.positions[v].clone());
+        group.uvs.push(vt.map(|i| uvs[i]));
+        group.normals.push(vn.map(|i| normals[i].clone()));
+        group.vertex_map.insert(key, idx);
+        idx
+    }
+
+    fn add_face<'b, I>(&mut self, args: I, line: usize) -> Result<(), ObjError>
+        where I: Iterator<Item = &'b str> {
+        let refs = args.map(|a| self.parse_vertex_ref(a, line)).collect::<Result<Vec<_>, _>>()?;
+        if refs.len() < 3 {
+            return parse_error(self.file, line, format!("face needs at least 3 vertices, found {}", refs.len()));
+        }
+        let verts: Vec<usize> = refs.into_iter().map(|r| self.mesh_vertex(r)).collect();
+        // triangulate polygons as a fan around the first vertex
+        let group = self.groups.last_mut().unwrap();
+        for k in 1..verts.len()-1 {
+            group.indices.push([verts[0], verts[k], verts[k+1]]);
+        }
+        Ok(())
+    }
+
+    fn use_material(&mut self, name: Option<String>) {
+        if self.groups.last().is_some_and(|g| g.indices.is_empty()) {
+            self.groups.pop();
+        }
+        self.groups.push(Group { material: name, ..Group::default() });
+    }
+}
+
+// Parses OBJ data into meshes. `mtllib` paths are resolved against `dir`.
+pub fn parse_obj<T: ElemT, R: BufRead>(reader: R, file: &str, dir: &Path) -> Result<HitableList<T>, ObjError> {
+    let mut parser = ObjParser {
+        file,
+        positions: Vec::new(),
+        normals: Vec::new(),
+        uvs: Vec::new(),
+        groups: vec![Group::default()]
+    };
+    let mut materials = HashMap::new();
+
+    for (i, line) in reader.lines().enumerate() {
+        let lineno = i + 1;
+        let line = line.map_err(|e| ObjError::Io(file.to_string(), e))?;
+        let mut args = line.split_whitespace();
+        let key = match args.next() {
+            Some(k) if !k.starts_with('#') => k,
+            _ => continue
+        };
+
+        match key {
+            "v" => {
+                let p = parse_floats::<_, 3>(args, file, lineno, key)?;
+                parser.positions.push(Vec3::new(p[0], p[1], p[2]));
+            }
+            "vn" => {
+                let n = parse_floats::<_, 3>(args, file, lineno, key)?;
+                let n = Vec3::new(n[0], n[1], n[2]);
+                if n.squared_length() == 0. {
+                    return parse_error(file, lineno, "'vn' can't be all zeroes".to_string());
+                }
+                parser.normals.push(n.unit_vector());
+            }
+            "vt" => {
+                // v (and the w we don't use) can be left out
+                let args: Vec<&str> = args.collect();
+                let u = parse_floats::<_, 1>(args.iter().cloned(), file, lineno, key)?[0];
+                let v = if args.len() > 1 { parse_floats::<_, 1>(args[1..].iter().cloned(), file, lineno, key)?[0] } else { 0. };
+                parser.uvs.push((u, v));
+            }
+            "f" => parser.add_face(args, lineno)?,
+            "usemtl" => {
+                let name = match args.next() {
+                    Some(n) => n.to_string(),
+                    None => return parse_error(file, lineno, "'usemtl' expects a name".to_string())
+                };
+                if !materials.contains_key(&name) {
+                    return parse_error(file, lineno, format!("unknown material '{}'", name));
+                }
+                parser.use_material(Some(name));
+            }
+            "mtllib" => {
+                for lib in args {
+                    let path = dir.join(lib);
+                    let name = path.display().to_string();
+                    materials.extend(parse_mtl(open(&path)?, &name)?);
+                }
+            }
+            _ => {} // o, g, s, l, ... don't affect the geometry we build
+        }
+    }
+
+    let mut list = Vec::<Box<dyn Hitable<T>>>::new();
+    for group in parser.groups {
+        if group.indices.is_empty() {
+            continue;
+        }
+        let mtl = group.material.as_ref()
+            .map(|m| materials[m].clone())
+            .unwrap_or_default();
+        let conv = |v: &Vec3<f64>| Vec3::new(T::from_f64(v.x()).unwrap(),
+                                             T::from_f64(v.y()).unwrap(),
+                                             T::from_f64(v.z()).unwrap());
+        // a mesh only gets normals/uvs if every one of its vertices has them
+        let normals = group.normals.iter()
+            .map(|n| n.as_ref().map(&conv))
+            .collect::<Option<Vec<_>>>();
+        let uvs = group.uvs.iter()
+            .map(|uv| uv.map(|(u, v)| (T::from_f64(u).unwrap(), T::from_f64(v).unwrap())))
+            .collect::<Option<Vec<_>>>();
+        let positions = group.positions.iter().map(&conv).collect();
+        list.push(Box::new(TriangleMesh::new(positions, group.indices, normals, uvs, mtl.to_material())));
+    }
+    Ok(HitableList::new(list))
+}
+
+pub fn load_obj<T: ElemT>(path: &Path) -> Result<HitableList<T>, ObjError> {
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    parse_obj(open(path)?, &path.display().to_string(), dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_mtl, parse_obj, MaterialKind, ObjError};
+    use std::io::Cursor;
+    use std::path::Path;
+    use vec3::Vec3;
+    use ray::Ray;
+    use hitable::Hitable;
+    use hitablelist::HitableList;
+
+    fn parse(src: &str) -> Result<HitableList<f64>, ObjError> {
+        parse_obj(Cursor::new(src), "test.obj", Path::new(""))
+    }
+
+    #[test]
+    fn test_quad() {
+        let world = parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1 4//1\n").unwrap();
+        let rec = world.hit(&Ray::new(Vec3::new(0.25, 0.75, 1.), Vec3::new(0., 0., -1.)), 0., 100.).unwrap();
+        assert_approx_eq!(1.0, rec.t);
+        assert_approx_eq!(1.0, rec.normal.z());
+        // negative indices are relative to the end of the vertex list
+        assert!(parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nf -3 -2 -1\n").is_ok());
+        // texture coordinates with one, two or three components
+        assert!(parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nvt 0\nvt 1 0\nvt 1 1 0\nf 1/1 2/2 3/3\n").is_ok());
+    }
+
+    #[test]
+    fn test_errors_have_line_numbers() {
+        let check = |src: &str, expected_line: usize| {
+            match parse(src) {
+                Err(ObjError::Parse { line, .. }) => assert_eq!(expected_line, line),
+                _ => panic!("expected a parse error")
+            }
+        };
+        check("v 0 0 0\nv 1 0 zero\n", 2);
+        check("v 0 0 0\nv 1 0 0\nv 1 1 0\n\nf 1 2 4\n", 5);
+        check("v 0 0 0\nv 1 0 0\nf 1 2\n", 3);
+        check("# comment\nusemtl missing\n", 2);
+        check("vn 0 0 1\nvn 0 0 0\n", 2);
+        check("vt 0.5\nvt\n", 2);
+    }
+
+    #[test]
+    fn test_mtl() {
+        let src = "newmtl red\nKd 0.8 0.1 0.1\nKs 0.1 0.1 0.1\n\
+                   newmtl chrome\nKd 0.1 0.1 0.1\nKs 0.9 0.9 0.9\nNs 1000\n\
+                   newmtl glass\nNi 1.45\nd 0.1\n\
+                   newmtl lamp\nKd 0.8 0.8 0.8\nKe 15 15 15\n\
+                   newmtl brushed\nKd 0.9 0.6 0.2\nKs 0.5 0.5 0.5\nPm 1\nPr 0.3\naniso 0.5\n";
+        let mtls = parse_mtl(Cursor::new(src), "test.mtl").unwrap();
+        assert_eq!(MaterialKind::Diffuse, mtls["red"].kind());
+        assert_eq!(MaterialKind::Glossy, mtls["chrome"].kind());
+        assert!(mtls["chrome"].fuzz() < 0.1);
+        assert_eq!(MaterialKind::Glass, mtls["glass"].kind());
+        assert_approx_eq!(1.45, mtls["glass"].ni);
+        assert_eq!(MaterialKind::Emissive, mtls["lamp"].kind());
+        assert_eq!(MaterialKind::Principled, mtls["brushed"].kind());
+        let pbr = mtls["brushed"].pbr.clone().unwrap();
+        assert_approx_eq!(1.0, pbr.metallic);
+        assert_approx_eq!(0.3, pbr.roughness);
+        assert_approx_eq!(0.1, pbr.clearcoat_roughness);
+
+        match parse_mtl(Cursor::new("Kd 1 1 1\n"), "test.mtl") {
+            Err(ObjError::Parse { line: 1, .. }) => {},
+            _ => panic!("expected a parse error")
+        }
+    }
+}
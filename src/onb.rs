@@ -0,0 +1,71 @@
+use vec3::{ElemT, Vec3};
+
+// Orthonormal basis with `w` along a given direction, for turning directions
+// sampled around the z axis into world space
+pub struct Onb<T: ElemT> {
+    u: Vec3<T>,
+    v: Vec3<T>,
+    w: Vec3<T>
+}
+
+impl<T: ElemT> Onb<T> {
+    pub fn from_w(n: &Vec3<T>) -> Onb<T> {
+        let w = n.unit_vector();
+        let a = if w.x().abs() > T::from_f64(0.9).unwrap() { Vec3::new(T::zero(), T::one(), T::zero()) }
+                else { Vec3::new(T::one(), T::zero(), T::zero()) };
+        let v = w.cross(&a).unit_vector();
+        let u = w.cross(&v);
+        Onb { u, v, w }
+    }
+
+    // With `u` along `tangent`, or as near as it can be at right angles to `n`
+    pub fn with_tangent(n: &Vec3<T>, tangent: &Vec3<T>) -> Onb<T> {
+        let w = n.unit_vector();
+        let v = w.cross(tangent);
+        if v.squared_length() == T::zero() {
+            return Onb::from_w(n);
+        }
+        let v = v.unit_vector();
+        let u = v.cross(&w);
+        Onb { u, v, w }
+    }
+
+    pub fn u(&self) -> Vec3<T> { self.u.clone() }
+    pub fn v(&self) -> Vec3<T> { self.v.clone() }
+
+    pub fn local(&self, x: T, y: T, z: T) -> Vec3<T> {
+        &self.u*x + &self.v*y + &self.w*z
+    }
+
+    // The other way, from world space into the basis
+    pub fn to_local(&self, a: &Vec3<T>) -> Vec3<T> {
+        Vec3::new(a.dot(&self.u), a.dot(&self.v), a.dot(&self.w))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Onb;
+    use vec3::Vec3;
+
+    #[test]
+    fn test_orthonormal() {
+        for n in &[Vec3::<f64>::new(0., 0., 1.), Vec3::new(1., 0., 0.), Vec3::new(-1., 2., 0.5)] {
+            let onb = Onb::from_w(n);
+            let (u, v, w) = (onb.local(1., 0., 0.), onb.local(0., 1., 0.), onb.local(0., 0., 1.));
+            assert_approx_eq!(1.0, u.length());
+            assert_approx_eq!(1.0, v.length());
+            assert_approx_eq!(0.0, u.dot(&v));
+            assert_approx_eq!(0.0, u.dot(&w));
+            assert_approx_eq!(0.0, v.dot(&w));
+            assert_approx_eq!(1.0, w.dot(&n.unit_vector()));
+            let back = onb.to_local(&onb.local(0.3, -0.2, 0.9));
+            assert_approx_eq!(0.3, back.x());
+            assert_approx_eq!(-0.2, back.y());
+            assert_approx_eq!(0.9, back.z());
+        }
+        let onb = Onb::with_tangent(&Vec3::<f64>::new(0., 0., 2.), &Vec3::new(1., 0., 1.));
+        assert_approx_eq!(1.0, onb.u().x());
+        assert_approx_eq!(1.0, onb.v().y());
+    }
+}
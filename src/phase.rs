@@ -0,0 +1,123 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+use rng::Sampler;
+use hitable::HitRecord;
+use material::Material;
+use onb::Onb;
+use texture::{ConstantTexture, Texture};
+
+// Phase functions say how light scattering inside a medium spreads out. They
+// take the place of a BSDF: the density of leaving along `wo` for light
+// travelling along `wi`, over the whole sphere. There's no cosine term, so
+// importance sampling them leaves just the albedo as the weight.
+
+// Henyey-Greenstein: g in (-1, 1) is the mean cosine of the scattering
+// angle, positive for forward scattering, negative for back scattering and
+// zero for isotropic
+pub fn hg_phase<T: ElemT>(g: T, cos_theta: T) -> T {
+    let four_pi = T::from_f64(4.*PI).unwrap();
+    let denom = T::one() + g*g - (g + g)*cos_theta;
+    (T::one() - g*g) / (four_pi * denom * denom.sqrt())
+}
+
+// A direction around `wi` (which need not be unit length) drawn from the
+// Henyey-Greenstein phase function
+pub fn sample_hg<T: ElemT>(g: T, wi: &Vec3<T>, sampler: &mut Sampler) -> Vec3<T> {
+    let xi = T::from_f64(sampler.next_f64()).unwrap();
+    let two = T::from_f64(2.).unwrap();
+    let cos_theta = if g.abs() < T::from_f64(1e-3).unwrap() {
+        T::one() - two*xi
+    } else {
+        let s = (T::one() - g*g) / (T::one() - g + two*g*xi);
+        ((T::one() + g*g - s*s) / (two*g)).max(-T::one()).min(T::one())
+    };
+    let sin_theta = (T::one() - cos_theta*cos_theta).max(T::zero()).sqrt();
+    let phi = T::from_f64(2.*PI*sampler.next_f64()).unwrap();
+    Onb::from_w(wi).local(sin_theta*phi.cos(), sin_theta*phi.sin(), cos_theta)
+}
+
+// The material of a medium's particles, scattering with a Henyey-Greenstein
+// phase function and keeping `albedo` of the light
+#[derive(Clone)]
+pub struct HenyeyGreenstein<T: ElemT> {
+    albedo: Arc<dyn Texture<T>>,
+    g: T
+}
+
+impl<T: ElemT> HenyeyGreenstein<T> {
+    pub fn new(albedo: Vec3<T>, g: T) -> HenyeyGreenstein<T> {
+        HenyeyGreenstein::with_texture(Arc::new(ConstantTexture::new(albedo)), g)
+    }
+
+    pub fn with_texture(albedo: Arc<dyn Texture<T>>, g: T) -> HenyeyGreenstein<T> {
+        HenyeyGreenstein { albedo, g }
+    }
+}
+
+impl<T: ElemT> Material<T> for HenyeyGreenstein<T> {
+    fn scatter(&self, r_in: &Ray<T>, rec: &HitRecord<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, Ray<T>)> {
+        let direction = sample_hg(self.g, &r_in.direction(), sampler);
+        Some((self.albedo.value(rec.u, rec.v, &rec.p), Ray::with_time(rec.p.clone(), direction, r_in.time())))
+    }
+
+    fn eval(&self, r_in: &Ray<T>, rec: &HitRecord<T>, direction: &Vec3<T>) -> Option<(Vec3<T>, T)> {
+        let pdf = hg_phase(self.g, r_in.direction().unit_vector().dot(&direction.unit_vector()));
+        Some((self.albedo.value(rec.u, rec.v, &rec.p)*pdf, pdf))
+    }
+}
+
+// Scatters equally in every direction, as in the book
+#[derive(Clone)]
+pub struct Isotropic<T: ElemT> {
+    phase: HenyeyGreenstein<T>
+}
+
+impl<T: ElemT> Isotropic<T> {
+    pub fn with_texture(albedo: Arc<dyn Texture<T>>) -> Isotropic<T> {
+        Isotropic { phase: HenyeyGreenstein::with_texture(albedo, T::zero()) }
+    }
+}
+
+impl<T: ElemT> Material<T> for Isotropic<T> {
+    fn scatter(&self, r_in: &Ray<T>, rec: &HitRecord<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, Ray<T>)> {
+        self.phase.scatter(r_in, rec, sampler)
+    }
+
+    fn eval(&self, r_in: &Ray<T>, rec: &HitRecord<T>, direction: &Vec3<T>) -> Option<(Vec3<T>, T)> {
+        self.phase.eval(r_in, rec, direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::{hg_phase, sample_hg};
+    use vec3::Vec3;
+    use rng::Sampler;
+
+    #[test]
+    fn test_hg() {
+        assert_approx_eq!(1. / (4.*PI), hg_phase(0., 0.3));
+        let mut sampler = Sampler::new(3);
+        let wi = Vec3::new(0., 0., 2.);
+        for &g in &[-0.7, 0., 0.5, 0.9] {
+            // integrates to one over the sphere
+            let n = 200000;
+            let mut integral = 0.;
+            for i in 0..n {
+                let mu = -1. + 2.*(i as f64 + 0.5) / n as f64;
+                integral += hg_phase(g, mu) * 2.*PI * 2. / n as f64;
+            }
+            assert_approx_eq!(1.0, integral, 1e-3);
+
+            // and the samples have g as their mean cosine
+            let m = 100000;
+            let mean: f64 = (0..m).map(|_| sample_hg(g, &wi, &mut sampler).unit_vector().z()).sum::<f64>() / m as f64;
+            assert!((mean - g).abs() < 0.01, "g {} mean {}", g, mean);
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use std::f64::consts::PI;
+
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+use hitable::{area_to_solid_angle, HitRecord, Hitable};
+use material::Material;
+use aabb::Aabb;
+use onb::Onb;
+use rect::{intersect_plane, padded_box};
+use rng::Sampler;
+use sampling;
+
+// An infinite plane through `point`. It has no bounding box, so it sits
+// outside the BVH; (u, v) are distances across it from `point`, so textures
+// on it repeat with their own scale.
+pub struct Plane<T: ElemT> {
+    point: Vec3<T>,
+    normal: Vec3<T>,
+    frame: Onb<T>,
+    material: Box<dyn Material<T>>
+}
+
+impl<T: ElemT> Plane<T> {
+    pub fn new(point: Vec3<T>, normal: &Vec3<T>, mat: Box<dyn Material<T>>) -> Plane<T> {
+        Plane {
+            point,
+            normal: normal.unit_vector(),
+            frame: Onb::from_w(normal),
+            material: mat
+        }
+    }
+}
+
+impl<T: ElemT> Hitable<T> for Plane<T> {
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        let t = intersect_plane(r, &self.point, &self.normal, t_min, t_max)?;
+        let p = r.point_at_parameter(t);
+        let d = &p - &self.point;
+        Some(HitRecord {
+            t,
+            u: d.dot(&self.frame.u()),
+            v: d.dot(&self.frame.v()),
+            p,
+            normal: self.normal.clone(),
+            mat_opt: Some(&*self.material)
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        None
+    }
+}
+
+// A round flat disk. u goes once around the centre and v out from it to the
+// rim.
+pub struct Disk<T: ElemT> {
+    center: Vec3<T>,
+    normal: Vec3<T>,
+    radius: T,
+    frame: Onb<T>,
+    material: Box<dyn Material<T>>
+}
+
+impl<T: ElemT> Disk<T> {
+    pub fn new(center: Vec3<T>, normal: &Vec3<T>, radius: T, mat: Box<dyn Material<T>>) -> Disk<T> {
+        Disk {
+            center,
+            normal: normal.unit_vector(),
+            radius,
+            frame: Onb::from_w(normal),
+            material: mat
+        }
+    }
+
+    // Density over the disk's area of the points sample() picks, the unit
+    // disk's scaled up to the radius
+    fn area_pdf(&self) -> T {
+        sampling::concentric_disk_pdf::<T>() / (self.radius * self.radius)
+    }
+
+    fn intersect(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<T> {
+        let t = intersect_plane(r, &self.center, &self.normal, t_min, t_max)?;
+        let d = r.point_at_parameter(t) - &self.center;
+        if d.squared_length() <= self.radius*self.radius { Some(t) } else { None }
+    }
+}
+
+impl<T: ElemT> Hitable<T> for Disk<T> {
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        let t = self.intersect(r, t_min, t_max)?;
+        let p = r.point_at_parameter(t);
+        let d = &p - &self.center;
+        let pi = T::from_f64(PI).unwrap();
+        let phi = d.dot(&self.frame.v()).atan2(d.dot(&self.frame.u()));
+        Some(HitRecord {
+            t,
+            u: (phi + pi) / (pi + pi),
+            v: d.length() / self.radius,
+            p,
+            normal: self.normal.clone(),
+            mat_opt: Some(&*self.material)
+        })
+    }
+
+    // Along each axis the rim reaches radius * sin of the angle between it
+    // and the normal
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        let mut extent = Vec3::default();
+        for a in 0..3 {
+            extent[a] = self.radius * (T::one() - self.normal[a]*self.normal[a]).max(T::zero()).sqrt();
+        }
+        Some(padded_box(&self.center - &extent, &self.center + &extent))
+    }
+
+    fn sample(&self, origin: &Vec3<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, T)> {
+        let (x, y) = sampling::concentric_disk(T::from_f64(sampler.next_f64()).unwrap(), T::from_f64(sampler.next_f64()).unwrap());
+        let p = &self.center + self.frame.local(x*self.radius, y*self.radius, T::zero());
+        let pdf = area_to_solid_angle(self.area_pdf(), origin, &p, &self.normal);
+        if pdf > T::zero() { Some((p, pdf)) } else { None }
+    }
+
+    fn pdf(&self, origin: &Vec3<T>, direction: &Vec3<T>) -> T {
+        let r = Ray::new(origin.clone(), direction.clone());
+        match self.intersect(&r, T::zero(), T::max_value()) {
+            Some(t) => area_to_solid_angle(self.area_pdf(), origin, &r.point_at_parameter(t), &self.normal),
+            None => T::zero()
+        }
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Hitable<T>>) {
+        if self.material.is_emissive() {
+            lights.push(self);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Disk, Plane};
+    use vec3::Vec3;
+    use ray::Ray;
+    use hitable::Hitable;
+    use lambertian::Lambertian;
+    use diffuselight::DiffuseLight;
+    use rect::{xz_rect, Parallelogram};
+    use aabox::AaBox;
+    use lightlist::LightList;
+    use rng::Sampler;
+    use sampling;
+
+    fn grey() -> Box<Lambertian<f64>> {
+        Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn down(x: f64, z: f64) -> Ray<f64> {
+        Ray::new(Vec3::new(x, 5., z), Vec3::new(0., -1., 0.))
+    }
+
+    #[test]
+    fn test_rects() {
+        let floor = xz_rect(-1., 1., -2., 2., 0., grey());
+        let rec = floor.hit(&down(0.5, -1.), 0., 100.).unwrap();
+        assert_approx_eq!(5.0, rec.t);
+        assert_approx_eq!(1.0, rec.normal.y());
+        assert_approx_eq!(0.75, rec.u);
+        assert_approx_eq!(0.75, rec.v);
+        assert!(floor.hit(&down(1.5, 0.), 0., 100.).is_none());
+        assert!(floor.bounding_box().unwrap().max().y() > 0.);
+
+        // a slanted parallelogram: its normal is u x v
+        let p = Parallelogram::new(Vec3::new(0., 0., 0.), Vec3::new(2., 0., 0.), Vec3::new(1., 1., -1.), grey());
+        let rec = p.hit(&down(1.5, -0.5), 0., 100.).unwrap();
+        assert_approx_eq!(4.5, rec.t);
+        assert_approx_eq!(0.5, rec.u);
+        assert_approx_eq!(0.5, rec.v);
+        assert_approx_eq!(0.5f64.sqrt(), rec.normal.y());
+        assert!(p.hit(&down(0.2, -0.5), 0., 100.).is_none());
+    }
+
+    #[test]
+    fn test_box() {
+        let b = AaBox::new(&Vec3::new(1., 1., 1.), &Vec3::new(-1., 0., -1.), grey());
+        let rec = b.hit(&down(0.5, 0.), 0., 100.).unwrap();
+        assert_approx_eq!(4.0, rec.t);
+        assert_approx_eq!(1.0, rec.normal.y());
+        // from inside the far wall is hit
+        let rec = b.hit(&Ray::new(Vec3::new(0., 0.5, 0.), Vec3::new(1., 0., 0.)), 0., 100.).unwrap();
+        assert_approx_eq!(1.0, rec.t);
+        assert_approx_eq!(1.0, rec.normal.x());
+        assert!(b.hit(&down(2., 0.), 0., 100.).is_none());
+    }
+
+    #[test]
+    fn test_plane_and_disk() {
+        let plane = Plane::new(Vec3::new(0., -1., 0.), &Vec3::new(0., 1., 0.), grey());
+        assert!(plane.bounding_box().is_none());
+        let rec = plane.hit(&down(1000., -3000.), 0., 100.).unwrap();
+        assert_approx_eq!(6.0, rec.t);
+        assert_approx_eq!(1000f64.powi(2) + 3000f64.powi(2), rec.u*rec.u + rec.v*rec.v, 1e-6);
+
+        let disk = Disk::new(Vec3::new(0., 0., 0.), &Vec3::new(0., 1., 0.), 2., grey());
+        let rec = disk.hit(&down(1., 0.), 0., 100.).unwrap();
+        assert_approx_eq!(5.0, rec.t);
+        assert_approx_eq!(0.5, rec.v);
+        assert!(disk.hit(&down(1.5, 1.5), 0., 100.).is_none());
+        let bbox = disk.bounding_box().unwrap();
+        assert_approx_eq!(-2.0, bbox.min().x());
+        assert_approx_eq!(2.0, bbox.max().z());
+    }
+
+    #[test]
+    fn test_light_pdfs() {
+        // every shape's pdf integrates to one over the directions that see it
+        let light = || Box::new(DiffuseLight::new(Vec3::new(1., 1., 1.)));
+        let shapes: Vec<Box<dyn Hitable<f64>>> = vec![
+            Box::new(xz_rect(-1., 1., -0.5, 0.5, 2., light())),
+            Box::new(Disk::new(Vec3::new(0., 2., 0.), &Vec3::new(1., -1., 0.), 1., light())),
+            Box::new(AaBox::new(&Vec3::new(-1., 1., -1.), &Vec3::new(1., 2., 1.), light()))
+        ];
+        let origin = Vec3::new(0.2, 0., 0.1);
+        let mut sampler = Sampler::new(5);
+        for shape in &shapes {
+            let lights = LightList::new(&**shape);
+            for _ in 0..20 {
+                let (d, pdf) = lights.sample(&origin, &mut sampler).unwrap();
+                assert_approx_eq!(pdf, lights.pdf(&origin, &d), 1e-9);
+            }
+            let n = 200000;
+            let mut sum = 0.;
+            for _ in 0..n {
+                let d = sampling::uniform_sphere(sampler.next_f64(), sampler.next_f64());
+                sum += lights.pdf(&origin, &d);
+            }
+            let integral = sum * 4.*::std::f64::consts::PI / n as f64;
+            assert!((integral - 1.).abs() < 0.03, "integral {}", integral);
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+use rng::Sampler;
+use hitable::HitRecord;
+use material::Material;
+use microfacet::{self, Ggx};
+use roughdielectric::RoughDielectric;
+use onb::Onb;
+use sampling;
+use texture::{ConstantTexture, Texture};
+
+// The "principled" BSDF of Burley, "Physically Based Shading at Disney"
+// (2012) and "Extending the Disney BRDF to a BSDF with Integrated Subsurface
+// Scattering" (2015), as exported by most authoring tools. It mixes:
+//
+// - a diffuse lobe with retro-reflection at grazing angles, blended towards
+//   a flattened look by `subsurface` (the 2012 approximation, not real
+//   subsurface scattering), plus `sheen` for cloth
+// - a GGX specular lobe, from 8% * `specular` at normal incidence for
+//   dielectrics to `base_color` for metals, stretched by `anisotropic`
+// - a second, colourless GGX lobe for `clearcoat`
+// - rough glass of index `ior` for `transmission`, tinted by `base_color`
+//
+// Every parameter but `ior` is a texture, so each can vary over a surface.
+// The scalar ones read the average of the texture's channels, clamped to
+// [0, 1]. Hit records have no tangents, so the grain of anisotropic
+// highlights runs around the y axis, as on something turned on a lathe.
+pub struct Principled<T: ElemT> {
+    pub base_color: Arc<dyn Texture<T>>,
+    pub metallic: Arc<dyn Texture<T>>,
+    pub roughness: Arc<dyn Texture<T>>,
+    pub specular: Arc<dyn Texture<T>>,
+    pub sheen: Arc<dyn Texture<T>>,
+    pub clearcoat: Arc<dyn Texture<T>>,
+    pub clearcoat_roughness: Arc<dyn Texture<T>>,
+    pub transmission: Arc<dyn Texture<T>>,
+    pub subsurface: Arc<dyn Texture<T>>,
+    pub anisotropic: Arc<dyn Texture<T>>,
+    pub ior: T
+}
+
+// A texture of one value everywhere
+pub fn grey<T: ElemT>(x: T) -> Arc<dyn Texture<T>> {
+    Arc::new(ConstantTexture::new(Vec3::new(x, x, x)))
+}
+
+impl<T: ElemT> Principled<T> {
+    // Plastic of the given colour, with the other parameters at the usual
+    // defaults, to be changed as needed
+    pub fn new(base_color: Arc<dyn Texture<T>>) -> Principled<T> {
+        let t = |x: f64| T::from_f64(x).unwrap();
+        Principled {
+            base_color,
+            metallic: grey(T::zero()),
+            roughness: grey(t(0.5)),
+            specular: grey(t(0.5)),
+            sheen: grey(T::zero()),
+            clearcoat: grey(T::zero()),
+            clearcoat_roughness: grey(t(0.1)),
+            transmission: grey(T::zero()),
+            subsurface: grey(T::zero()),
+            anisotropic: grey(T::zero()),
+            ior: t(1.5)
+        }
+    }
+
+    // The shading frame, with the normal on the side the ray came from and
+    // the tangent around the y axis, the direction back along the ray in it,
+    // and the lobes at the hit point
+    fn lobes(&self, r_in: &Ray<T>, rec: &HitRecord<T>) -> (Onb<T>, Vec3<T>, Lobes<T>) {
+        let wo = -r_in.direction().unit_vector();
+        let (n, eta) = if wo.dot(&rec.normal) < T::zero() { (-rec.normal.clone(), T::one() / self.ior) }
+                       else { (rec.normal.clone(), self.ior) };
+        let onb = Onb::with_tangent(&n, &Vec3::new(T::zero(), T::one(), T::zero()).cross(&n));
+        let wo = onb.to_local(&wo);
+        let lobes = Lobes::new(self, rec, &wo, eta);
+        (onb, wo, lobes)
+    }
+}
+
+fn scalar<T: ElemT>(tex: &Arc<dyn Texture<T>>, rec: &HitRecord<T>) -> T {
+    let c = tex.value(rec.u, rec.v, &rec.p);
+    ((c.x() + c.y() + c.z()) / T::from_f64(3.).unwrap()).max(T::zero()).min(T::one())
+}
+
+fn luminance<T: ElemT>(c: &Vec3<T>) -> T {
+    let t = |x: f64| T::from_f64(x).unwrap();
+    t(0.2126)*c.x() + t(0.7152)*c.y() + t(0.0722)*c.z()
+}
+
+// (1 - cos)^5, the shape of Schlick's Fresnel approximation
+fn schlick_weight<T: ElemT>(cos: T) -> T {
+    (T::one() - cos).max(T::zero()).min(T::one()).powi(5)
+}
+
+fn mix<T: ElemT>(a: &Vec3<T>, b: &Vec3<T>, s: T) -> Vec3<T> {
+    a * (T::one() - s) + b * s
+}
+
+// Everything the lobes need at one point, in the local frame, with the
+// chance of sampling each
+struct Lobes<T: ElemT> {
+    base: Vec3<T>,
+    metallic: T,
+    roughness: T,
+    specular: T,
+    sheen: T,
+    sheen_color: Vec3<T>,
+    clearcoat: T,
+    subsurface: T,
+    // how much of the surface is opaque dielectric, and how much glass
+    diffuse_weight: T,
+    glass_weight: T,
+    spec: Ggx<T>,
+    coat: Ggx<T>,
+    glass: RoughDielectric<T>,
+    eta: T,
+    // diffuse, specular, clearcoat and transmission
+    probs: [T; 4]
+}
+
+impl<T: ElemT> Lobes<T> {
+    fn new(m: &Principled<T>, rec: &HitRecord<T>, wo: &Vec3<T>, eta: T) -> Lobes<T> {
+        let t = |x: f64| T::from_f64(x).unwrap();
+        let base = m.base_color.value(rec.u, rec.v, &rec.p);
+        let metallic = scalar(&m.metallic, rec);
+        let transmission = scalar(&m.transmission, rec);
+        // no smoother than microfacet::SMOOTH_ALPHA, so every lobe can
+        // always be evaluated for light sampling
+        let min_roughness = t(microfacet::SMOOTH_ALPHA.sqrt());
+        let roughness = scalar(&m.roughness, rec).max(min_roughness);
+        let aspect = (T::one() - t(0.9)*scalar(&m.anisotropic, rec)).sqrt();
+        let alpha = roughness * roughness;
+        let coat_roughness = scalar(&m.clearcoat_roughness, rec).max(min_roughness);
+        let lum = luminance(&base);
+        let white = Vec3::new(T::one(), T::one(), T::one());
+        let tint = if lum > T::zero() { &base / lum } else { white.clone() };
+
+        let mut lobes = Lobes {
+            sheen_color: mix(&white, &tint, t(0.5)),
+            metallic,
+            roughness,
+            specular: scalar(&m.specular, rec),
+            sheen: scalar(&m.sheen, rec),
+            clearcoat: scalar(&m.clearcoat, rec),
+            subsurface: scalar(&m.subsurface, rec),
+            diffuse_weight: (T::one() - metallic) * (T::one() - transmission),
+            glass_weight: (T::one() - metallic) * transmission,
+            spec: Ggx::anisotropic(alpha / aspect, alpha * aspect),
+            coat: Ggx::from_roughness(coat_roughness),
+            glass: RoughDielectric::new(m.ior, roughness),
+            eta,
+            probs: [T::zero(); 4],
+            base
+        };
+        // roughly how much each lobe reflects
+        let cos_o = wo.z();
+        let mut probs = [
+            lobes.diffuse_weight * lum.max(t(0.05)),
+            luminance(&lobes.specular_fresnel(cos_o)),
+            t(0.25) * lobes.clearcoat * lobes.coat_fresnel(cos_o),
+            lobes.glass_weight
+        ];
+        let total = probs.iter().fold(T::zero(), |a, &b| a + b);
+        if total > T::zero() {
+            for p in &mut probs {
+                *p /= total;
+            }
+        } else {
+            probs = [T::zero(), T::one(), T::zero(), T::zero()];
+        }
+        lobes.probs = probs;
+        lobes
+    }
+
+    // The opaque dielectric and metal parts of the specular lobe together
+    fn specular_fresnel(&self, cos: T) -> Vec3<T> {
+        let w = schlick_weight(cos);
+        let f0 = self.specular * T::from_f64(0.08).unwrap();
+        let dielectric = f0 + (T::one() - f0) * w;
+        let white = Vec3::new(T::one(), T::one(), T::one());
+        let metal = mix(&self.base, &white, w);
+        Vec3::new(dielectric, dielectric, dielectric) * self.diffuse_weight + metal * self.metallic
+    }
+
+    fn coat_fresnel(&self, cos: T) -> T {
+        let f0 = T::from_f64(0.04).unwrap();
+        f0 + (T::one() - f0) * schlick_weight(cos)
+    }
+
+    // BSDF times cosine, and the pdf of sampling wi, in the local frame
+    fn eval(&self, wo: &Vec3<T>, wi: &Vec3<T>) -> (Vec3<T>, T) {
+        let t = |x: f64| T::from_f64(x).unwrap();
+        let (cos_o, cos_i) = (wo.z(), wi.z());
+        let mut value = Vec3::default();
+        let mut pdf = T::zero();
+        if cos_o <= T::zero() {
+            return (value, pdf);
+        }
+        if cos_i > T::zero() {
+            let h = (wo + wi).unit_vector();
+            let cos_d = wi.dot(&h);
+            let four = t(4.);
+
+            let (fl, fv) = (schlick_weight(cos_i), schlick_weight(cos_o));
+            let rough_d = self.roughness * cos_d * cos_d;
+            let fd90 = t(0.5) + rough_d + rough_d;
+            let fd = (T::one() + (fd90 - T::one())*fl) * (T::one() + (fd90 - T::one())*fv);
+            let fss = (T::one() + (rough_d - T::one())*fl) * (T::one() + (rough_d - T::one())*fv);
+            let ss = t(1.25) * (fss * (T::one() / (cos_i + cos_o) - t(0.5)) + t(0.5));
+            let diffuse = &self.base * ((fd + (ss - fd)*self.subsurface) / t(PI));
+            let sheen = &self.sheen_color * (self.sheen * schlick_weight(cos_d));
+            value += (diffuse + sheen) * (self.diffuse_weight * cos_i);
+            pdf += self.probs[0] * sampling::cosine_hemisphere_pdf(cos_i);
+
+            value += self.specular_fresnel(cos_d) * (self.spec.d(&h) * self.spec.g(wo, wi) / (four * cos_o));
+            pdf += self.probs[1] * self.spec.pdf_visible(wo, &h) / (four * wo.dot(&h));
+
+            if self.clearcoat > T::zero() {
+                let coat = t(0.25) * self.clearcoat * self.coat_fresnel(cos_d) * self.coat.d(&h) * self.coat.g(wo, wi) / (four * cos_o);
+                value += Vec3::new(coat, coat, coat);
+                pdf += self.probs[2] * self.coat.pdf_visible(wo, &h) / (four * wo.dot(&h));
+            }
+        }
+        if self.glass_weight > T::zero() {
+            let (v, p) = self.glass.eval_local(wo, wi, self.eta);
+            let tint = if cos_i < T::zero() { self.base.clone() } else { Vec3::new(T::one(), T::one(), T::one()) };
+            value += tint * (self.glass_weight * v);
+            pdf += self.probs[3] * p;
+        }
+        (value, pdf)
+    }
+
+    // A direction drawn from one of the lobes, picked by `probs`
+    fn sample(&self, wo: &Vec3<T>, sampler: &mut Sampler) -> Option<Vec3<T>> {
+        let mut u = T::from_f64(sampler.next_f64()).unwrap();
+        let mut lobe = 0;
+        while lobe < 3 && u >= self.probs[lobe] {
+            u -= self.probs[lobe];
+            lobe += 1;
+        }
+        let wi = match lobe {
+            0 => sampling::cosine_hemisphere(T::from_f64(sampler.next_f64()).unwrap(), T::from_f64(sampler.next_f64()).unwrap()),
+            1 => microfacet::reflect(wo, &self.spec.sample_visible(wo, sampler)),
+            2 => microfacet::reflect(wo, &self.coat.sample_visible(wo, sampler)),
+            _ => return self.glass.sample_local(wo, self.eta, sampler)
+        };
+        if wi.z() > T::zero() { Some(wi) } else { None }
+    }
+}
+
+impl<T: ElemT> Material<T> for Principled<T> {
+    fn scatter(&self, r_in: &Ray<T>, rec: &HitRecord<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, Ray<T>)> {
+        let (onb, wo, lobes) = self.lobes(r_in, rec);
+        let wi = lobes.sample(&wo, sampler)?;
+        let (value, pdf) = lobes.eval(&wo, &wi);
+        if pdf <= T::zero() {
+            return None;
+        }
+        Some((value / pdf, Ray::with_time(rec.p.clone(), onb.local(wi.x(), wi.y(), wi.z()), r_in.time())))
+    }
+
+    fn eval(&self, r_in: &Ray<T>, rec: &HitRecord<T>, direction: &Vec3<T>) -> Option<(Vec3<T>, T)> {
+        let (onb, wo, lobes) = self.lobes(r_in, rec);
+        Some(lobes.eval(&wo, &onb.to_local(&direction.unit_vector())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+    use std::sync::Arc;
+
+    use super::{grey, Principled};
+    use vec3::Vec3;
+    use ray::Ray;
+    use hitable::HitRecord;
+    use material::Material;
+    use texture::ConstantTexture;
+    use rng::Sampler;
+
+    fn record() -> HitRecord<'static, f64> {
+        HitRecord { p: Vec3::new(0., 0., 0.), normal: Vec3::new(1., 0., 0.), ..HitRecord::default() }
+    }
+
+    fn color(r: f64, g: f64, b: f64) -> Principled<f64> {
+        Principled::new(Arc::new(ConstantTexture::new(Vec3::new(r, g, b))))
+    }
+
+    // Average sampled weight, and the integrals over the sphere of eval's
+    // value and pdf, which should match
+    fn check(m: &Principled<f64>, r: &Ray<f64>, sampler: &mut Sampler) -> Vec3<f64> {
+        let rec = record();
+        let n = 50000;
+        let mut sampled = Vec3::default();
+        for _ in 0..n {
+            if let Some((w, _)) = m.scatter(r, &rec, sampler) {
+                sampled += w;
+            }
+        }
+        sampled /= n as f64;
+
+        let k = 200;
+        let (mut value, mut pdf) = (Vec3::default(), 0.);
+        for i in 0..2*k {
+            let cos = -1. + (i as f64 + 0.5) / k as f64;
+            let sin = (1. - cos*cos).sqrt();
+            for j in 0..2*k {
+                let phi = PI * (j as f64 + 0.5) / k as f64;
+                // around the normal, which is x
+                let (f, p) = m.eval(r, &rec, &Vec3::new(cos, sin*phi.cos(), sin*phi.sin())).unwrap();
+                value += f;
+                pdf += p;
+            }
+        }
+        let dw = PI / (k*k) as f64;
+        for c in 0..3 {
+            assert_approx_eq!(value[c] * dw, sampled[c], 0.02);
+        }
+        assert!(pdf * dw <= 1.01, "{}", pdf * dw);
+        sampled
+    }
+
+    #[test]
+    fn test_lobes() {
+        let mut sampler = Sampler::new(21);
+        let r = Ray::new(Vec3::new(1., 0.5, 0.3), Vec3::new(-1., -0.5, -0.3));
+
+        // plastic reflects about its colour
+        let plastic = color(0.8, 0.2, 0.1);
+        let w = check(&plastic, &r, &mut sampler);
+        assert!(w.x() > 0.7 && w.x() < 1.0 && w.z() < 0.2, "{}", w);
+
+        // rough anisotropic red metal is all red
+        let mut metal = color(0.9, 0., 0.);
+        metal.metallic = grey(1.);
+        metal.anisotropic = grey(0.8);
+        let w = check(&metal, &r, &mut sampler);
+        assert!(w.x() > 0.5 && w.y() < 0.05, "{}", w);
+
+        let mut cloth = color(0.5, 0.5, 0.5);
+        cloth.sheen = grey(1.);
+        cloth.subsurface = grey(0.5);
+        cloth.clearcoat = grey(1.);
+        cloth.clearcoat_roughness = grey(0.5);
+        check(&cloth, &r, &mut sampler);
+
+        // frosted glass lets most of it through
+        let mut glass = color(1., 1., 1.);
+        glass.transmission = grey(1.);
+        let w = check(&glass, &r, &mut sampler);
+        assert!(w.x() > 0.85, "{}", w);
+        // and from inside too
+        check(&glass, &Ray::new(Vec3::new(-1., 0.2, 0.3), Vec3::new(1., -0.2, -0.3)), &mut sampler);
+    }
+}
@@ -0,0 +1,402 @@
+use std::f64::consts::PI;
+
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+use hitable::{HitRecord, Hitable};
+use material::Material;
+use aabb::Aabb;
+use roots;
+
+// Surfaces of revolution around the y axis. Each one is positioned by a point
+// on its axis and can be cut to a partial sweep: only the part with angle
+// phi <= sweep around the axis, measured from +x towards +z, is kept. On the
+// sides u goes around with phi and v goes up the axis; on end caps v goes out
+// from the axis to the rim.
+
+fn f<T: ElemT>(x: f64) -> T {
+    T::from_f64(x).unwrap()
+}
+
+// Angle around the y axis in [0, 2π)
+pub fn phi<T: ElemT>(p: &Vec3<T>) -> T {
+    let phi = p.z().atan2(p.x());
+    if phi < T::zero() { phi + f(2.*PI) } else { phi }
+}
+
+// Panics unless the sweep is positive; more than a full turn is a full turn
+pub fn sweep_radians<T: ElemT>(degrees: T) -> T {
+    assert!(degrees > T::zero(), "a sweep must be positive");
+    degrees.min(f(360.)).to_radians()
+}
+
+// A ray in a shape's own frame, with the origin relative to its axis point
+pub struct LocalRay<T: ElemT> {
+    pub o: Vec3<T>,
+    pub d: Vec3<T>
+}
+
+impl<T: ElemT> LocalRay<T> {
+    pub fn new(r: &Ray<T>, origin: &Vec3<T>) -> LocalRay<T> {
+        LocalRay { o: r.origin() - origin, d: r.direction() }
+    }
+
+    pub fn at(&self, t: T) -> Vec3<T> {
+        &self.o + &self.d*t
+    }
+}
+
+// A hit in a shape's own frame
+pub struct LocalHit<T: ElemT> {
+    pub t: T,
+    pub normal: Vec3<T>,
+    pub u: T,
+    pub v: T
+}
+
+impl<T: ElemT> LocalHit<T> {
+    pub fn record<'a>(self, r: &Ray<T>, mat: &'a dyn Material<T>) -> HitRecord<'a, T> {
+        HitRecord {
+            t: self.t,
+            u: self.u,
+            v: self.v,
+            p: r.point_at_parameter(self.t),
+            normal: self.normal.unit_vector(),
+            mat_opt: Some(mat)
+        }
+    }
+}
+
+fn nearer<T: ElemT>(a: Option<LocalHit<T>>, b: Option<LocalHit<T>>) -> Option<LocalHit<T>> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if b.t < a.t { b } else { a }),
+        (a, None) => a,
+        (None, b) => b
+    }
+}
+
+// The first of `roots` in (t_min, t_max) whose point lies between heights y0
+// and y1 and inside the sweep
+fn side<T: ElemT>(r: &LocalRay<T>, roots: Vec<T>, y0: T, y1: T, sweep: T, t_min: T, t_max: T) -> Option<(T, Vec3<T>)> {
+    roots.into_iter()
+        .filter(|&t| t > t_min && t < t_max)
+        .map(|t| (t, r.at(t)))
+        .find(|(_, p)| p.y() >= y0 && p.y() <= y1 && phi(p) <= sweep)
+}
+
+// A flat end cap at height y, a disk (or with a partial sweep, a sector) of
+// the given radius facing along `facing` (+1 or -1 in y)
+fn cap<T: ElemT>(r: &LocalRay<T>, y: T, radius: T, facing: T, sweep: T, t_min: T, t_max: T) -> Option<LocalHit<T>> {
+    if r.d.y() == T::zero() {
+        return None;
+    }
+    let t = (y - r.o.y()) / r.d.y();
+    if t <= t_min || t >= t_max {
+        return None;
+    }
+    let p = r.at(t);
+    let dist = (p.x()*p.x() + p.z()*p.z()).sqrt();
+    let phi = phi(&p);
+    if dist > radius || phi > sweep {
+        return None;
+    }
+    Some(LocalHit { t, normal: Vec3::new(T::zero(), facing, T::zero()), u: phi / sweep, v: dist / radius })
+}
+
+fn revolved_box<T: ElemT>(origin: &Vec3<T>, radius: T, y0: T, y1: T) -> Aabb<T> {
+    Aabb::new(origin + &Vec3::new(-radius, y0, -radius), origin + &Vec3::new(radius, y1, radius))
+}
+
+pub struct Cylinder<T: ElemT> {
+    base: Vec3<T>,
+    radius: T,
+    height: T,
+    sweep: T,
+    capped: bool,
+    material: Box<dyn Material<T>>
+}
+
+impl<T: ElemT> Cylinder<T> {
+    // Stands on `base` and reaches up `height`; `sweep` is in degrees
+    pub fn new(base: Vec3<T>, radius: T, height: T, sweep: T, capped: bool, mat: Box<dyn Material<T>>) -> Cylinder<T> {
+        Cylinder { base, radius, height, sweep: sweep_radians(sweep), capped, material: mat }
+    }
+}
+
+impl<T: ElemT> Hitable<T> for Cylinder<T> {
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        let l = LocalRay::new(r, &self.base);
+        let (o, d) = (&l.o, &l.d);
+        let roots = roots::quadratic(o.x()*o.x() + o.z()*o.z() - self.radius*self.radius,
+                                     f::<T>(2.)*(o.x()*d.x() + o.z()*d.z()),
+                                     d.x()*d.x() + d.z()*d.z());
+        let mut hit = side(&l, roots, T::zero(), self.height, self.sweep, t_min, t_max).map(|(t, p)| LocalHit {
+            t,
+            normal: Vec3::new(p.x(), T::zero(), p.z()),
+            u: phi(&p) / self.sweep,
+            v: p.y() / self.height
+        });
+        if self.capped {
+            hit = nearer(hit, cap(&l, T::zero(), self.radius, -T::one(), self.sweep, t_min, t_max));
+            hit = nearer(hit, cap(&l, self.height, self.radius, T::one(), self.sweep, t_min, t_max));
+        }
+        Some(hit?.record(r, &*self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        Some(revolved_box(&self.base, self.radius, T::zero(), self.height))
+    }
+}
+
+pub struct Cone<T: ElemT> {
+    base: Vec3<T>,
+    radius: T,
+    height: T,
+    sweep: T,
+    capped: bool,
+    material: Box<dyn Material<T>>
+}
+
+impl<T: ElemT> Cone<T> {
+    // A base of `radius` on `base` narrowing to a point `height` above it
+    pub fn new(base: Vec3<T>, radius: T, height: T, sweep: T, capped: bool, mat: Box<dyn Material<T>>) -> Cone<T> {
+        Cone { base, radius, height, sweep: sweep_radians(sweep), capped, material: mat }
+    }
+}
+
+impl<T: ElemT> Hitable<T> for Cone<T> {
+    // x^2 + z^2 = k^2 (h - y)^2 with k = radius / height
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        let l = LocalRay::new(r, &self.base);
+        let (o, d) = (&l.o, &l.d);
+        let k2 = (self.radius / self.height).powi(2);
+        let h = self.height - o.y();
+        let roots = roots::quadratic(o.x()*o.x() + o.z()*o.z() - k2*h*h,
+                                     f::<T>(2.)*(o.x()*d.x() + o.z()*d.z() + k2*h*d.y()),
+                                     d.x()*d.x() + d.z()*d.z() - k2*d.y()*d.y());
+        let mut hit = side(&l, roots, T::zero(), self.height, self.sweep, t_min, t_max).map(|(t, p)| LocalHit {
+            t,
+            normal: Vec3::new(p.x(), k2*(self.height - p.y()), p.z()),
+            u: phi(&p) / self.sweep,
+            v: p.y() / self.height
+        });
+        if self.capped {
+            hit = nearer(hit, cap(&l, T::zero(), self.radius, -T::one(), self.sweep, t_min, t_max));
+        }
+        Some(hit?.record(r, &*self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        Some(revolved_box(&self.base, self.radius, T::zero(), self.height))
+    }
+}
+
+pub struct Paraboloid<T: ElemT> {
+    vertex: Vec3<T>,
+    radius: T,
+    height: T,
+    sweep: T,
+    material: Box<dyn Material<T>>
+}
+
+impl<T: ElemT> Paraboloid<T> {
+    // A bowl with its lowest point on `vertex`, opening up to `radius` at
+    // `height` above it
+    pub fn new(vertex: Vec3<T>, radius: T, height: T, sweep: T, mat: Box<dyn Material<T>>) -> Paraboloid<T> {
+        Paraboloid { vertex, radius, height, sweep: sweep_radians(sweep), material: mat }
+    }
+}
+
+impl<T: ElemT> Hitable<T> for Paraboloid<T> {
+    // x^2 + z^2 = k y with k = radius^2 / height
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        let l = LocalRay::new(r, &self.vertex);
+        let (o, d) = (&l.o, &l.d);
+        let k = self.radius*self.radius / self.height;
+        let roots = roots::quadratic(o.x()*o.x() + o.z()*o.z() - k*o.y(),
+                                     f::<T>(2.)*(o.x()*d.x() + o.z()*d.z()) - k*d.y(),
+                                     d.x()*d.x() + d.z()*d.z());
+        let hit = side(&l, roots, T::zero(), self.height, self.sweep, t_min, t_max).map(|(t, p)| LocalHit {
+            t,
+            normal: Vec3::new(p.x() + p.x(), -k, p.z() + p.z()),
+            u: phi(&p) / self.sweep,
+            v: p.y() / self.height
+        });
+        Some(hit?.record(r, &*self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        Some(revolved_box(&self.vertex, self.radius, T::zero(), self.height))
+    }
+}
+
+pub struct Hyperboloid<T: ElemT> {
+    center: Vec3<T>,
+    waist: T,
+    end_radius: T,
+    height: T,
+    sweep: T,
+    material: Box<dyn Material<T>>
+}
+
+impl<T: ElemT> Hyperboloid<T> {
+    // A hyperboloid of one sheet, `waist` in radius at `center` and widening
+    // to `end_radius` at height / 2 above and below it. A zero waist makes a
+    // double cone.
+    pub fn new(center: Vec3<T>, waist: T, end_radius: T, height: T, sweep: T, mat: Box<dyn Material<T>>) -> Hyperboloid<T> {
+        Hyperboloid { center, waist, end_radius, height, sweep: sweep_radians(sweep), material: mat }
+    }
+}
+
+impl<T: ElemT> Hitable<T> for Hyperboloid<T> {
+    // x^2 + z^2 = waist^2 + c y^2, with c chosen to reach end_radius at the ends
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        let l = LocalRay::new(r, &self.center);
+        let (o, d) = (&l.o, &l.d);
+        let half = self.height / f(2.);
+        let c = (self.end_radius*self.end_radius - self.waist*self.waist) / (half*half);
+        let roots = roots::quadratic(o.x()*o.x() + o.z()*o.z() - self.waist*self.waist - c*o.y()*o.y(),
+                                     f::<T>(2.)*(o.x()*d.x() + o.z()*d.z() - c*o.y()*d.y()),
+                                     d.x()*d.x() + d.z()*d.z() - c*d.y()*d.y());
+        let hit = side(&l, roots, -half, half, self.sweep, t_min, t_max).map(|(t, p)| LocalHit {
+            t,
+            normal: Vec3::new(p.x(), -c*p.y(), p.z()),
+            u: phi(&p) / self.sweep,
+            v: (p.y() + half) / self.height
+        });
+        Some(hit?.record(r, &*self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        let half = self.height / f(2.);
+        Some(revolved_box(&self.center, self.waist.max(self.end_radius), -half, half))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{Cone, Cylinder, Hyperboloid, Paraboloid};
+    use rand::Rng;
+    use vec3::Vec3;
+    use ray::Ray;
+    use hitable::Hitable;
+    use lambertian::Lambertian;
+    use rng;
+
+    // The reference: march along the ray looking for sign changes of the
+    // surface's implicit function `f`, refine each by bisection and keep the
+    // first whose point `keep` accepts. Normals are the gradient of `f`.
+    pub fn reference(f: &dyn Fn(&Vec3<f64>) -> f64, keep: &dyn Fn(&Vec3<f64>) -> bool,
+                     r: &Ray<f64>, t_max: f64) -> Option<(f64, Vec3<f64>)> {
+        let step = 1e-3;
+        let at = |t: f64| f(&r.point_at_parameter(t));
+        let mut t0 = 1e-6;
+        let mut f0 = at(t0);
+        while t0 < t_max {
+            let t1 = t0 + step;
+            let f1 = at(t1);
+            if f0 == 0. || f0.signum() != f1.signum() {
+                let (mut a, mut b) = (t0, t1);
+                for _ in 0..60 {
+                    let m = (a + b) / 2.;
+                    if at(a).signum() == at(m).signum() { a = m; } else { b = m; }
+                }
+                let p = r.point_at_parameter(a);
+                if keep(&p) {
+                    let e = 1e-6;
+                    let grad = Vec3::new(f(&(&p + Vec3::new(e, 0., 0.))) - f(&(&p - Vec3::new(e, 0., 0.))),
+                                         f(&(&p + Vec3::new(0., e, 0.))) - f(&(&p - Vec3::new(0., e, 0.))),
+                                         f(&(&p + Vec3::new(0., 0., e))) - f(&(&p - Vec3::new(0., 0., e))));
+                    return Some((a, grad.unit_vector()));
+                }
+            }
+            t0 = t1;
+            f0 = f1;
+        }
+        None
+    }
+
+    // Compares `shape` with the reference along rays from all around aimed
+    // at random points near the origin
+    pub fn compare(shape: &dyn Hitable<f64>, f: &dyn Fn(&Vec3<f64>) -> f64, keep: &dyn Fn(&Vec3<f64>) -> bool, seed: u64) {
+        let mut rng = rng::generator(seed);
+        let mut hits = 0;
+        for _ in 0..150 {
+            let mut random = || Vec3::new(rng.next_f64()*2. - 1., rng.next_f64()*2. - 1., rng.next_f64()*2. - 1.);
+            let origin = random().unit_vector()*4.;
+            let target = random()*1.2;
+            let r = Ray::new(origin.clone(), &target - &origin);
+            let t_max = 10. / (&target - &origin).length();
+            match (shape.hit(&r, 1e-6, t_max), reference(f, keep, &r, t_max)) {
+                (Some(rec), Some((t, normal))) => {
+                    hits += 1;
+                    assert_approx_eq!(t, rec.t, 1e-6);
+                    assert_approx_eq!(1.0, rec.normal.dot(&normal), 1e-4);
+                    assert!(rec.u >= 0. && rec.u <= 1. && rec.v >= 0. && rec.v <= 1.);
+                }
+                (None, None) => {}
+                (hit, expected) => panic!("{:?} hit at {:?}, expected {:?}", r.direction(), hit.map(|h| h.t), expected)
+            }
+        }
+        assert!(hits > 20, "only {} hits", hits);
+    }
+
+    fn grey() -> Box<Lambertian<f64>> {
+        Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn sweep_keep(p: &Vec3<f64>, degrees: f64) -> bool {
+        super::phi(p) <= degrees.to_radians()
+    }
+
+    #[test]
+    fn test_cylinder() {
+        let base = Vec3::new(0., -0.5, 0.);
+        let open = Cylinder::new(base.clone(), 0.8, 1.2, 360., false, grey());
+        compare(&open, &|p| p.x()*p.x() + p.z()*p.z() - 0.64, &|p| p.y() >= -0.5 && p.y() <= 0.7, 1);
+
+        // capped, as a solid: outside all three of side, top and bottom
+        let capped = Cylinder::new(base.clone(), 0.8, 1.2, 360., true, grey());
+        compare(&capped, &|p| ((p.x()*p.x() + p.z()*p.z()).sqrt() - 0.8).max(p.y() - 0.7).max(-0.5 - p.y()),
+                &|_| true, 2);
+
+        let partial = Cylinder::new(base, 0.8, 1.2, 250., false, grey());
+        compare(&partial, &|p| p.x()*p.x() + p.z()*p.z() - 0.64,
+                &|p| p.y() >= -0.5 && p.y() <= 0.7 && sweep_keep(p, 250.), 3);
+    }
+
+    #[test]
+    fn test_cone() {
+        // apex at y = 1, radius 1 at y = -0.5
+        let cone = Cone::new(Vec3::new(0., -0.5, 0.), 1., 1.5, 360., false, grey());
+        let k2 = 1./(1.5*1.5);
+        compare(&cone, &|p| p.x()*p.x() + p.z()*p.z() - k2*(1. - p.y()).powi(2), &|p| p.y() >= -0.5 && p.y() <= 1., 4);
+
+        let partial = Cone::new(Vec3::new(0., -0.5, 0.), 1., 1.5, 120., true, grey());
+        let hit = partial.hit(&Ray::new(Vec3::new(0.3, -3., 0.3), Vec3::new(0., 1., 0.)), 0., 100.).unwrap();
+        assert_approx_eq!(2.5, hit.t);
+        assert_approx_eq!(-1.0, hit.normal.y());
+        assert!(partial.hit(&Ray::new(Vec3::new(0.3, -3., -0.3), Vec3::new(0., 1., 0.)), 0., 100.).is_none());
+        let partial = Cone::new(Vec3::new(0., -0.5, 0.), 1., 1.5, 120., false, grey());
+        compare(&partial, &|p| p.x()*p.x() + p.z()*p.z() - k2*(1. - p.y()).powi(2),
+                &|p| p.y() >= -0.5 && p.y() <= 1. && sweep_keep(p, 120.), 5);
+    }
+
+    #[test]
+    fn test_paraboloid() {
+        let bowl = Paraboloid::new(Vec3::new(0., -0.8, 0.), 1., 1.6, 300., grey());
+        compare(&bowl, &|p| p.x()*p.x() + p.z()*p.z() - (p.y() + 0.8)/1.6,
+                &|p| p.y() >= -0.8 && p.y() <= 0.8 && sweep_keep(p, 300.), 6);
+    }
+
+    #[test]
+    fn test_hyperboloid() {
+        let c = (1. - 0.25) / 0.64;
+        let h = Hyperboloid::new(Vec3::new(0., 0., 0.), 0.5, 1., 1.6, 360., grey());
+        compare(&h, &|p| p.x()*p.x() + p.z()*p.z() - 0.25 - c*p.y()*p.y(), &|p| p.y().abs() <= 0.8, 7);
+
+        // a double cone
+        let h = Hyperboloid::new(Vec3::new(0., 0., 0.), 0., 1., 1.6, 200., grey());
+        compare(&h, &|p| p.x()*p.x() + p.z()*p.z() - p.y()*p.y()/0.64,
+                &|p| p.y().abs() <= 0.8 && sweep_keep(p, 200.), 8);
+    }
+}
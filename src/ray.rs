@@ -7,17 +7,25 @@ use vec3::Vec3;
 #[derive(Default)]
 pub struct Ray<T: ElemT> {
     a: Vec3<T>,
-    b: Vec3<T>
+    b: Vec3<T>,
+    time: T
 }
 
 impl<T: ElemT> Ray<T> {
     pub fn new(a: Vec3<T>, b: Vec3<T>) -> Ray<T> {
+        Ray::with_time(a, b, T::zero())
+    }
+    // A ray at an instant while the shutter is open, for motion blur. Rays
+    // bounced off a surface keep the time of the ray that hit it.
+    pub fn with_time(a: Vec3<T>, b: Vec3<T>, time: T) -> Ray<T> {
         Ray::<T> {
-            a: a,
-            b: b
+            a,
+            b,
+            time
         }
     }
     pub fn origin(&self) -> Vec3<T> { self.a.clone() }
     pub fn direction(&self) -> Vec3<T> { self.b.clone() }
+    pub fn time(&self) -> T { self.time }
     pub fn point_at_parameter(&self, t: T) -> Vec3<T> { &self.a + &self.b * t }
 }
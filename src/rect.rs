@@ -0,0 +1,170 @@
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+use hitable::{area_to_solid_angle, HitRecord, Hitable};
+use material::Material;
+use aabb::Aabb;
+use rng::Sampler;
+
+// Flat shapes have no thickness; their boxes get a little so the slab test
+// doesn't have to deal with an empty interval
+pub fn padded_box<T: ElemT>(min: Vec3<T>, max: Vec3<T>) -> Aabb<T> {
+    let delta = T::from_f64(1e-4).unwrap();
+    let mut min = min;
+    let mut max = max;
+    for a in 0..3 {
+        if max[a] - min[a] < delta {
+            min[a] -= delta / T::from_f64(2.).unwrap();
+            max[a] += delta / T::from_f64(2.).unwrap();
+        }
+    }
+    Aabb::new(min, max)
+}
+
+// Where a ray crosses the plane through `point` with normal `normal`, if it
+// does within (t_min, t_max)
+pub fn intersect_plane<T: ElemT>(r: &Ray<T>, point: &Vec3<T>, normal: &Vec3<T>, t_min: T, t_max: T) -> Option<T> {
+    let denom = normal.dot(&r.direction());
+    if denom.abs() < T::epsilon() { // ray is parallel to the plane
+        return None;
+    }
+    let t = normal.dot(&(point - &r.origin())) / denom;
+    if t < t_max && t > t_min { Some(t) } else { None }
+}
+
+// The shape of a parallelogram: the corner `q` and the two edges `u` and `v`
+// leaving it. The normal is u x v.
+#[derive(Clone)]
+pub struct Quad<T: ElemT> {
+    q: Vec3<T>,
+    u: Vec3<T>,
+    v: Vec3<T>,
+    normal: Vec3<T>,
+    // n / (n.n) with n = u x v, for finding a point's coordinates along u and v
+    w: Vec3<T>,
+    area: T
+}
+
+impl<T: ElemT> Quad<T> {
+    pub fn new(q: Vec3<T>, u: Vec3<T>, v: Vec3<T>) -> Quad<T> {
+        let n = u.cross(&v);
+        let area = n.length();
+        Quad {
+            normal: n.unit_vector(),
+            w: &n / n.dot(&n),
+            area,
+            q,
+            u,
+            v
+        }
+    }
+
+    pub fn normal(&self) -> &Vec3<T> { &self.normal }
+    pub fn area(&self) -> T { self.area }
+
+    // The ray parameter and (u, v) coordinates, both in [0, 1], of a hit
+    pub fn intersect(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<(T, T, T)> {
+        let t = intersect_plane(r, &self.q, &self.normal, t_min, t_max)?;
+        let d = r.point_at_parameter(t) - &self.q;
+        let alpha = self.w.dot(&d.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&d));
+        let inside = |x: T| x >= T::zero() && x <= T::one();
+        if inside(alpha) && inside(beta) { Some((t, alpha, beta)) } else { None }
+    }
+
+    pub fn bounding_box(&self) -> Aabb<T> {
+        let corners = [&self.q + &self.u, &self.q + &self.v, &self.q + &self.u + &self.v];
+        let mut min = self.q.clone();
+        let mut max = self.q.clone();
+        for c in &corners {
+            for a in 0..3 {
+                min[a] = min[a].min(c[a]);
+                max[a] = max[a].max(c[a]);
+            }
+        }
+        padded_box(min, max)
+    }
+
+    // Picks a point uniformly over the area. Returns it with its solid angle
+    // pdf seen from `origin`.
+    pub fn sample(&self, origin: &Vec3<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, T)> {
+        let r1 = T::from_f64(sampler.next_f64()).unwrap();
+        let r2 = T::from_f64(sampler.next_f64()).unwrap();
+        let p = &self.q + &self.u*r1 + &self.v*r2;
+        let pdf = area_to_solid_angle(T::one() / self.area, origin, &p, &self.normal);
+        if pdf > T::zero() { Some((p, pdf)) } else { None }
+    }
+
+    pub fn pdf(&self, origin: &Vec3<T>, direction: &Vec3<T>) -> T {
+        let r = Ray::new(origin.clone(), direction.clone());
+        match self.intersect(&r, T::zero(), T::max_value()) {
+            Some((t, _, _)) => area_to_solid_angle(T::one() / self.area, origin, &r.point_at_parameter(t), &self.normal),
+            None => T::zero()
+        }
+    }
+}
+
+// A flat parallelogram with its normal along u x v. Its (u, v) run from 0 to
+// 1 along the two edges.
+pub struct Parallelogram<T: ElemT> {
+    quad: Quad<T>,
+    material: Box<dyn Material<T>>
+}
+
+impl<T: ElemT> Parallelogram<T> {
+    pub fn new(q: Vec3<T>, u: Vec3<T>, v: Vec3<T>, mat: Box<dyn Material<T>>) -> Parallelogram<T> {
+        Parallelogram {
+            quad: Quad::new(q, u, v),
+            material: mat
+        }
+    }
+}
+
+// Axis-aligned rectangles as in the book, from (a0, b0) to (a1, b1) in the
+// plane where the third axis is k. Their normals point along +z, +y and +x.
+pub fn xy_rect<T: ElemT>(x0: T, x1: T, y0: T, y1: T, k: T, mat: Box<dyn Material<T>>) -> Parallelogram<T> {
+    Parallelogram::new(Vec3::new(x0, y0, k), Vec3::new(x1 - x0, T::zero(), T::zero()),
+                       Vec3::new(T::zero(), y1 - y0, T::zero()), mat)
+}
+
+pub fn xz_rect<T: ElemT>(x0: T, x1: T, z0: T, z1: T, k: T, mat: Box<dyn Material<T>>) -> Parallelogram<T> {
+    // v runs towards -z, which keeps the normal on +y
+    Parallelogram::new(Vec3::new(x0, k, z1), Vec3::new(x1 - x0, T::zero(), T::zero()),
+                       Vec3::new(T::zero(), T::zero(), z0 - z1), mat)
+}
+
+pub fn yz_rect<T: ElemT>(y0: T, y1: T, z0: T, z1: T, k: T, mat: Box<dyn Material<T>>) -> Parallelogram<T> {
+    Parallelogram::new(Vec3::new(k, y0, z0), Vec3::new(T::zero(), y1 - y0, T::zero()),
+                       Vec3::new(T::zero(), T::zero(), z1 - z0), mat)
+}
+
+impl<T: ElemT> Hitable<T> for Parallelogram<T> {
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        let (t, u, v) = self.quad.intersect(r, t_min, t_max)?;
+        Some(HitRecord {
+            t,
+            u,
+            v,
+            p: r.point_at_parameter(t),
+            normal: self.quad.normal().clone(),
+            mat_opt: Some(&*self.material)
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        Some(self.quad.bounding_box())
+    }
+
+    fn sample(&self, origin: &Vec3<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, T)> {
+        self.quad.sample(origin, sampler)
+    }
+
+    fn pdf(&self, origin: &Vec3<T>, direction: &Vec3<T>) -> T {
+        self.quad.pdf(origin, direction)
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Hitable<T>>) {
+        if self.material.is_emissive() {
+            lights.push(self);
+        }
+    }
+}
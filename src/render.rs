@@ -0,0 +1,331 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+use hitable::{HitRecord, Hitable};
+use lightlist::LightList;
+use atmosphere::Atmosphere;
+use camera::Camera;
+use scene::RenderSettings;
+use framebuffer::Framebuffer;
+use rng::{self, Sampler};
+
+// What a ray sees when it leaves the scene without hitting anything
+#[derive(Clone)]
+pub enum Background<T: ElemT> {
+    // blend from `bottom` looking straight down to `top` looking straight up
+    Gradient { bottom: Vec3<T>, top: Vec3<T> },
+    Constant(Vec3<T>)
+}
+
+impl<T: ElemT> Default for Background<T> {
+    // the white to sky blue gradient from the book
+    fn default() -> Background<T> {
+        Background::Gradient {
+            bottom: Vec3::new(T::one(), T::one(), T::one()),
+            top: Vec3::new(T::from_f64(0.5).unwrap(), T::from_f64(0.7).unwrap(), T::one())
+        }
+    }
+}
+
+impl<T: ElemT> Background<T> {
+    pub fn color(&self, r: &Ray<T>) -> Vec3<T> {
+        match *self {
+            Background::Gradient { ref bottom, ref top } => {
+                let unit_direction = r.direction().unit_vector();
+                let t = T::from_f64(0.5).unwrap()*(unit_direction.y() + T::one());
+                bottom*(T::one()-t) + top*t
+            }
+            Background::Constant(ref c) => c.clone()
+        }
+    }
+}
+
+// Power heuristic weight for a sample from the strategy with pdf `a` when the
+// other strategy has pdf `b`
+fn mis_weight<T: ElemT>(a: T, b: T) -> T {
+    let (a2, b2) = (a*a, b*b);
+    if a2 + b2 > T::zero() { a2 / (a2 + b2) } else { T::zero() }
+}
+
+// Everything a path can meet: the objects, the lights among them, the
+// background and the atmosphere, if there is one
+struct Environment<'a, T: ElemT + 'a> {
+    world: &'a dyn Hitable<T>,
+    lights: &'a LightList<'a, T>,
+    background: &'a Background<T>,
+    atmosphere: Option<&'a Atmosphere<T>>
+}
+
+// Light arriving along a shadow ray: the emission of the first surface it
+// hits, dimmed by any media and the atmosphere on the way. The background isn't one of the
+// lights, so a miss counts for nothing.
+fn direct<T: ElemT>(r: &Ray<T>, env: &Environment<T>) -> Vec3<T> {
+    let t_min = T::from_f64(0.001).unwrap();
+    match env.world.hit_surface(r, t_min, T::max_value()) {
+        Some(rec) => {
+            let le = rec.mat_opt.unwrap().emitted(r, &rec) * env.world.transmittance(r, t_min, rec.t);
+            match env.atmosphere {
+                Some(atmosphere) => le * atmosphere.transmittance(rec.t * r.direction().length()),
+                None => le
+            }
+        }
+        None => Vec3::default()
+    }
+}
+
+// Path tracing with next-event estimation: at every surface that can be
+// evaluated (see Material::eval) one light is sampled with a shadow ray, and
+// that is combined with the BSDF sampled path through multiple importance
+// sampling. `bsdf_pdf` is the pdf the previous bounce had of choosing `r`, or
+// None if it wasn't a bounce lights could have been sampled from (a camera
+// ray or a mirror), in which case emission counts in full. In an atmosphere
+// the ray may scatter before it gets to the surface, which is handled the
+// same way with the phase function in place of the BSDF.
+fn color<T: ElemT>(r: &Ray<T>, env: &Environment<T>, bsdf_pdf: Option<T>, depth: usize, max_depth: usize,
+                   sampler: &mut Sampler) -> Vec3<T> {
+    let hit = env.world.hit(r, T::from_f64(0.001).unwrap(), T::max_value());
+    let atmosphere = match env.atmosphere {
+        Some(atmosphere) => atmosphere,
+        None => return match hit {
+            Some(rec) => shade(r, &rec, env, bsdf_pdf, depth, max_depth, sampler),
+            None => env.background.color(r)
+        }
+    };
+    let t_max = hit.as_ref().map_or(T::max_value(), |rec| rec.t);
+    match atmosphere.sample(r, t_max, sampler) {
+        (Some(t), weight) => {
+            let rec = HitRecord {
+                t,
+                u: T::zero(),
+                v: T::zero(),
+                p: r.point_at_parameter(t),
+                normal: Vec3::new(T::one(), T::zero(), T::zero()),
+                mat_opt: Some(atmosphere.phase())
+            };
+            weight * shade(r, &rec, env, bsdf_pdf, depth, max_depth, sampler)
+        }
+        (None, weight) => weight * match hit {
+            Some(rec) => shade(r, &rec, env, bsdf_pdf, depth, max_depth, sampler),
+            None => env.background.color(r)
+        }
+    }
+}
+
+// The light leaving `rec` back along `r`
+fn shade<T: ElemT>(r: &Ray<T>, rec: &HitRecord<T>, env: &Environment<T>, bsdf_pdf: Option<T>, depth: usize, max_depth: usize,
+                   sampler: &mut Sampler) -> Vec3<T> {
+    let mat = rec.mat_opt.unwrap();
+    let mut emitted = mat.emitted(r, rec);
+    if let Some(pdf) = bsdf_pdf {
+        if mat.is_emissive() && !env.lights.is_empty() {
+            emitted *= mis_weight(pdf, env.lights.pdf(&r.origin(), &r.direction()));
+        }
+    }
+    if depth >= max_depth { // stop recursion
+        return emitted;
+    }
+
+    let mut light = Vec3::default();
+    if let Some((direction, light_pdf)) = env.lights.sample(&rec.p, sampler) {
+        if let Some((f, pdf)) = mat.eval(r, rec, &direction) {
+            if light_pdf > T::zero() && f.squared_length() > T::zero() {
+                let le = direct(&Ray::with_time(rec.p.clone(), direction, r.time()), env);
+                light = f * le * (mis_weight(light_pdf, pdf) / light_pdf);
+            }
+        }
+    }
+
+    if let Some((attenuated, scattered)) = mat.scatter(r, rec, sampler) {
+        let pdf = mat.eval(r, rec, &scattered.direction()).map(|(_, pdf)| pdf);
+        emitted + light + attenuated * color(&scattered, env, pdf, depth+1, max_depth, sampler)
+    }
+    else {
+        emitted + light
+    }
+}
+
+const TILE_SIZE: usize = 32;
+
+struct Tile {
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize
+}
+
+fn make_tiles(nx: usize, ny: usize) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+    for y0 in (0..ny).step_by(TILE_SIZE) {
+        for x0 in (0..nx).step_by(TILE_SIZE) {
+            tiles.push(Tile { x0, y0, x1: (x0+TILE_SIZE).min(nx), y1: (y0+TILE_SIZE).min(ny) });
+        }
+    }
+    tiles
+}
+
+// Returns the sum of the pixel's samples
+fn render_pixel<T: ElemT>(env: &Environment<T>, cam: &Camera<T>, settings: &RenderSettings, i: usize, j: usize) -> Vec3<T> {
+    let (nx, ny, ns) = (settings.width, settings.height, settings.samples);
+
+    let mut col = Vec3::default();
+    for s in 0..ns {
+        // seed from the pixel and sample alone so the result doesn't depend
+        // on scheduling. Media draw their collisions inside Hitable::hit, from
+        // the thread's generator, which is reseeded from the sampler too.
+        let mut sampler = Sampler::for_sample(settings.seed, (j*nx + i) as u64, s as u64);
+        rng::seed(sampler.next_u64());
+        let u = T::from_f64((i as f64 + sampler.next_f64()) / (nx as f64)).unwrap();
+        let v = T::from_f64((j as f64 + sampler.next_f64()) / (ny as f64)).unwrap();
+        let r = cam.get_ray(u, v, &mut sampler);
+        col += color(&r, env, None, 0, settings.max_depth, &mut sampler);
+    }
+    col
+}
+
+// Renders the image with `nthreads` workers pulling tiles off a shared counter
+pub fn render<T: ElemT>(world: &dyn Hitable<T>, background: &Background<T>, atmosphere: Option<&Atmosphere<T>>,
+                        cam: &Camera<T>, settings: &RenderSettings, nthreads: usize) -> Framebuffer<T> {
+    let (nx, ny) = (settings.width, settings.height);
+    let lights = LightList::new(world);
+    let env = Environment { world, lights: &lights, background, atmosphere };
+    let tiles = make_tiles(nx, ny);
+    let next_tile = AtomicUsize::new(0);
+    let fb = Mutex::new(Framebuffer::new(nx, ny));
+
+    thread::scope(|s| {
+        for _ in 0..nthreads {
+            s.spawn(|| {
+                loop {
+                    let idx = next_tile.fetch_add(1, Ordering::Relaxed);
+                    if idx >= tiles.len() { break; }
+                    let tile = &tiles[idx];
+
+                    let mut buf = Vec::with_capacity((tile.x1-tile.x0)*(tile.y1-tile.y0));
+                    for j in tile.y0..tile.y1 {
+                        for i in tile.x0..tile.x1 {
+                            buf.push(render_pixel(&env, cam, settings, i, j));
+                        }
+                    }
+
+                    let mut fb = fb.lock().unwrap();
+                    let mut it = buf.into_iter();
+                    for j in tile.y0..tile.y1 {
+                        for i in tile.x0..tile.x1 {
+                            // j counts up from the bottom of the image
+                            fb.add_samples(i, ny-1-j, it.next().unwrap(), settings.samples);
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    fb.into_inner().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{color, render, Background, Environment};
+    use lightlist::LightList;
+    use atmosphere::Atmosphere;
+    use vec3::Vec3;
+    use ray::Ray;
+    use sphere::Sphere;
+    use diffuselight::DiffuseLight;
+    use scene::{self, RenderSettings, Scene};
+    use rng::Sampler;
+
+    #[test]
+    fn test_emission() {
+        let light = Sphere::new(Vec3::new(0., 0., 0.), 1., Box::new(DiffuseLight::new(Vec3::new(4., 2., 1.))));
+        let black = Background::Constant(Vec3::default());
+
+        let lights = LightList::new(&light);
+        let env = Environment { world: &light, lights: &lights, background: &black, atmosphere: None };
+        let mut sampler = Sampler::new(0);
+
+        let c = color(&Ray::new(Vec3::new(0., 0., 5.), Vec3::new(0., 0., -1.)), &env, None, 0, 50, &mut sampler);
+        assert_eq!((4., 2., 1.), (c.x(), c.y(), c.z()));
+        // emission still counts on the last bounce
+        let c = color(&Ray::new(Vec3::new(0., 0., 5.), Vec3::new(0., 0., -1.)), &env, None, 50, 50, &mut sampler);
+        assert_eq!(4., c.x());
+        let c = color(&Ray::new(Vec3::new(0., 0., 5.), Vec3::new(0., 1., 0.)), &env, None, 0, 50, &mut sampler);
+        assert_eq!(0., c.length());
+    }
+
+    #[test]
+    fn test_atmosphere() {
+        let mut sampler = Sampler::new(2);
+        let light = Sphere::new(Vec3::new(0., 0., 0.), 1., Box::new(DiffuseLight::new(Vec3::new(4., 2., 1.))));
+        let black = Background::Constant(Vec3::default());
+        let lights = LightList::new(&light);
+        let n = 20000;
+        let mut average = |atmosphere: &Atmosphere<f64>, r: &Ray<f64>| {
+            let env = Environment { world: &light, lights: &lights, background: &black, atmosphere: Some(atmosphere) };
+            let mut sum = Vec3::default();
+            // a few bounces are plenty, and in lossless fog paths would
+            // otherwise all go on to the limit
+            for _ in 0..n {
+                sum += color(r, &env, None, 0, 3, &mut sampler);
+            }
+            sum / n as f64
+        };
+
+        // absorption alone dims the light 4 away by exp(-4 sigma)
+        let haze = Atmosphere::new(Vec3::new(0.1, 0.2, 0.3), Vec3::default(), 0.);
+        let c = average(&haze, &Ray::new(Vec3::new(0., 0., 5.), Vec3::new(0., 0., -1.)));
+        assert_approx_eq!(4. * (-0.4f64).exp(), c.x(), 0.05);
+        assert_approx_eq!(2. * (-0.8f64).exp(), c.y(), 0.05);
+        assert_approx_eq!(1. * (-1.2f64).exp(), c.z(), 0.05);
+
+        // scattering lights up the air beside the light, more so when it
+        // scatters forwards towards the viewer
+        let beside = Ray::new(Vec3::new(-5., 2., 5.), Vec3::new(1., 0., -1.));
+        let fog = |g| Atmosphere::new(Vec3::default(), Vec3::new(0.1, 0.1, 0.1), g);
+        let isotropic = average(&fog(0.), &beside);
+        assert!(isotropic.x() > 0.);
+        assert!(average(&fog(0.6), &beside).x() > isotropic.x());
+    }
+
+    #[test]
+    fn test_render_independent_of_thread_count() {
+        let settings = RenderSettings { width: 40, height: 30, samples: 2, ..RenderSettings::default() };
+        let scene: Scene<f64> = scene::random_scene();
+        let cam = scene.camera.build((settings.width as f64) / (settings.height as f64));
+
+        let a = render(&scene.world, &scene.background, None, &cam, &settings, 1);
+        let b = render(&scene.world, &scene.background, None, &cam, &settings, 3);
+        for y in 0..settings.height {
+            for x in 0..settings.width {
+                let (pa, pb) = (a.pixel(x, y), b.pixel(x, y));
+                assert_eq!(pa.x(), pb.x());
+                assert_eq!(pa.y(), pb.y());
+                assert_eq!(pa.z(), pb.z());
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_reproducible_with_media() {
+        // the atmosphere and the media draw their own random numbers, and
+        // those come out the same too, until the seed changes
+        for path in &["scenes/fog.toml", "scenes/volume.toml"] {
+            let scene: Scene<f64> = scene::load_scene(Path::new(path)).unwrap();
+            let settings = RenderSettings { width: 24, height: 16, samples: 2, max_depth: 4, seed: 5 };
+            let cam = scene.camera.build((settings.width as f64) / (settings.height as f64));
+            let draw = |settings: &RenderSettings, nthreads| {
+                let fb = render(&scene.world, &scene.background, scene.atmosphere.as_ref(), &cam, settings, nthreads);
+                (0..settings.height).flat_map(|y| (0..settings.width).map(move |x| (x, y)))
+                    .map(|(x, y)| { let p = fb.pixel(x, y); (p.x(), p.y(), p.z()) }).collect::<Vec<_>>()
+            };
+            let a = draw(&settings, 1);
+            assert_eq!(a, draw(&settings, 4));
+            assert!(a != draw(&RenderSettings { seed: 6, ..settings.clone() }, 4));
+        }
+    }
+}
@@ -0,0 +1,102 @@
+extern crate rand;
+
+use std::cell::RefCell;
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+// Every render thread owns its own generator, for whatever can't be handed a
+// Sampler (media deciding where rays collide inside Hitable::hit). The
+// renderer reseeds it before each sample so the image doesn't depend on which
+// thread rendered which tile.
+thread_local! {
+    static RNG: RefCell<XorShiftRng> = RefCell::new(XorShiftRng::from_seed(expand_seed(0)));
+}
+
+// splitmix64, used to spread nearby seeds (e.g. adjacent pixels) apart
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+fn expand_seed(seed: u64) -> [u32; 4] {
+    let mut state = seed;
+    let a = splitmix64(&mut state);
+    let b = splitmix64(&mut state);
+    let s = [a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32];
+    // xorshift can't be seeded with all zeroes
+    if s.iter().all(|&x| x == 0) { [1, 0, 0, 0] } else { s }
+}
+
+pub fn seed(seed: u64) {
+    RNG.with(|rng| rng.borrow_mut().reseed(expand_seed(seed)));
+}
+
+pub fn next_f64() -> f64 {
+    RNG.with(|rng| rng.borrow_mut().next_f64())
+}
+
+// A generator of its own, for things that need a reproducible sequence
+// independent of the per-pixel streams (e.g. noise tables)
+pub fn generator(seed: u64) -> XorShiftRng {
+    XorShiftRng::from_seed(expand_seed(seed))
+}
+
+// The random numbers for one camera sample of one pixel, handed down
+// through Camera::get_ray, the materials and the lights. Every sample has a
+// stream of its own under the global seed, so a pixel comes out the same
+// however the threads are scheduled and whichever other pixels or samples
+// are rendered with it.
+pub struct Sampler {
+    rng: XorShiftRng,
+    // The colour channel a dispersive material narrowed the path down to
+    channel: Option<usize>
+}
+
+impl Sampler {
+    pub fn new(seed: u64) -> Sampler {
+        Sampler { rng: generator(seed), channel: None }
+    }
+
+    pub fn for_sample(seed: u64, pixel: u64, sample: u64) -> Sampler {
+        let mut state = seed;
+        let mut state = splitmix64(&mut state) ^ pixel;
+        Sampler::new(splitmix64(&mut state) ^ sample)
+    }
+
+    pub fn next_f64(&mut self) -> f64 {
+        self.rng.next_f64()
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    pub fn channel(&self) -> Option<usize> {
+        self.channel
+    }
+
+    pub fn set_channel(&mut self, channel: usize) {
+        self.channel = Some(channel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sampler;
+
+    #[test]
+    fn test_sampler() {
+        let draw = |mut s: Sampler| (0..4).map(|_| s.next_f64()).collect::<Vec<f64>>();
+        // the same sample of the same pixel gives the same numbers
+        assert_eq!(draw(Sampler::for_sample(3, 10, 2)), draw(Sampler::for_sample(3, 10, 2)));
+        // and any other seed, pixel or sample different ones
+        let a = draw(Sampler::for_sample(3, 10, 2));
+        for b in &[draw(Sampler::for_sample(4, 10, 2)), draw(Sampler::for_sample(3, 11, 2)),
+                   draw(Sampler::for_sample(3, 10, 3)), draw(Sampler::for_sample(3, 2, 10))] {
+            assert!(a.iter().zip(b).all(|(x, y)| x != y));
+        }
+        assert!(a.iter().all(|&x| (0. ..1.).contains(&x)));
+    }
+}
@@ -0,0 +1,169 @@
+use std::f64::consts::PI;
+
+use vec3::ElemT;
+
+// Real roots of low degree polynomials, in increasing order. Coefficients
+// are given from the constant term up. The cubic and quartic solvers follow
+// Schwarze's closed forms from Graphics Gems I.
+
+fn f<T: ElemT>(x: f64) -> T {
+    T::from_f64(x).unwrap()
+}
+
+fn is_zero<T: ElemT>(x: T) -> bool {
+    x.abs() < f(1e-9)
+}
+
+fn sorted<T: ElemT>(mut roots: Vec<T>) -> Vec<T> {
+    roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    roots
+}
+
+// c0 + c1 x + c2 x^2. Degenerates to the linear case when c2 is zero.
+pub fn quadratic<T: ElemT>(c0: T, c1: T, c2: T) -> Vec<T> {
+    if c2 == T::zero() {
+        return if c1 == T::zero() { Vec::new() } else { vec![-c0 / c1] };
+    }
+    let disc = c1*c1 - f::<T>(4.)*c2*c0;
+    if disc < T::zero() {
+        return Vec::new();
+    }
+    // avoids cancellation between -c1 and the square root
+    let q = -(c1 + c1.signum()*disc.sqrt()) / f(2.);
+    if q == T::zero() {
+        return vec![T::zero(), T::zero()];
+    }
+    sorted(vec![q / c2, c0 / q])
+}
+
+// x^2 + p x + q, with a near-zero discriminant taken as a double root
+fn monic_quadratic<T: ElemT>(q: T, p: T) -> Vec<T> {
+    let half = p / f(2.);
+    let d = half*half - q;
+    if is_zero(d) {
+        vec![-half]
+    } else if d < T::zero() {
+        Vec::new()
+    } else {
+        let s = d.sqrt();
+        vec![s - half, -s - half]
+    }
+}
+
+// c0 + c1 x + c2 x^2 + c3 x^3
+pub fn cubic<T: ElemT>(c0: T, c1: T, c2: T, c3: T) -> Vec<T> {
+    let (a, b, c) = (c2 / c3, c1 / c3, c0 / c3);
+    // substitute x = y - a/3 to get y^3 + 3p y + 2q = 0
+    let sq_a = a*a;
+    let p = (-sq_a/f(3.) + b) / f(3.);
+    let q = (f::<T>(2./27.)*a*sq_a - a*b/f(3.) + c) / f(2.);
+    let cb_p = p*p*p;
+    let d = q*q + cb_p;
+
+    let roots = if is_zero(d) {
+        if is_zero(q) {
+            vec![T::zero()]
+        } else {
+            let u = (-q).cbrt();
+            vec![u + u, -u]
+        }
+    } else if d < T::zero() { // three real roots
+        let phi = (-q / (-cb_p).sqrt()).max(-T::one()).min(T::one()).acos() / f(3.);
+        let t = f::<T>(2.)*(-p).sqrt();
+        vec![t*phi.cos(), -t*(phi + f(PI/3.)).cos(), -t*(phi - f(PI/3.)).cos()]
+    } else {
+        let sqrt_d = d.sqrt();
+        vec![(sqrt_d - q).cbrt() - (sqrt_d + q).cbrt()]
+    };
+    sorted(roots.into_iter().map(|y| y - a/f(3.)).collect())
+}
+
+// c0 + c1 x + c2 x^2 + c3 x^3 + c4 x^4. The closed form loses precision, so
+// each root is polished with a few Newton steps on the original polynomial.
+pub fn quartic<T: ElemT>(c0: T, c1: T, c2: T, c3: T, c4: T) -> Vec<T> {
+    let (a, b, c, d) = (c3 / c4, c2 / c4, c1 / c4, c0 / c4);
+    // substitute x = y - a/4 to get y^4 + p y^2 + q y + r = 0
+    let sq_a = a*a;
+    let p = f::<T>(-3./8.)*sq_a + b;
+    let q = sq_a*a/f(8.) - a*b/f(2.) + c;
+    let r = f::<T>(-3./256.)*sq_a*sq_a + sq_a*b/f(16.) - a*c/f(4.) + d;
+
+    let mut roots = if is_zero(r) {
+        // y (y^3 + p y + q) = 0
+        let mut roots = cubic(q, p, T::zero(), T::one());
+        roots.push(T::zero());
+        roots
+    } else {
+        // a root z of the resolvent cubic with z^2 - r and 2z - p both
+        // non-negative splits it into two real quadratics
+        let split = |z: T| {
+            let (u, v) = (z*z - r, z + z - p);
+            let u = if is_zero(u) { T::zero() } else { u };
+            let v = if is_zero(v) { T::zero() } else { v };
+            if u < T::zero() || v < T::zero() { None } else { Some((z, u.sqrt(), v.sqrt())) }
+        };
+        let resolvent = cubic(r*p/f(2.) - q*q/f(8.), -r, -p/f(2.), T::one());
+        let (z, u, v) = match resolvent.into_iter().rev().filter_map(split).next() {
+            Some(zuv) => zuv,
+            None => return Vec::new()
+        };
+        let v = if q < T::zero() { -v } else { v };
+        let mut roots = monic_quadratic(z - u, v);
+        roots.extend(monic_quadratic(z + u, -v));
+        roots
+    };
+
+    for x in &mut roots {
+        *x -= a/f(4.);
+        for _ in 0..3 {
+            let fx = (((c4*(*x) + c3)*(*x) + c2)*(*x) + c1)*(*x) + c0;
+            let dfx = ((f::<T>(4.)*c4*(*x) + f::<T>(3.)*c3)*(*x) + f::<T>(2.)*c2)*(*x) + c1;
+            if dfx == T::zero() {
+                break;
+            }
+            *x -= fx / dfx;
+        }
+    }
+    sorted(roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cubic, quadratic, quartic};
+
+    fn assert_roots(expected: &[f64], roots: &[f64]) {
+        assert_eq!(expected.len(), roots.len(), "{:?}", roots);
+        for (a, b) in expected.iter().zip(roots) {
+            assert_approx_eq!(*a, *b, 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_quadratic() {
+        assert_roots(&[-3., 2.], &quadratic(-6., 1., 1.));
+        assert_roots(&[], &quadratic(1., 0., 1.));
+        assert_roots(&[0.5], &quadratic(-1., 2., 0.));
+        // no cancellation for a tiny root next to a large one
+        assert_roots(&[1e-8, 1e8], &quadratic(1., -(1e8 + 1e-8), 1.));
+    }
+
+    #[test]
+    fn test_cubic() {
+        // (x - 1)(x + 2)(x - 3)
+        assert_roots(&[-2., 1., 3.], &cubic(6., -5., -2., 1.));
+        // (x - 2)(x^2 + 1)
+        assert_roots(&[2.], &cubic(-2., 1., -2., 1.));
+    }
+
+    #[test]
+    fn test_quartic() {
+        // (x - 1)(x + 2)(x - 3)(x + 0.5)
+        assert_roots(&[-2., -0.5, 1., 3.], &quartic(3., 3.5, -6., -1.5, 1.));
+        // (x^2 + 1)(x - 4)(x - 5)
+        assert_roots(&[4., 5.], &quartic(20., -9., 21., -9., 1.));
+        // (x^2 + 1)(x^2 + 2)
+        assert_roots(&[], &quartic(2., 0., 3., 0., 1.));
+        // scaled and with a zero root
+        assert_roots(&[-1., 0., 1., 2.], &quartic(0., 4., -2., -4., 2.));
+    }
+}
@@ -0,0 +1,195 @@
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+use rng::Sampler;
+use hitable::HitRecord;
+use material::Material;
+use microfacet::{self, Ggx};
+use onb::Onb;
+
+// Frosted glass: a GGX microfacet BSDF both reflecting and refracting, after
+// Walter et al., "Microfacet Models for Refraction through Rough Surfaces"
+// (2007), sampled by visible normals. Reflection or refraction is picked by
+// the Fresnel term, so the weights are close to one. Like Dielectric it
+// leaves out the 1/eta^2 change in radiance across the boundary, which
+// cancels out going in and out of a closed object. Below
+// microfacet::SMOOTH_ALPHA it's smooth glass.
+#[derive(Clone)]
+pub struct RoughDielectric<T: ElemT> {
+    ior: T,
+    ggx: Ggx<T>
+}
+
+impl<T: ElemT> RoughDielectric<T> {
+    pub fn new(ior: T, roughness: T) -> RoughDielectric<T> {
+        RoughDielectric { ior, ggx: Ggx::from_roughness(roughness) }
+    }
+
+    // The shading frame, with the normal on the side the ray came from, the
+    // direction back along the ray in it, and the index of the far side
+    // relative to the near one
+    fn frame(&self, r_in: &Ray<T>, rec: &HitRecord<T>) -> (Onb<T>, Vec3<T>, T) {
+        let wo = -r_in.direction().unit_vector();
+        let (n, eta) = if wo.dot(&rec.normal) < T::zero() { (-rec.normal.clone(), T::one() / self.ior) }
+                       else { (rec.normal.clone(), self.ior) };
+        let onb = Onb::from_w(&n);
+        let wo = onb.to_local(&wo);
+        (onb, wo, eta)
+    }
+
+    // BSDF times cosine, and the pdf of sampling wi, in the local frame. Also
+    // used by Principled for its transmission.
+    pub fn eval_local(&self, wo: &Vec3<T>, wi: &Vec3<T>, eta: T) -> (T, T) {
+        let (cos_o, cos_i) = (wo.z(), wi.z());
+        if cos_o <= T::zero() || cos_i == T::zero() {
+            return (T::zero(), T::zero());
+        }
+        let reflect = cos_i > T::zero();
+        // the microfacet normal that takes wo to wi
+        let mut h = if reflect { wo + wi } else { wi * eta + wo };
+        if h.squared_length() == T::zero() {
+            return (T::zero(), T::zero());
+        }
+        h = h.unit_vector();
+        if h.z() < T::zero() {
+            h = -h;
+        }
+        // not from microfacets facing away from either direction
+        if h.dot(wi) * cos_i < T::zero() || h.dot(wo) < T::zero() {
+            return (T::zero(), T::zero());
+        }
+        let f = microfacet::fresnel_dielectric(wo.dot(&h), eta);
+        let d = self.ggx.d(&h);
+        let pdf_h = self.ggx.pdf_visible(wo, &h);
+        if reflect {
+            let four = T::from_f64(4.).unwrap();
+            (f * d * self.ggx.g(wo, wi) / (four * cos_o), f * pdf_h / (four * wo.dot(&h)))
+        } else {
+            let denom = wi.dot(&h) + wo.dot(&h) / eta;
+            let denom = denom * denom;
+            let t = T::one() - f;
+            (t * d * self.ggx.g(wo, wi) * (wi.dot(&h) * wo.dot(&h)).abs() / (cos_o * denom),
+             t * pdf_h * wi.dot(&h).abs() / denom)
+        }
+    }
+
+    // A direction drawn from the BSDF in the local frame, for a rough surface
+    pub fn sample_local(&self, wo: &Vec3<T>, eta: T, sampler: &mut Sampler) -> Option<Vec3<T>> {
+        let h = self.ggx.sample_visible(wo, sampler);
+        let f = microfacet::fresnel_dielectric(wo.dot(&h), eta);
+        let reflect = T::from_f64(sampler.next_f64()).unwrap() < f;
+        let wi = if reflect { microfacet::reflect(wo, &h) } else { microfacet::refract(wo, &h, eta)? };
+        // lost if the microfacet sent it to the wrong side of the surface
+        if (wi.z() > T::zero()) != reflect {
+            return None;
+        }
+        Some(wi)
+    }
+
+    fn smooth_scatter(&self, wo: &Vec3<T>, eta: T, sampler: &mut Sampler) -> Vec3<T> {
+        let f = microfacet::fresnel_dielectric(wo.z(), eta);
+        let n = Vec3::new(T::zero(), T::zero(), T::one());
+        match microfacet::refract(wo, &n, eta) {
+            Some(wi) if T::from_f64(sampler.next_f64()).unwrap() >= f => wi,
+            _ => microfacet::reflect(wo, &n)
+        }
+    }
+}
+
+impl<T: ElemT> Material<T> for RoughDielectric<T> {
+    fn scatter(&self, r_in: &Ray<T>, rec: &HitRecord<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, Ray<T>)> {
+        let (onb, wo, eta) = self.frame(r_in, rec);
+        let scattered = |wi: Vec3<T>| Ray::with_time(rec.p.clone(), onb.local(wi.x(), wi.y(), wi.z()), r_in.time());
+        if self.ggx.is_smooth() {
+            return Some((Vec3::new(T::one(), T::one(), T::one()), scattered(self.smooth_scatter(&wo, eta, sampler))));
+        }
+        let wi = self.sample_local(&wo, eta, sampler)?;
+        let (value, pdf) = self.eval_local(&wo, &wi, eta);
+        if pdf <= T::zero() {
+            return None;
+        }
+        let w = value / pdf;
+        Some((Vec3::new(w, w, w), scattered(wi)))
+    }
+
+    fn eval(&self, r_in: &Ray<T>, rec: &HitRecord<T>, direction: &Vec3<T>) -> Option<(Vec3<T>, T)> {
+        if self.ggx.is_smooth() {
+            return None;
+        }
+        let (onb, wo, eta) = self.frame(r_in, rec);
+        let (value, pdf) = self.eval_local(&wo, &onb.to_local(&direction.unit_vector()), eta);
+        Some((Vec3::new(value, value, value), pdf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::RoughDielectric;
+    use vec3::Vec3;
+    use ray::Ray;
+    use hitable::HitRecord;
+    use material::Material;
+    use rng::Sampler;
+
+    fn record() -> HitRecord<'static, f64> {
+        HitRecord { p: Vec3::new(0., 0., 0.), normal: Vec3::new(0., 0., 1.), ..HitRecord::default() }
+    }
+
+    // Integrals over the whole sphere of eval's value and pdf
+    fn integrate(glass: &RoughDielectric<f64>, r: &Ray<f64>) -> (f64, f64) {
+        let rec = record();
+        let m = 400;
+        let (mut value, mut pdf) = (0., 0.);
+        for i in 0..2*m {
+            let cos = -1. + (i as f64 + 0.5) / m as f64;
+            let sin = (1. - cos*cos).sqrt();
+            for j in 0..m {
+                let phi = 2.*PI * (j as f64 + 0.5) / m as f64;
+                let (f, p) = glass.eval(r, &rec, &Vec3::new(sin*phi.cos(), sin*phi.sin(), cos)).unwrap();
+                value += f.x();
+                pdf += p;
+            }
+        }
+        let dw = 2.*PI / (m*m) as f64;
+        (value * dw, pdf * dw)
+    }
+
+    #[test]
+    fn test_energy() {
+        let mut sampler = Sampler::new(12);
+        let rec = record();
+        // from outside, and from inside but short of total internal reflection
+        for &(ref from, roughness) in &[(Vec3::new(-1., 0., 1.), 0.3), (Vec3::new(-0.3, 0., -1.), 0.5)] {
+            let glass = RoughDielectric::new(1.5, roughness);
+            let r = Ray::new(from.clone(), from * -1.);
+            let n = 100000;
+            let (mut sampled, mut reflected) = (0., 0);
+            for _ in 0..n {
+                if let Some((w, scattered)) = glass.scatter(&r, &rec, &mut sampler) {
+                    sampled += w.x();
+                    if scattered.direction().z() * from.z() > 0. {
+                        reflected += 1;
+                    }
+                }
+            }
+            let (value, pdf) = integrate(&glass, &r);
+            assert_approx_eq!(value, sampled / n as f64, 0.01);
+            // nearly all of it scatters somewhere, most of it through
+            assert!(value > 0.9 && value <= 1.01, "{}", value);
+            assert!(pdf <= 1.01);
+            assert!(reflected < n / 5);
+        }
+    }
+
+    #[test]
+    fn test_smooth() {
+        let mut sampler = Sampler::new(13);
+        let glass = RoughDielectric::new(1.5, 0.);
+        let r = Ray::new(Vec3::new(0., 0., 1.), Vec3::new(0., 0., -1.));
+        let n = 10000;
+        let reflected = (0..n).filter(|_| glass.scatter(&r, &record(), &mut sampler).unwrap().1.direction().z() > 0.).count();
+        assert_approx_eq!(0.04, reflected as f64 / n as f64, 0.01);
+        assert!(glass.eval(&r, &record(), &Vec3::new(0., 0., 1.)).is_none());
+    }
+}
@@ -0,0 +1,195 @@
+use std::f64::consts::PI;
+
+use vec3::{ElemT, Vec3};
+
+// Warps from uniform numbers u1, u2 (and u3) in [0, 1) to points or
+// directions distributed over some domain, each with the density it gives.
+// Directions are around the z axis and unit length; turn them into world
+// space with an Onb.
+
+fn t<T: ElemT>(x: f64) -> T {
+    T::from_f64(x).unwrap()
+}
+
+// From the polar angle's cosine and the azimuth
+fn spherical<T: ElemT>(cos_theta: T, phi: T) -> Vec3<T> {
+    let sin_theta = (T::one() - cos_theta*cos_theta).max(T::zero()).sqrt();
+    Vec3::new(sin_theta*phi.cos(), sin_theta*phi.sin(), cos_theta)
+}
+
+pub fn uniform_sphere<T: ElemT>(u1: T, u2: T) -> Vec3<T> {
+    spherical(T::one() - t::<T>(2.)*u1, t::<T>(2.*PI)*u2)
+}
+
+pub fn uniform_sphere_pdf<T: ElemT>() -> T {
+    t(1. / (4.*PI))
+}
+
+// Points inside the unit sphere, at a radius making the density even
+pub fn uniform_ball<T: ElemT>(u1: T, u2: T, u3: T) -> Vec3<T> {
+    uniform_sphere(u1, u2) * u3.cbrt()
+}
+
+// Nothing renders with the next few yet; they complete the set of warps and
+// densities for materials that will
+#[allow(dead_code)]
+pub fn uniform_ball_pdf<T: ElemT>() -> T {
+    t(3. / (4.*PI))
+}
+
+#[allow(dead_code)]
+pub fn uniform_hemisphere<T: ElemT>(u1: T, u2: T) -> Vec3<T> {
+    spherical(u1, t::<T>(2.*PI)*u2)
+}
+
+#[allow(dead_code)]
+pub fn uniform_hemisphere_pdf<T: ElemT>() -> T {
+    t(1. / (2.*PI))
+}
+
+// Shirley and Chiu's map from the square to the unit disk, which keeps
+// neighbouring samples together. Returns x and y.
+pub fn concentric_disk<T: ElemT>(u1: T, u2: T) -> (T, T) {
+    let (x, y) = (t::<T>(2.)*u1 - T::one(), t::<T>(2.)*u2 - T::one());
+    if x == T::zero() && y == T::zero() {
+        return (T::zero(), T::zero());
+    }
+    let quarter_pi = t::<T>(PI / 4.);
+    let (r, theta) = if x.abs() > y.abs() { (x, quarter_pi * (y / x)) }
+                     else { (y, t::<T>(PI / 2.) - quarter_pi * (x / y)) };
+    (r * theta.cos(), r * theta.sin())
+}
+
+pub fn concentric_disk_pdf<T: ElemT>() -> T {
+    t(1. / PI)
+}
+
+// Points on the disk lifted up onto the hemisphere, which makes the density
+// proportional to the cosine (Malley's method)
+pub fn cosine_hemisphere<T: ElemT>(u1: T, u2: T) -> Vec3<T> {
+    let (x, y) = concentric_disk(u1, u2);
+    Vec3::new(x, y, (T::one() - x*x - y*y).max(T::zero()).sqrt())
+}
+
+pub fn cosine_hemisphere_pdf<T: ElemT>(cos_theta: T) -> T {
+    cos_theta.max(T::zero()) / t(PI)
+}
+
+// Barycentric coordinates of a point drawn uniformly over a triangle, whose
+// density over the triangle is one over its area
+pub fn uniform_triangle<T: ElemT>(u1: T, u2: T) -> (T, T, T) {
+    let su = u1.sqrt();
+    (T::one() - su, su * (T::one() - u2), su * u2)
+}
+
+pub fn uniform_triangle_pdf<T: ElemT>(area: T) -> T {
+    T::one() / area
+}
+
+// Directions within the cone around z whose half angle has cosine
+// `cos_theta_max`. A cosine of -1 covers the whole sphere.
+pub fn uniform_cone<T: ElemT>(u1: T, u2: T, cos_theta_max: T) -> Vec3<T> {
+    spherical(T::one() - u1 + u1*cos_theta_max, t::<T>(2.*PI)*u2)
+}
+
+pub fn uniform_cone_pdf<T: ElemT>(cos_theta_max: T) -> T {
+    T::one() / (t::<T>(2.*PI) * (T::one() - cos_theta_max))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::{concentric_disk, concentric_disk_pdf, cosine_hemisphere, cosine_hemisphere_pdf, uniform_ball,
+                uniform_ball_pdf, uniform_cone, uniform_cone_pdf, uniform_hemisphere, uniform_hemisphere_pdf,
+                uniform_sphere, uniform_sphere_pdf, uniform_triangle, uniform_triangle_pdf};
+    use vec3::Vec3;
+
+    // The average of `f` over an n by n grid of stratified (u1, u2)
+    fn mean<F: FnMut(f64, f64) -> f64>(mut f: F) -> f64 {
+        let n = 300;
+        let mut sum = 0.;
+        for i in 0..n {
+            for j in 0..n {
+                sum += f((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+            }
+        }
+        sum / (n*n) as f64
+    }
+
+    // The integral of `f` over the directions with cos theta above `z0`,
+    // by midpoint sums in cos theta and phi
+    fn integrate<F: FnMut(&Vec3<f64>) -> f64>(z0: f64, mut f: F) -> f64 {
+        let n = 300;
+        let mut sum = 0.;
+        for i in 0..n {
+            let z = z0 + (1. - z0) * (i as f64 + 0.5) / n as f64;
+            for j in 0..n {
+                let phi = 2.*PI * (j as f64 + 0.5) / n as f64;
+                let s = (1. - z*z).sqrt();
+                sum += f(&Vec3::new(s*phi.cos(), s*phi.sin(), z));
+            }
+        }
+        sum * (1. - z0) * 2.*PI / (n*n) as f64
+    }
+
+    #[test]
+    fn test_directions() {
+        // unit length, on the right side, with the moments of the density
+        // the pdf describes, which integrates to one
+        assert_approx_eq!(1.0, mean(|u1, u2| uniform_sphere(u1, u2).length()));
+        assert_approx_eq!(0.0, mean(|u1, u2| uniform_sphere(u1, u2).z()), 1e-3);
+        assert_approx_eq!(1. / 3., mean(|u1, u2| uniform_sphere(u1, u2).z().powi(2)), 1e-3);
+        assert_approx_eq!(1.0, integrate(-1., |_| uniform_sphere_pdf()), 1e-3);
+
+        assert!(mean(|u1, u2| if uniform_hemisphere(u1, u2).z() >= 0. { 1. } else { 0. }) == 1.);
+        assert_approx_eq!(0.5, mean(|u1, u2| uniform_hemisphere(u1, u2).z()), 1e-3);
+        assert_approx_eq!(1.0, integrate(0., |_| uniform_hemisphere_pdf()), 1e-3);
+
+        assert_approx_eq!(1.0, mean(|u1, u2| cosine_hemisphere(u1, u2).length()));
+        assert!(mean(|u1, u2| if cosine_hemisphere(u1, u2).z() >= 0. { 1. } else { 0. }) == 1.);
+        let pdf = |w: &Vec3<f64>| cosine_hemisphere_pdf(w.z());
+        assert_approx_eq!(integrate(0., |w| w.z() * pdf(w)), mean(|u1, u2| cosine_hemisphere(u1, u2).z()), 1e-3);
+        assert_approx_eq!(2. / 3., mean(|u1, u2| cosine_hemisphere(u1, u2).z()), 1e-3);
+        assert_approx_eq!(1.0, integrate(0., pdf), 1e-3);
+
+        let cos_max = 0.8;
+        assert!(mean(|u1, u2| if uniform_cone(u1, u2, cos_max).z() >= cos_max { 1. } else { 0. }) == 1.);
+        assert_approx_eq!(0.9, mean(|u1, u2| uniform_cone(u1, u2, cos_max).z()), 1e-3);
+        assert_approx_eq!(1.0, integrate(cos_max, |_| uniform_cone_pdf(cos_max)), 1e-3);
+        // the whole sphere
+        assert_approx_eq!(uniform_sphere_pdf::<f64>(), uniform_cone_pdf(-1.));
+    }
+
+    #[test]
+    fn test_points() {
+        let r2 = |u1, u2| { let (x, y) = concentric_disk(u1, u2); x*x + y*y };
+        assert!(mean(|u1, u2| if r2(u1, u2) <= 1. { 1. } else { 0. }) == 1.);
+        assert_approx_eq!(0.5, mean(r2), 1e-3);
+        assert_approx_eq!(0.0, mean(|u1, u2| concentric_disk(u1, u2).0), 1e-3);
+        // the fraction inside half the radius is its share of the area
+        assert_approx_eq!(0.25 / PI, mean(|u1, u2| if r2(u1, u2) < 0.25 { 1. } else { 0. }) * concentric_disk_pdf::<f64>(), 1e-3);
+
+        // inside the sphere, not outside it
+        assert!(mean(|u1, u2| uniform_ball(u1, u2, (u1 + u2) / 2.).length()) < 1.);
+        let n = 50;
+        let mut sum = 0.;
+        for k in 0..n {
+            let u3 = (k as f64 + 0.5) / n as f64;
+            sum += mean(|u1, u2| {
+                let p = uniform_ball(u1, u2, u3);
+                assert!(p.length() < 1.);
+                p.squared_length()
+            });
+        }
+        assert_approx_eq!(0.6, sum / n as f64, 1e-3);
+        assert_approx_eq!(4. / 3. * PI, 1. / uniform_ball_pdf::<f64>());
+
+        let b = |u1, u2| uniform_triangle(u1, u2);
+        assert_approx_eq!(1.0, mean(|u1, u2| { let (b0, b1, b2) = b(u1, u2); b0 + b1 + b2 }));
+        for &c in &[0, 1, 2] {
+            assert_approx_eq!(1. / 3., mean(|u1, u2| { let b = b(u1, u2); [b.0, b.1, b.2][c] }), 1e-3);
+        }
+        assert_approx_eq!(0.5, uniform_triangle_pdf(2f64));
+    }
+}
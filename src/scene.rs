@@ -0,0 +1,1750 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use toml;
+use toml::Value;
+use toml::value::Table;
+
+use vec3::{ElemT, Vec3};
+use mat4::Mat4;
+use aabb::Aabb;
+use hitable::Hitable;
+use bvh::BvhNode;
+use transformed::Transformed;
+use animated::{Animated, Keyframe};
+use camera::Camera;
+use material::Material;
+use sphere::Sphere;
+use movingsphere::MovingSphere;
+use rect::{self, Parallelogram};
+use triangle::Triangle;
+use aabox::AaBox;
+use plane::{Disk, Plane};
+use quadric::{Cone, Cylinder, Hyperboloid, Paraboloid};
+use torus::Torus;
+use csg::{Csg, CsgOp};
+use sdf::{self, Field, Sdf};
+use constantmedium::ConstantMedium;
+use voxelgrid::{GridError, VoxelGrid};
+use volume::Volume;
+use lambertian::Lambertian;
+use metal::Metal;
+use dielectric::{Dielectric, Ior, WAVELENGTHS};
+use conductor::{self, Conductor};
+use roughdielectric::RoughDielectric;
+use principled::{self, Principled};
+use diffuselight::DiffuseLight;
+use phase::{HenyeyGreenstein, Isotropic};
+use objloader::{self, ObjError};
+use texture::{CheckerTexture, ConstantTexture, Texture};
+use noise::{NoisePattern, NoiseTexture};
+use imagetexture::{ImageTexture, TextureError, WrapMode};
+use render::Background;
+use atmosphere::Atmosphere;
+use rng::Sampler;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(String, io::Error),
+    Syntax(String, toml::de::Error),
+    Key { key: String, msg: String },
+    Obj(ObjError),
+    Texture(TextureError),
+    Grid(GridError)
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SceneError::Io(ref file, ref err) => write!(f, "{}: {}", file, err),
+            SceneError::Syntax(ref file, ref err) => write!(f, "{}: {}", file, err),
+            SceneError::Key { ref key, ref msg } => write!(f, "{}: {}", key, msg),
+            SceneError::Obj(ref err) => write!(f, "{}", err),
+            SceneError::Texture(ref err) => write!(f, "{}", err),
+            SceneError::Grid(ref err) => write!(f, "{}", err)
+        }
+    }
+}
+
+impl From<ObjError> for SceneError {
+    fn from(err: ObjError) -> SceneError {
+        SceneError::Obj(err)
+    }
+}
+
+impl From<TextureError> for SceneError {
+    fn from(err: TextureError) -> SceneError {
+        SceneError::Texture(err)
+    }
+}
+
+impl From<GridError> for SceneError {
+    fn from(err: GridError) -> SceneError {
+        SceneError::Grid(err)
+    }
+}
+
+fn key_error<R>(key: &str, msg: &str) -> Result<R, SceneError> {
+    Err(SceneError::Key { key: key.to_string(), msg: msg.to_string() })
+}
+
+fn t<T: ElemT>(x: f64) -> T {
+    T::from_f64(x).unwrap()
+}
+
+// A TOML table plus the dotted path that leads to it, so that errors can
+// name the full key (e.g. `objects[3].radius`).
+#[derive(Clone)]
+struct Section<'a> {
+    path: String,
+    table: &'a Table
+}
+
+impl<'a> Section<'a> {
+    fn new(path: String, value: &'a Value) -> Result<Section<'a>, SceneError> {
+        match value.as_table() {
+            Some(table) => Ok(Section { path, table }),
+            None => key_error(&path, "expected a table")
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        if self.path.is_empty() { key.to_string() } else { format!("{}.{}", self.path, key) }
+    }
+
+    // Rejects keys we don't understand so typos don't pass silently
+    fn check_keys(&self, allowed: &[&str]) -> Result<(), SceneError> {
+        for key in self.table.keys() {
+            if !allowed.contains(&key.as_str()) {
+                return key_error(&self.key(key), "unknown key");
+            }
+        }
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Result<&'a Value, SceneError> {
+        match self.table.get(key) {
+            Some(v) => Ok(v),
+            None => key_error(&self.key(key), "missing required key")
+        }
+    }
+
+    fn section(&self, key: &str) -> Result<Option<Section<'a>>, SceneError> {
+        match self.table.get(key) {
+            Some(v) => Section::new(self.key(key), v).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    fn float(&self, key: &str) -> Result<f64, SceneError> {
+        match *self.get(key)? {
+            Value::Float(x) => Ok(x),
+            Value::Integer(i) => Ok(i as f64),
+            _ => key_error(&self.key(key), "expected a number")
+        }
+    }
+
+    fn float_or(&self, key: &str, default: f64) -> Result<f64, SceneError> {
+        if self.table.contains_key(key) { self.float(key) } else { Ok(default) }
+    }
+
+    fn positive(&self, key: &str) -> Result<f64, SceneError> {
+        match self.float(key)? {
+            x if x > 0. => Ok(x),
+            _ => key_error(&self.key(key), "expected a positive number")
+        }
+    }
+
+    // The mean cosine of a phase function, strictly between -1 and 1
+    fn mean_cosine(&self, key: &str) -> Result<f64, SceneError> {
+        match self.float_or(key, 0.)? {
+            g if g > -1. && g < 1. => Ok(g),
+            _ => key_error(&self.key(key), "expected a number between -1 and 1")
+        }
+    }
+
+    // Microfacet roughness, from 0 for smooth to 1, defaulting to smooth
+    fn roughness(&self, key: &str) -> Result<f64, SceneError> {
+        match self.float_or(key, 0.)? {
+            r if (0. ..=1.).contains(&r) => Ok(r),
+            _ => key_error(&self.key(key), "expected a number between 0 and 1")
+        }
+    }
+
+    fn bool_or(&self, key: &str, default: bool) -> Result<bool, SceneError> {
+        match self.table.get(key) {
+            Some(&Value::Boolean(b)) => Ok(b),
+            Some(_) => key_error(&self.key(key), "expected true or false"),
+            None => Ok(default)
+        }
+    }
+
+    fn uint(&self, key: &str) -> Result<u64, SceneError> {
+        match *self.get(key)? {
+            Value::Integer(i) if i >= 0 => Ok(i as u64),
+            _ => key_error(&self.key(key), "expected a non-negative integer")
+        }
+    }
+
+    fn uint_or(&self, key: &str, default: u64) -> Result<u64, SceneError> {
+        if self.table.contains_key(key) { self.uint(key) } else { Ok(default) }
+    }
+
+    fn count_or(&self, key: &str, default: usize) -> Result<usize, SceneError> {
+        match self.uint_or(key, default as u64)? {
+            0 => key_error(&self.key(key), "expected a positive integer"),
+            n => Ok(n as usize)
+        }
+    }
+
+    fn string(&self, key: &str) -> Result<&'a str, SceneError> {
+        match self.get(key)?.as_str() {
+            Some(s) => Ok(s),
+            None => key_error(&self.key(key), "expected a string")
+        }
+    }
+
+    fn vec3<T: ElemT>(&self, key: &str) -> Result<Vec3<T>, SceneError> {
+        let xs = match self.get(key)?.as_array() {
+            Some(xs) if xs.len() == 3 => xs,
+            _ => return key_error(&self.key(key), "expected an array of 3 numbers")
+        };
+        let mut v = Vec3::default();
+        for (i, x) in xs.iter().enumerate() {
+            v[i] = match *x {
+                Value::Float(x) => t(x),
+                Value::Integer(x) => t(x as f64),
+                _ => return key_error(&self.key(key), "expected an array of 3 numbers")
+            };
+        }
+        Ok(v)
+    }
+
+    fn vec3_or<T: ElemT>(&self, key: &str, default: Vec3<T>) -> Result<Vec3<T>, SceneError> {
+        if self.table.contains_key(key) { self.vec3(key) } else { Ok(default) }
+    }
+
+    // A number stands for the same value on every axis or channel
+    fn vec3_or_scalar<T: ElemT>(&self, key: &str) -> Result<Vec3<T>, SceneError> {
+        match *self.get(key)? {
+            Value::Float(_) | Value::Integer(_) => {
+                let k = t(self.float(key)?);
+                Ok(Vec3::new(k, k, k))
+            }
+            _ => self.vec3(key)
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    pub samples: usize,
+    pub max_depth: usize,
+    pub seed: u64
+}
+
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings {
+            width: 1200,
+            height: 800,
+            samples: 10,
+            max_depth: 50,
+            seed: 0
+        }
+    }
+}
+
+// Everything Camera::new needs except the aspect ratio, which isn't known
+// until the output resolution is settled.
+#[derive(Clone)]
+pub struct CameraSettings<T: ElemT> {
+    pub lookfrom: Vec3<T>,
+    pub lookat: Vec3<T>,
+    pub vup: Vec3<T>,
+    pub vfov: T,
+    pub aperture: T,
+    pub focus_dist: T,
+    pub shutter_open: T,
+    pub shutter_close: T
+}
+
+impl<T: ElemT> CameraSettings<T> {
+    pub fn build(&self, aspect: T) -> Camera<T> {
+        Camera::new(self.lookfrom.clone(), self.lookat.clone(), self.vup.clone(),
+                    self.vfov, aspect, self.aperture, self.focus_dist)
+            .with_shutter(self.shutter_open, self.shutter_close)
+    }
+}
+
+pub struct Scene<T: ElemT> {
+    pub world: BvhNode<T>,
+    pub camera: CameraSettings<T>,
+    pub background: Background<T>,
+    pub atmosphere: Option<Atmosphere<T>>,
+    pub render: RenderSettings
+}
+
+///////////////
+// Generator //
+///////////////
+
+// The random sphere field from the end of the book, standing on a ground
+// sphere with the given albedo, laid out by `sampler`
+pub fn random_spheres<T: ElemT>(sampler: &mut Sampler, ground: Arc<dyn Texture<T>>) -> Vec<Box<dyn Hitable<T>>> {
+    let vec3 = |x: f64, y: f64, z: f64| Vec3::<T>::new(t(x), t(y), t(z));
+    let mut list = Vec::<Box<dyn Hitable<T>>>::new();
+    list.push(Box::new(Sphere::new(vec3(0., -1000., 0.), t(1000.), Box::new(Lambertian::with_texture(ground)))));
+    for a in -11..12 { // TODO: better way to write inclusive?
+        for b in -11..12 {
+            let a = a as f64;
+            let b = b as f64;
+            let choose_mat = sampler.next_f64();
+            let center = vec3(a+0.9*sampler.next_f64(), 0.2, b+0.9*sampler.next_f64());
+            if (&center - vec3(4., 0.2, 0.)).length() > t(0.9) {
+                if choose_mat < 0.8 { // diffuse
+                    let r1 = sampler.next_f64();
+                    let r2 = sampler.next_f64();
+                    let r3 = sampler.next_f64();
+                    let r4 = sampler.next_f64();
+                    let r5 = sampler.next_f64();
+                    let r6 = sampler.next_f64();
+                    list.push(Box::new(Sphere::new(center, t(0.2), Box::new(Lambertian::new(vec3(r1*r2, r3*r4, r5*r6))))));
+                }
+                else if choose_mat < 0.95 { // metal
+                    let r1 = sampler.next_f64();
+                    let r2 = sampler.next_f64();
+                    let r3 = sampler.next_f64();
+                    let r4 = sampler.next_f64();
+                    list.push(Box::new(Sphere::new(center, t(0.2), Box::new(Metal::new(vec3(0.5*(1. + r1), 0.5*(1.+r2), 0.5*(1.+r3)), t(0.5*r4))))));
+                }
+                else { // glass
+                    list.push(Box::new(Sphere::new(center, t(0.2), Box::new(Dielectric::new(t(1.5))))));
+                }
+            }
+        }
+    }
+    list.push(Box::new(Sphere::new(vec3(0., 1., 0.), t(1.), Box::new(Dielectric::new(t(1.5))))));
+    list.push(Box::new(Sphere::new(vec3(-4., 1., 0.), t(1.), Box::new(Lambertian::new(vec3(0.4, 0.2, 0.1))))));
+    list.push(Box::new(Sphere::new(vec3(4., 1., 0.), t(1.), Box::new(Metal::new(vec3(0.7, 0.6, 0.5), t(0.))))));
+    list
+}
+
+pub fn random_scene<T: ElemT>() -> Scene<T> {
+    Scene {
+        // fixed seed so every run (and every thread count) sees the same scene
+        world: BvhNode::new(random_spheres(&mut Sampler::new(0), Arc::new(ConstantTexture::new(Vec3::new(t(0.5), t(0.5), t(0.5)))))),
+        camera: CameraSettings {
+            lookfrom: Vec3::new(t(13.), t(2.), t(3.)),
+            lookat: Vec3::new(t(0.), t(0.), t(0.)),
+            vup: Vec3::new(t(0.), t(1.), t(0.)),
+            vfov: t(20.),
+            aperture: t(0.1),
+            focus_dist: t(10.),
+            shutter_open: T::zero(),
+            shutter_close: T::zero()
+        },
+        background: Background::default(),
+        atmosphere: None,
+        render: RenderSettings::default()
+    }
+}
+
+////////////
+// Loader //
+////////////
+
+// Textures are built the first time they're used and then shared by every
+// material referring to them, so image files are only loaded once.
+struct Textures<'a, T: ElemT> {
+    sections: HashMap<String, Section<'a>>,
+    built: HashMap<String, Arc<dyn Texture<T>>>,
+    building: HashSet<String>,
+    dir: &'a Path
+}
+
+impl<'a, T: ElemT> Textures<'a, T> {
+    fn get(&mut self, key: &str, name: &str) -> Result<Arc<dyn Texture<T>>, SceneError> {
+        if let Some(tex) = self.built.get(name) {
+            return Ok(tex.clone());
+        }
+        let s = match self.sections.get(name) {
+            Some(s) => s.clone(),
+            None => return key_error(key, &format!("no texture named '{}'", name))
+        };
+        if !self.building.insert(name.to_string()) {
+            return key_error(key, &format!("texture '{}' refers to itself", name));
+        }
+        let tex = self.build(&s)?;
+        self.building.remove(name);
+        self.built.insert(name.to_string(), tex.clone());
+        Ok(tex)
+    }
+
+    // Either a colour or the name of a texture
+    fn color_or_texture(&mut self, s: &Section, key: &str) -> Result<Arc<dyn Texture<T>>, SceneError> {
+        match *s.get(key)? {
+            Value::String(ref name) => self.get(&s.key(key), name),
+            _ => Ok(Arc::new(ConstantTexture::new(s.vec3(key)?)))
+        }
+    }
+
+    // Either a number from 0 to 1 or the name of a texture, if it's there
+    fn fraction_or_texture(&mut self, s: &Section, key: &str) -> Result<Option<Arc<dyn Texture<T>>>, SceneError> {
+        match s.table.get(key) {
+            None => Ok(None),
+            Some(Value::String(name)) => self.get(&s.key(key), name).map(Some),
+            Some(_) => match s.float(key)? {
+                x if (0. ..=1.).contains(&x) => Ok(Some(principled::grey(t(x)))),
+                _ => key_error(&s.key(key), "expected a number between 0 and 1 or a texture")
+            }
+        }
+    }
+
+    fn build(&mut self, s: &Section) -> Result<Arc<dyn Texture<T>>, SceneError> {
+        match s.string("type")? {
+            "constant" => {
+                s.check_keys(&["type", "color"])?;
+                Ok(Arc::new(ConstantTexture::new(s.vec3("color")?)))
+            }
+            "checker" => {
+                s.check_keys(&["type", "odd", "even", "scale"])?;
+                let odd = self.color_or_texture(s, "odd")?;
+                let even = self.color_or_texture(s, "even")?;
+                Ok(Arc::new(CheckerTexture::new(odd, even, t(s.float_or("scale", 10.)?))))
+            }
+            "noise" => {
+                s.check_keys(&["type", "pattern", "seed", "scale", "octaves", "low", "high"])?;
+                let pattern = match s.table.get("pattern") {
+                    None => NoisePattern::Perlin,
+                    Some(_) => match NoisePattern::from_name(s.string("pattern")?) {
+                        Some(p) => p,
+                        None => return key_error(&s.key("pattern"),
+                                                 "expected \"perlin\", \"turbulence\", \"fbm\", \"marble\" or \"wood\"")
+                    }
+                };
+                Ok(Arc::new(NoiseTexture::new(s.uint_or("seed", 0)?, pattern, t(s.float_or("scale", 1.)?),
+                                              s.count_or("octaves", 7)?,
+                                              s.vec3_or("low", Vec3::new(T::zero(), T::zero(), T::zero()))?,
+                                              s.vec3_or("high", Vec3::new(T::one(), T::one(), T::one()))?)))
+            }
+            "image" => {
+                s.check_keys(&["type", "file", "wrap"])?;
+                let wrap = match s.table.get("wrap") {
+                    None => WrapMode::Repeat,
+                    Some(_) => match WrapMode::from_name(s.string("wrap")?) {
+                        Some(w) => w,
+                        None => return key_error(&s.key("wrap"), "expected \"repeat\", \"clamp\" or \"mirror\"")
+                    }
+                };
+                Ok(Arc::new(ImageTexture::load(&self.dir.join(s.string("file")?), wrap)?))
+            }
+            _ => key_error(&s.key("type"), "unknown texture type")
+        }
+    }
+}
+
+fn build_material<T: ElemT>(s: &Section, textures: &mut Textures<T>) -> Result<Box<dyn Material<T>>, SceneError> {
+    match s.string("type")? {
+        "lambertian" => {
+            s.check_keys(&["type", "albedo"])?;
+            Ok(Box::new(Lambertian::with_texture(textures.color_or_texture(s, "albedo")?)))
+        }
+        "metal" => {
+            s.check_keys(&["type", "albedo", "fuzz"])?;
+            Ok(Box::new(Metal::with_texture(textures.color_or_texture(s, "albedo")?, t(s.float_or("fuzz", 0.)?))))
+        }
+        "dielectric" => {
+            s.check_keys(&["type", "ref_idx", "glass", "cauchy_a", "cauchy_b", "sellmeier_b", "sellmeier_c", "absorption"])?;
+            // the index of refraction, fixed or varying with wavelength
+            let sources = ["ref_idx", "glass", "cauchy_a", "sellmeier_b"];
+            let given: Vec<&str> = sources.iter().cloned().filter(|key| s.table.contains_key(*key)).collect();
+            if given.len() > 1 {
+                return key_error(&s.key(given[1]), &format!("can't be given with {}", given[0]));
+            }
+            for &(key, first) in &[("cauchy_b", "cauchy_a"), ("sellmeier_c", "sellmeier_b")] {
+                if s.table.contains_key(key) && !s.table.contains_key(first) {
+                    return key_error(&s.key(key), &format!("needs {}", first));
+                }
+            }
+            let ior = match given.first() {
+                Some(&"glass") => match Ior::from_name(s.string("glass")?) {
+                    Some(ior) => ior,
+                    None => return key_error(&s.key("glass"), "expected bk7, sf11 or diamond")
+                },
+                Some(&"cauchy_a") => Ior::Cauchy(t(s.positive("cauchy_a")?), t(s.float_or("cauchy_b", 0.)?)),
+                Some(&"sellmeier_b") => {
+                    let (b, c): (Vec3<T>, Vec3<T>) = (s.vec3("sellmeier_b")?, s.vec3("sellmeier_c")?);
+                    let ior = Ior::Sellmeier([b[0], b[1], b[2]], [c[0], c[1], c[2]]);
+                    // n^2 has poles where a c[i] is a wavelength squared and
+                    // can drop below 1 past them
+                    if WAVELENGTHS.iter().any(|&l| { let n = ior.at(t(l)); !(n > T::one() && n.is_finite()) }) {
+                        return key_error(&s.key("sellmeier_c"), "gives no index of refraction above 1 at some wavelengths");
+                    }
+                    ior
+                }
+                _ => Ior::Constant(t(s.positive("ref_idx")?))
+            };
+            let dielectric = Dielectric::with_ior(ior);
+            if !s.table.contains_key("absorption") {
+                return Ok(Box::new(dielectric));
+            }
+            let absorption: Vec3<T> = s.vec3_or_scalar("absorption")?;
+            if (0..3).any(|c| absorption[c] < T::zero()) {
+                return key_error(&s.key("absorption"), "expected non-negative numbers");
+            }
+            Ok(Box::new(dielectric.with_absorption(absorption)))
+        }
+        "conductor" => {
+            s.check_keys(&["type", "metal", "eta", "k", "roughness"])?;
+            // a named metal, or its complex index of refraction
+            let (eta, k) = if s.table.contains_key("metal") {
+                match conductor::preset(s.string("metal")?) {
+                    Some(ior) => ior,
+                    None => return key_error(&s.key("metal"), "expected gold, silver, copper or aluminium")
+                }
+            } else {
+                (s.vec3("eta")?, s.vec3("k")?)
+            };
+            Ok(Box::new(Conductor::new(eta, k, t(s.roughness("roughness")?))))
+        }
+        "rough_dielectric" => {
+            s.check_keys(&["type", "ref_idx", "roughness"])?;
+            Ok(Box::new(RoughDielectric::new(t(s.positive("ref_idx")?), t(s.roughness("roughness")?))))
+        }
+        "principled" => {
+            s.check_keys(&["type", "base_color", "metallic", "roughness", "specular", "sheen", "clearcoat",
+                           "clearcoat_roughness", "transmission", "subsurface", "anisotropic", "ior"])?;
+            let base_color = if s.table.contains_key("base_color") { textures.color_or_texture(s, "base_color")? }
+                             else { Arc::new(ConstantTexture::new(Vec3::new(t(0.8), t(0.8), t(0.8)))) };
+            let mut m = Principled::new(base_color);
+            {
+                let params: [(&str, &mut Arc<dyn Texture<T>>); 9] = [
+                    ("metallic", &mut m.metallic), ("roughness", &mut m.roughness), ("specular", &mut m.specular),
+                    ("sheen", &mut m.sheen), ("clearcoat", &mut m.clearcoat),
+                    ("clearcoat_roughness", &mut m.clearcoat_roughness), ("transmission", &mut m.transmission),
+                    ("subsurface", &mut m.subsurface), ("anisotropic", &mut m.anisotropic)
+                ];
+                for (key, param) in params {
+                    if let Some(tex) = textures.fraction_or_texture(s, key)? {
+                        *param = tex;
+                    }
+                }
+            }
+            m.ior = t(s.float_or("ior", 1.5)?);
+            if m.ior <= T::zero() {
+                return key_error(&s.key("ior"), "expected a positive number");
+            }
+            Ok(Box::new(m))
+        }
+        "diffuse_light" => {
+            s.check_keys(&["type", "emit"])?;
+            Ok(Box::new(DiffuseLight::new(s.vec3("emit")?)))
+        }
+        // for media
+        "isotropic" => {
+            s.check_keys(&["type", "albedo"])?;
+            Ok(Box::new(Isotropic::with_texture(textures.color_or_texture(s, "albedo")?)))
+        }
+        "henyey_greenstein" => {
+            s.check_keys(&["type", "albedo", "g"])?;
+            Ok(Box::new(HenyeyGreenstein::with_texture(textures.color_or_texture(s, "albedo")?, t(s.mean_cosine("g")?))))
+        }
+        _ => key_error(&s.key("type"), "unknown material type")
+    }
+}
+
+// Objects refer to materials by name; each reference gets its own instance.
+fn object_material<T: ElemT>(s: &Section, materials: &HashMap<String, Section>, textures: &mut Textures<T>) -> Result<Box<dyn Material<T>>, SceneError> {
+    let name = s.string("material")?;
+    match materials.get(name) {
+        Some(m) => build_material(m, textures),
+        None => key_error(&s.key("material"), &format!("no material named '{}'", name))
+    }
+}
+
+// How far round its axis a surface of revolution goes, in degrees, all the
+// way by default
+fn sweep(s: &Section) -> Result<f64, SceneError> {
+    match s.float_or("sweep", 360.)? {
+        x if x > 0. => Ok(x),
+        _ => key_error(&s.key("sweep"), "expected a positive angle")
+    }
+}
+
+// A negative radius turns a sphere's normals inwards, making it hollow like
+// the inside of a glass bubble
+fn sphere_radius(s: &Section) -> Result<f64, SceneError> {
+    match s.float("radius")? {
+        r if r < 0. => Ok(r),
+        _ => s.positive("radius")
+    }
+}
+
+// A direction, which can't be all zeroes
+fn nonzero_vec3<T: ElemT>(s: &Section, key: &str) -> Result<Vec3<T>, SceneError> {
+    let v: Vec3<T> = s.vec3(key)?;
+    if v.squared_length() == T::zero() {
+        return key_error(&s.key(key), "expected a non-zero vector");
+    }
+    Ok(v)
+}
+
+// Keys any object can have to place it with a Transformed
+const TRANSFORM_KEYS: [&str; 3] = ["scale", "rotate", "translate"];
+
+// Scale (a number or per axis), then rotation about x, y and z in turn (in
+// degrees), then translation, at `time`
+fn build_keyframe<T: ElemT>(s: &Section, time: T) -> Result<Keyframe<T>, SceneError> {
+    let scale = if s.table.contains_key("scale") { s.vec3_or_scalar("scale")? }
+                else { Vec3::new(T::one(), T::one(), T::one()) };
+    if scale.x()*scale.y()*scale.z() == T::zero() {
+        return key_error(&s.key("scale"), "scale can't be zero");
+    }
+    let keyframe = Keyframe {
+        time,
+        scale,
+        rotate: s.vec3_or("rotate", Vec3::default())?,
+        translate: s.vec3_or("translate", Vec3::default())?
+    };
+    for &(key, v) in &[("scale", &keyframe.scale), ("rotate", &keyframe.rotate), ("translate", &keyframe.translate)] {
+        if (0..3).any(|a| !v[a].is_finite()) {
+            return key_error(&s.key(key), "expected finite numbers");
+        }
+    }
+    if keyframe.matrix().inverse().is_none() {
+        return key_error(&s.key("scale"), "transform can't be inverted");
+    }
+    Ok(keyframe)
+}
+
+// None if the object has none of the transform keys
+fn build_transform<T: ElemT>(s: &Section) -> Result<Option<Mat4<T>>, SceneError> {
+    if !TRANSFORM_KEYS.iter().any(|k| s.table.contains_key(*k)) {
+        return Ok(None);
+    }
+    Ok(Some(build_keyframe(s, T::zero())?.matrix()))
+}
+
+// An array of tables, each with a `time` and any of the transform keys
+fn build_keyframes<T: ElemT>(s: &Section) -> Result<Vec<Keyframe<T>>, SceneError> {
+    let frames = match s.get("keyframes")?.as_array() {
+        Some(frames) if !frames.is_empty() => frames,
+        _ => return key_error(&s.key("keyframes"), "expected an array of tables")
+    };
+    let mut keyframes = Vec::new();
+    for (i, value) in frames.iter().enumerate() {
+        let k = Section::new(format!("{}[{}]", s.key("keyframes"), i), value)?;
+        k.check_keys(&["time", "scale", "rotate", "translate"])?;
+        keyframes.push(build_keyframe(&k, t(k.float("time")?))?);
+    }
+    Ok(keyframes)
+}
+
+// A distance field from a table with its `type`, which is a primitive or a
+// combinator of other such tables. Any of them can be moved by `translate`.
+fn build_field<T: ElemT>(s: &Section) -> Result<Box<dyn Field<T>>, SceneError> {
+    let child = |key: &str| -> Result<Box<dyn Field<T>>, SceneError> {
+        match s.section(key)? {
+            Some(shape) => build_field(&shape),
+            None => key_error(&s.key(key), "missing required key")
+        }
+    };
+    let children = |key: &str| -> Result<Vec<Box<dyn Field<T>>>, SceneError> {
+        let shapes = match s.get(key)?.as_array() {
+            Some(shapes) if !shapes.is_empty() => shapes,
+            _ => return key_error(&s.key(key), "expected an array of tables")
+        };
+        let mut fields = Vec::new();
+        for (i, value) in shapes.iter().enumerate() {
+            fields.push(build_field(&Section::new(format!("{}[{}]", s.key(key), i), value)?)?);
+        }
+        Ok(fields)
+    };
+
+    let field: Box<dyn Field<T>> = match s.string("type")? {
+        "sphere" => {
+            s.check_keys(&["type", "translate", "radius"])?;
+            Box::new(sdf::SphereField { radius: t(s.positive("radius")?) })
+        }
+        "box" => {
+            s.check_keys(&["type", "translate", "size"])?;
+            let size: Vec3<T> = s.vec3("size")?;
+            if (0..3).any(|a| size[a] <= T::zero()) {
+                return key_error(&s.key("size"), "expected positive sizes");
+            }
+            Box::new(sdf::BoxField { half: size / t::<T>(2.) })
+        }
+        "torus" => {
+            s.check_keys(&["type", "translate", "major", "minor"])?;
+            Box::new(sdf::TorusField { major: t(s.positive("major")?), minor: t(s.positive("minor")?) })
+        }
+        "mandelbulb" => {
+            s.check_keys(&["type", "translate", "power", "iterations"])?;
+            Box::new(sdf::Mandelbulb { power: t(s.float_or("power", 8.)?), iterations: s.count_or("iterations", 10)? })
+        }
+        "union" => {
+            s.check_keys(&["type", "translate", "shapes"])?;
+            Box::new(sdf::Union { fields: children("shapes")? })
+        }
+        "smooth_union" => {
+            s.check_keys(&["type", "translate", "shapes", "k"])?;
+            Box::new(sdf::SmoothUnion { fields: children("shapes")?, k: t(s.positive("k")?) })
+        }
+        "repeat" => {
+            s.check_keys(&["type", "translate", "shape", "period"])?;
+            Box::new(sdf::Repeat { field: child("shape")?, period: s.vec3("period")? })
+        }
+        "twist" => {
+            // degrees per unit of height
+            s.check_keys(&["type", "translate", "shape", "angle"])?;
+            Box::new(sdf::Twist { field: child("shape")?, rate: t(s.float("angle")?.to_radians()) })
+        }
+        "round" => {
+            s.check_keys(&["type", "translate", "shape", "radius"])?;
+            Box::new(sdf::Round { field: child("shape")?, radius: t(s.positive("radius")?) })
+        }
+        _ => return key_error(&s.key("type"), "unknown shape type")
+    };
+    Ok(match s.table.get("translate") {
+        Some(_) => Box::new(sdf::Translate { field, offset: s.vec3("translate")? }),
+        None => field
+    })
+}
+
+// An object given as a table under `key` of another, e.g. the operands of a
+// CSG object
+fn build_nested<T: ElemT>(s: &Section,
+                          key: &str,
+                          materials: &HashMap<String, Section>,
+                          textures: &mut Textures<T>,
+                          meshes: &mut MeshCache<T>,
+                          dir: &Path) -> Result<Box<dyn Hitable<T>>, SceneError> {
+    let nested = match s.section(key)? {
+        Some(nested) => nested,
+        None => return key_error(&s.key(key), "missing required key")
+    };
+    let mut items = Vec::new();
+    build_objects(&nested, materials, textures, meshes, dir, &mut items)?;
+    Ok(if items.len() == 1 { items.pop().unwrap() } else { Box::new(BvhNode::new(items)) })
+}
+
+// OBJ files are loaded once per scene; every object using the same file
+// shares the one mesh
+type MeshCache<T> = HashMap<PathBuf, Arc<dyn Hitable<T>>>;
+
+fn build_objects<T: ElemT>(s: &Section,
+                           materials: &HashMap<String, Section>,
+                           textures: &mut Textures<T>,
+                           meshes: &mut MeshCache<T>,
+                           dir: &Path,
+                           list: &mut Vec<Box<dyn Hitable<T>>>) -> Result<(), SceneError> {
+    let check_keys = |keys: &[&str]| {
+        let mut all = keys.to_vec();
+        all.extend_from_slice(&TRANSFORM_KEYS);
+        all.push("keyframes");
+        s.check_keys(&all)
+    };
+
+    let mut objects: Vec<Box<dyn Hitable<T>>> = Vec::new();
+    match s.string("type")? {
+        "sphere" => {
+            check_keys(&["type", "center", "radius", "material"])?;
+            objects.push(Box::new(Sphere::new(s.vec3("center")?, t(sphere_radius(s)?),
+                                              object_material(s, materials, textures)?)));
+        }
+        "moving_sphere" => {
+            check_keys(&["type", "center0", "center1", "time0", "time1", "radius", "material"])?;
+            objects.push(Box::new(MovingSphere::new(s.vec3("center0")?, s.vec3("center1")?,
+                                                    t(s.float_or("time0", 0.)?), t(s.float_or("time1", 1.)?),
+                                                    t(sphere_radius(s)?), object_material(s, materials, textures)?)));
+        }
+        "xy_rect" => {
+            check_keys(&["type", "x0", "x1", "y0", "y1", "k", "material"])?;
+            objects.push(Box::new(rect::xy_rect(t(s.float("x0")?), t(s.float("x1")?), t(s.float("y0")?), t(s.float("y1")?),
+                                                t(s.float("k")?), object_material(s, materials, textures)?)));
+        }
+        "xz_rect" => {
+            check_keys(&["type", "x0", "x1", "z0", "z1", "k", "material"])?;
+            objects.push(Box::new(rect::xz_rect(t(s.float("x0")?), t(s.float("x1")?), t(s.float("z0")?), t(s.float("z1")?),
+                                                t(s.float("k")?), object_material(s, materials, textures)?)));
+        }
+        "yz_rect" => {
+            check_keys(&["type", "y0", "y1", "z0", "z1", "k", "material"])?;
+            objects.push(Box::new(rect::yz_rect(t(s.float("y0")?), t(s.float("y1")?), t(s.float("z0")?), t(s.float("z1")?),
+                                                t(s.float("k")?), object_material(s, materials, textures)?)));
+        }
+        "parallelogram" => {
+            check_keys(&["type", "corner", "u", "v", "material"])?;
+            let (u, v): (Vec3<T>, Vec3<T>) = (s.vec3("u")?, s.vec3("v")?);
+            if u.cross(&v).squared_length() == T::zero() {
+                return key_error(&s.key("v"), "edges can't be parallel");
+            }
+            objects.push(Box::new(Parallelogram::new(s.vec3("corner")?, u, v, object_material(s, materials, textures)?)));
+        }
+        "triangle" => {
+            check_keys(&["type", "v0", "v1", "v2", "n0", "n1", "n2", "material"])?;
+            let (v0, v1, v2): (Vec3<T>, Vec3<T>, Vec3<T>) = (s.vec3("v0")?, s.vec3("v1")?, s.vec3("v2")?);
+            if (&v1 - &v0).cross(&(&v2 - &v0)).squared_length() == T::zero() {
+                return key_error(&s.key("v2"), "vertices can't be in a line");
+            }
+            // vertex normals to shade smoothly with, all three or none
+            let material = object_material(s, materials, textures)?;
+            if ["n0", "n1", "n2"].iter().any(|k| s.table.contains_key(*k)) {
+                let normals = [nonzero_vec3(s, "n0")?, nonzero_vec3(s, "n1")?, nonzero_vec3(s, "n2")?];
+                objects.push(Box::new(Triangle::with_normals(v0, v1, v2, normals, material)));
+            } else {
+                objects.push(Box::new(Triangle::new(v0, v1, v2, material)));
+            }
+        }
+        "box" => {
+            check_keys(&["type", "corner0", "corner1", "material"])?;
+            objects.push(Box::new(AaBox::new(&s.vec3("corner0")?, &s.vec3("corner1")?,
+                                             object_material(s, materials, textures)?)));
+        }
+        "plane" => {
+            check_keys(&["type", "point", "normal", "material"])?;
+            objects.push(Box::new(Plane::new(s.vec3("point")?, &nonzero_vec3(s, "normal")?,
+                                             object_material(s, materials, textures)?)));
+        }
+        "disk" => {
+            check_keys(&["type", "center", "normal", "radius", "material"])?;
+            objects.push(Box::new(Disk::new(s.vec3("center")?, &nonzero_vec3(s, "normal")?, t(s.positive("radius")?),
+                                            object_material(s, materials, textures)?)));
+        }
+        "cylinder" => {
+            check_keys(&["type", "base", "radius", "height", "sweep", "capped", "material"])?;
+            objects.push(Box::new(Cylinder::new(s.vec3_or("base", Vec3::default())?, t(s.positive("radius")?),
+                                                t(s.positive("height")?), t(sweep(s)?),
+                                                s.bool_or("capped", true)?, object_material(s, materials, textures)?)));
+        }
+        "cone" => {
+            check_keys(&["type", "base", "radius", "height", "sweep", "capped", "material"])?;
+            objects.push(Box::new(Cone::new(s.vec3_or("base", Vec3::default())?, t(s.positive("radius")?),
+                                            t(s.positive("height")?), t(sweep(s)?),
+                                            s.bool_or("capped", true)?, object_material(s, materials, textures)?)));
+        }
+        "paraboloid" => {
+            check_keys(&["type", "vertex", "radius", "height", "sweep", "material"])?;
+            objects.push(Box::new(Paraboloid::new(s.vec3_or("vertex", Vec3::default())?, t(s.positive("radius")?),
+                                                  t(s.positive("height")?), t(sweep(s)?),
+                                                  object_material(s, materials, textures)?)));
+        }
+        "hyperboloid" => {
+            check_keys(&["type", "center", "waist", "end_radius", "height", "sweep", "material"])?;
+            let waist = s.float("waist")?;
+            if waist < 0. {
+                return key_error(&s.key("waist"), "expected a non-negative number");
+            }
+            objects.push(Box::new(Hyperboloid::new(s.vec3_or("center", Vec3::default())?, t(waist),
+                                                   t(s.positive("end_radius")?), t(s.positive("height")?),
+                                                   t(sweep(s)?), object_material(s, materials, textures)?)));
+        }
+        "torus" => {
+            check_keys(&["type", "center", "major", "minor", "sweep", "material"])?;
+            objects.push(Box::new(Torus::new(s.vec3_or("center", Vec3::default())?, t(s.positive("major")?),
+                                             t(s.positive("minor")?), t(sweep(s)?),
+                                             object_material(s, materials, textures)?)));
+        }
+        "csg" => {
+            check_keys(&["type", "operation", "left", "right"])?;
+            let op = match CsgOp::from_name(s.string("operation")?) {
+                Some(op) => op,
+                None => return key_error(&s.key("operation"), "expected union, intersection or difference")
+            };
+            let left = build_nested(s, "left", materials, textures, meshes, dir)?;
+            let right = build_nested(s, "right", materials, textures, meshes, dir)?;
+            objects.push(Box::new(Csg::new(op, left, right)));
+        }
+        "constant_medium" => {
+            check_keys(&["type", "boundary", "density", "material"])?;
+            let boundary = build_nested(s, "boundary", materials, textures, meshes, dir)?;
+            objects.push(Box::new(ConstantMedium::new(boundary, t(s.positive("density")?),
+                                                      object_material(s, materials, textures)?)));
+        }
+        "volume" => {
+            check_keys(&["type", "grid", "corner0", "corner1", "density", "albedo", "g", "emission_grid", "emission"])?;
+            let grid = Arc::new(VoxelGrid::load(&dir.join(s.string("grid")?))?);
+            let density = s.float_or("density", 1.)?;
+            if density < 0. {
+                return key_error(&s.key("density"), "expected a non-negative number");
+            }
+            let albedo: Vec3<T> = s.vec3_or("albedo", Vec3::new(T::one(), T::one(), T::one()))?;
+            if (0..3).any(|c| albedo[c] < T::zero() || albedo[c] > T::one()) {
+                return key_error(&s.key("albedo"), "expected values between 0 and 1");
+            }
+            let mut volume = Volume::new(grid, s.vec3("corner0")?, s.vec3("corner1")?, t(density), albedo, t(s.mean_cosine("g")?));
+            // glowing takes both a grid and a colour
+            for &(key, other) in &[("emission_grid", "emission"), ("emission", "emission_grid")] {
+                if s.table.contains_key(key) && !s.table.contains_key(other) {
+                    return key_error(&s.key(other), &format!("missing, needed with {}", key));
+                }
+            }
+            if s.table.contains_key("emission_grid") {
+                let glow = Arc::new(VoxelGrid::load(&dir.join(s.string("emission_grid")?))?);
+                volume = volume.with_emission(glow, s.vec3("emission")?);
+            }
+            objects.push(Box::new(volume));
+        }
+        "sdf" => {
+            check_keys(&["type", "shape", "bounds_min", "bounds_max", "material"])?;
+            let field = match s.section("shape")? {
+                Some(shape) => build_field(&shape)?,
+                None => return key_error(&s.key("shape"), "missing required key")
+            };
+            let mut object = Sdf::new(field, object_material(s, materials, textures)?);
+            // needed to keep a repeated shape in the BVH
+            if s.table.contains_key("bounds_min") || s.table.contains_key("bounds_max") {
+                object = object.with_bounds(Aabb::new(s.vec3("bounds_min")?, s.vec3("bounds_max")?));
+            }
+            objects.push(Box::new(object));
+        }
+        "obj" => {
+            check_keys(&["type", "file"])?;
+            let path = dir.join(s.string("file")?);
+            let mesh = match meshes.get(&path) {
+                Some(mesh) => mesh.clone(),
+                None => {
+                    let mesh: Arc<dyn Hitable<T>> = Arc::new(objloader::load_obj::<T>(&path)?);
+                    meshes.insert(path, mesh.clone());
+                    mesh
+                }
+            };
+            objects.push(Box::new(mesh));
+        }
+        "random_spheres" => {
+            check_keys(&["type", "seed", "ground"])?;
+            let ground = if s.table.contains_key("ground") { textures.color_or_texture(s, "ground")? }
+                         else { Arc::new(ConstantTexture::new(Vec3::new(t(0.5), t(0.5), t(0.5)))) };
+            objects.extend(random_spheres(&mut Sampler::new(s.uint_or("seed", 0)?), ground));
+        }
+        _ => return key_error(&s.key("type"), "unknown object type")
+    }
+
+    let shared = |mut objects: Vec<Box<dyn Hitable<T>>>| -> Arc<dyn Hitable<T>> {
+        if objects.len() == 1 { Arc::from(objects.pop().unwrap()) } else { Arc::new(BvhNode::new(objects)) }
+    };
+    if let Some(m) = build_transform(s)? {
+        objects = vec![Box::new(Transformed::new(shared(objects), m))];
+    }
+    // the keyframes move the object as placed by its own transform
+    if s.table.contains_key("keyframes") {
+        objects = vec![Box::new(Animated::new(shared(objects), build_keyframes(s)?))];
+    }
+    list.extend(objects);
+    Ok(())
+}
+
+fn build_camera<T: ElemT>(s: &Section) -> Result<CameraSettings<T>, SceneError> {
+    s.check_keys(&["lookfrom", "lookat", "vup", "vfov", "aperture", "focus_dist", "shutter_open", "shutter_close"])?;
+    let lookfrom: Vec3<T> = s.vec3("lookfrom")?;
+    let lookat: Vec3<T> = s.vec3("lookat")?;
+    let view = &lookfrom - &lookat;
+    if view.squared_length() == T::zero() {
+        return key_error(&s.key("lookat"), "can't be the same as lookfrom");
+    }
+    let vup: Vec3<T> = s.vec3_or("vup", Vec3::new(T::zero(), T::one(), T::zero()))?;
+    if vup.cross(&view).squared_length() == T::zero() {
+        return key_error(&s.key("vup"), "can't be parallel to the view direction");
+    }
+    let focus_dist = if s.table.contains_key("focus_dist") { t(s.positive("focus_dist")?) }
+                     else { view.length() };
+    let vfov = s.float_or("vfov", 90.)?;
+    if vfov <= 0. || vfov >= 180. {
+        return key_error(&s.key("vfov"), "expected an angle between 0 and 180 degrees");
+    }
+    let aperture = s.float_or("aperture", 0.)?;
+    if aperture < 0. {
+        return key_error(&s.key("aperture"), "expected a non-negative number");
+    }
+    let shutter_open = s.float_or("shutter_open", 0.)?;
+    let shutter_close = s.float_or("shutter_close", shutter_open)?;
+    if shutter_close < shutter_open {
+        return key_error(&s.key("shutter_close"), "shutter can't close before it opens");
+    }
+    Ok(CameraSettings {
+        vfov: t(vfov),
+        aperture: t(aperture),
+        lookfrom,
+        lookat,
+        vup,
+        focus_dist,
+        shutter_open: t(shutter_open),
+        shutter_close: t(shutter_close)
+    })
+}
+
+fn build_background<T: ElemT>(s: &Section) -> Result<Background<T>, SceneError> {
+    match s.string("type")? {
+        "gradient" => {
+            s.check_keys(&["type", "bottom", "top"])?;
+            Ok(Background::Gradient {
+                bottom: s.vec3_or("bottom", Vec3::new(t(1.), t(1.), t(1.)))?,
+                top: s.vec3_or("top", Vec3::new(t(0.5), t(0.7), t(1.)))?
+            })
+        }
+        "constant" => {
+            s.check_keys(&["type", "color"])?;
+            Ok(Background::Constant(s.vec3("color")?))
+        }
+        // only the scene's own lights contribute
+        "none" => {
+            s.check_keys(&["type"])?;
+            Ok(Background::Constant(Vec3::default()))
+        }
+        _ => key_error(&s.key("type"), "unknown background type")
+    }
+}
+
+// Coefficients per unit length, a number or one per channel
+fn build_atmosphere<T: ElemT>(s: &Section) -> Result<Atmosphere<T>, SceneError> {
+    s.check_keys(&["absorption", "scattering", "g"])?;
+    let coefficient = |key: &str| -> Result<Vec3<T>, SceneError> {
+        if !s.table.contains_key(key) {
+            return Ok(Vec3::default());
+        }
+        let v: Vec3<T> = s.vec3_or_scalar(key)?;
+        if (0..3).any(|c| v[c] < T::zero()) {
+            return key_error(&s.key(key), "can't be negative");
+        }
+        Ok(v)
+    };
+    Ok(Atmosphere::new(coefficient("absorption")?, coefficient("scattering")?, t(s.mean_cosine("g")?)))
+}
+
+fn build_render(s: &Section) -> Result<RenderSettings, SceneError> {
+    s.check_keys(&["width", "height", "samples", "max_depth", "seed"])?;
+    let default = RenderSettings::default();
+    Ok(RenderSettings {
+        width: s.count_or("width", default.width)?,
+        height: s.count_or("height", default.height)?,
+        samples: s.count_or("samples", default.samples)?,
+        max_depth: s.count_or("max_depth", default.max_depth)?,
+        seed: s.uint_or("seed", default.seed)?
+    })
+}
+
+// Builds a scene from TOML source. Relative file names inside the scene
+// (e.g. OBJ meshes) are resolved against `dir`.
+pub fn parse_scene<T: ElemT>(src: &str, file: &str, dir: &Path) -> Result<Scene<T>, SceneError> {
+    let root_value = match src.parse::<Value>() {
+        Ok(v) => v,
+        Err(e) => return Err(SceneError::Syntax(file.to_string(), e))
+    };
+    let root = Section::new(String::new(), &root_value)?;
+    root.check_keys(&["camera", "render", "background", "atmosphere", "textures", "materials", "objects"])?;
+
+    let camera = match root.section("camera")? {
+        Some(s) => build_camera(&s)?,
+        None => return key_error("camera", "missing required key")
+    };
+    let render = match root.section("render")? {
+        Some(s) => build_render(&s)?,
+        None => RenderSettings::default()
+    };
+    let background = match root.section("background")? {
+        Some(s) => build_background(&s)?,
+        None => Background::default()
+    };
+    let atmosphere = match root.section("atmosphere")? {
+        Some(s) => Some(build_atmosphere(&s)?),
+        None => None
+    };
+
+    let mut textures = Textures { sections: HashMap::new(), built: HashMap::new(), building: HashSet::new(), dir };
+    if let Some(s) = root.section("textures")? {
+        for (name, value) in s.table {
+            textures.sections.insert(name.clone(), Section::new(s.key(name), value)?);
+        }
+        // build up front so mistakes are reported even if unused
+        for name in s.table.keys() {
+            textures.get(&s.key(name), name)?;
+        }
+    }
+
+    let mut materials = HashMap::new();
+    if let Some(s) = root.section("materials")? {
+        for (name, value) in s.table {
+            let m = Section::new(s.key(name), value)?;
+            // build once up front so mistakes are reported even if unused
+            build_material::<T>(&m, &mut textures)?;
+            materials.insert(name.clone(), m);
+        }
+    }
+
+    let mut list = Vec::new();
+    let mut meshes = HashMap::new();
+    if let Some(objects) = root.table.get("objects") {
+        let objects = match objects.as_array() {
+            Some(a) => a,
+            None => return key_error("objects", "expected an array of tables")
+        };
+        for (i, value) in objects.iter().enumerate() {
+            let s = Section::new(format!("objects[{}]", i), value)?;
+            build_objects(&s, &materials, &mut textures, &mut meshes, dir, &mut list)?;
+        }
+    }
+
+    Ok(Scene {
+        world: BvhNode::new(list),
+        camera,
+        background,
+        atmosphere,
+        render
+    })
+}
+
+pub fn load_scene<T: ElemT>(path: &Path) -> Result<Scene<T>, SceneError> {
+    let name = path.display().to_string();
+    let src = fs::read_to_string(path).map_err(|e| SceneError::Io(name.clone(), e))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    parse_scene(&src, &name, dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_scene, Scene, SceneError};
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use vec3::Vec3;
+    use rng::Sampler;
+    use ray::Ray;
+    use hitable::Hitable;
+
+    fn parse(src: &str) -> Result<Scene<f64>, SceneError> {
+        parse_scene(src, "test.toml", Path::new(""))
+    }
+
+    fn error_key(src: &str) -> String {
+        match parse(src) {
+            Err(SceneError::Key { key, .. }) => key,
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("expected an error")
+        }
+    }
+
+    const CAMERA: &str = "[camera]\nlookfrom = [0, 0, 5]\nlookat = [0, 0, 0]\n";
+
+    #[test]
+    fn test_parse() {
+        let src = format!("{}{}", CAMERA, r#"
+            [render]
+            width = 64
+            height = 32
+
+            [materials.red]
+            type = "lambertian"
+            albedo = [0.8, 0.1, 0.1]
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1.5
+            material = "red"
+        "#);
+        let scene = parse(&src).unwrap();
+        assert_eq!(64, scene.render.width);
+        assert_eq!(32, scene.render.height);
+        assert_eq!(10, scene.render.samples);
+        assert_approx_eq!(5.0, scene.camera.focus_dist);
+
+        let rec = scene.world.hit(&Ray::new(Vec3::new(0., 0., 5.), Vec3::new(0., 0., -1.)), 0., 100.).unwrap();
+        assert_approx_eq!(3.5, rec.t);
+    }
+
+    #[test]
+    fn test_textures() {
+        let src = format!("{}{}", CAMERA, r#"
+            [textures.checker]
+            type = "checker"
+            odd = "dark"
+            even = [0.9, 0.9, 0.9]
+
+            [textures.dark]
+            type = "constant"
+            color = [0.2, 0.3, 0.1]
+
+            [textures.wood]
+            type = "noise"
+            pattern = "wood"
+            seed = 3
+
+            [materials.ground]
+            type = "lambertian"
+            albedo = "checker"
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "ground"
+        "#);
+        let scene = parse(&src).unwrap();
+        let rec = scene.world.hit(&Ray::new(Vec3::new(0., 0., 5.), Vec3::new(0., 0., -1.)), 0., 100.).unwrap();
+        assert_approx_eq!(0.5, rec.v);
+        assert!(rec.mat_opt.unwrap().scatter(&Ray::new(Vec3::new(0., 0., 5.), Vec3::new(0., 0., -1.)), &rec, &mut Sampler::new(0)).is_some());
+
+        assert_eq!("materials.m.albedo",
+                   error_key(&format!("{}[materials.m]\ntype = \"lambertian\"\nalbedo = \"nope\"\n", CAMERA)));
+        assert_eq!("textures.a.odd",
+                   error_key(&format!("{}[textures.a]\ntype = \"checker\"\nodd = \"a\"\neven = [1, 1, 1]\n", CAMERA)));
+        assert_eq!("textures.a.pattern",
+                   error_key(&format!("{}[textures.a]\ntype = \"noise\"\npattern = \"plaid\"\n", CAMERA)));
+        assert_eq!("textures.a.wrap",
+                   error_key(&format!("{}[textures.a]\ntype = \"image\"\nfile = \"a.png\"\nwrap = \"tile\"\n", CAMERA)));
+        match parse(&format!("{}[textures.a]\ntype = \"image\"\nfile = \"missing.png\"\n", CAMERA)) {
+            Err(SceneError::Texture(_)) => {},
+            _ => panic!("expected a texture error")
+        }
+    }
+
+    #[test]
+    fn test_transform() {
+        let src = format!("{}{}", CAMERA, r#"
+            [materials.red]
+            type = "lambertian"
+            albedo = [0.8, 0.1, 0.1]
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "red"
+            scale = [1, 1, 2]
+            rotate = [90, 0, 0]
+            translate = [0, 0, -1]
+        "#);
+        let scene = parse(&src).unwrap();
+        // stretched along z, then turned so the long axis runs along -y
+        let rec = scene.world.hit(&Ray::new(Vec3::new(0., 0., 5.), Vec3::new(0., 0., -1.)), 0., 100.).unwrap();
+        assert_approx_eq!(5.0, rec.t);
+        let rec = scene.world.hit(&Ray::new(Vec3::new(0., -5., -1.), Vec3::new(0., 1., 0.)), 0., 100.).unwrap();
+        assert_approx_eq!(3.0, rec.t);
+
+        assert_eq!("objects[0].scale",
+                   error_key(&format!("{}[[objects]]\ntype = \"random_spheres\"\nscale = 0\n", CAMERA)));
+        assert_eq!("objects[0].scale",
+                   error_key(&format!("{}[[objects]]\ntype = \"random_spheres\"\nscale = inf\n", CAMERA)));
+        assert_eq!("objects[0].translate",
+                   error_key(&format!("{}[[objects]]\ntype = \"random_spheres\"\ntranslate = [0, nan, 0]\n", CAMERA)));
+        // however small, a scale can be undone
+        let tiny = src.replace("scale = [1, 1, 2]", "scale = 1e-17");
+        let scene = parse(&tiny).unwrap();
+        assert_approx_eq!(6.0, scene.world.hit(&Ray::new(Vec3::new(0., 0., 5.), Vec3::new(0., 0., -1.)), 0., 100.).unwrap().t, 1e-6);
+    }
+
+    #[test]
+    fn test_motion() {
+        let src = format!("{}{}", CAMERA, r#"
+            shutter_open = 0
+            shutter_close = 1
+
+            [materials.red]
+            type = "lambertian"
+            albedo = [0.8, 0.1, 0.1]
+
+            [[objects]]
+            type = "moving_sphere"
+            center0 = [0, 0, 0]
+            center1 = [0, 2, 0]
+            radius = 0.5
+            material = "red"
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 0.5
+            material = "red"
+            translate = [0, 0, -2]
+            keyframes = [
+                { time = 0, translate = [-2, 0, 0] },
+                { time = 1, translate = [2, 0, 0] },
+            ]
+        "#);
+        let scene = parse(&src).unwrap();
+        assert_approx_eq!(1.0, scene.camera.shutter_close);
+        let at = |x: f64, time: f64| Ray::with_time(Vec3::new(x, 0., 5.), Vec3::new(0., 0., -1.), time);
+        assert_approx_eq!(4.5, scene.world.hit(&at(0., 0.), 0., 100.).unwrap().t);
+        // the moving sphere has gone up, the keyframed one is passing behind
+        assert_approx_eq!(6.5, scene.world.hit(&at(0., 0.5), 0., 100.).unwrap().t);
+        assert!(scene.world.hit(&at(1., 0.5), 0., 100.).is_none());
+        // keyframes apply after the object's own transform
+        assert_approx_eq!(6.5, scene.world.hit(&at(1., 0.75), 0., 100.).unwrap().t);
+
+        assert_eq!("camera.shutter_close", error_key("[camera]\nlookfrom = [0, 0, 5]\nlookat = [0, 0, 0]\nshutter_open = 1\nshutter_close = 0\n"));
+        assert_eq!("objects[0].keyframes[0].time",
+                   error_key(&format!("{}[[objects]]\ntype = \"random_spheres\"\nkeyframes = [{{ scale = 2 }}]\n", CAMERA)));
+    }
+
+    #[test]
+    fn test_flat_shapes() {
+        let src = format!("{}{}", CAMERA, r#"
+            [materials.white]
+            type = "lambertian"
+            albedo = [0.7, 0.7, 0.7]
+
+            [[objects]]
+            type = "plane"
+            point = [0, 0, -10]
+            normal = [0, 0, 1]
+            material = "white"
+
+            [[objects]]
+            type = "xy_rect"
+            x0 = -1
+            x1 = 1
+            y0 = -1
+            y1 = 1
+            k = -1
+            material = "white"
+
+            [[objects]]
+            type = "box"
+            corner0 = [2, -1, -1]
+            corner1 = [4, 1, -3]
+            material = "white"
+
+            [[objects]]
+            type = "disk"
+            center = [-3, 0, -2]
+            normal = [0, 0, 1]
+            radius = 1
+            material = "white"
+        "#);
+        let scene = parse(&src).unwrap();
+        let at = |x: f64| scene.world.hit(&Ray::new(Vec3::new(x, 0., 5.), Vec3::new(0., 0., -1.)), 0., 100.).unwrap().t;
+        assert_approx_eq!(6.0, at(0.));
+        assert_approx_eq!(6.0, at(3.));
+        assert_approx_eq!(7.0, at(-3.));
+        assert_approx_eq!(15.0, at(-10.));
+
+        assert_eq!("objects[0].normal", error_key(&format!(
+            "{}[materials.m]\ntype = \"dielectric\"\nref_idx = 1.5\n[[objects]]\ntype = \"plane\"\npoint = [0, 0, 0]\nnormal = [0, 0, 0]\nmaterial = \"m\"\n", CAMERA)));
+
+    }
+
+    #[test]
+    fn test_triangle() {
+        let src = format!("{}{}", CAMERA, r#"
+            [materials.white]
+            type = "lambertian"
+            albedo = [0.7, 0.7, 0.7]
+
+            [[objects]]
+            type = "triangle"
+            v0 = [0, 0, 0]
+            v1 = [1, 0, 0]
+            v2 = [0, 1, 0]
+            material = "white"
+
+            [[objects]]
+            type = "triangle"
+            v0 = [2, 0, -1]
+            v1 = [3, 0, -1]
+            v2 = [2, 1, -1]
+            n0 = [0, 0, 1]
+            n1 = [1, 0, 0]
+            n2 = [0, 0, 1]
+            material = "white"
+        "#);
+        let scene = parse(&src).unwrap();
+        let at = |x: f64| scene.world.hit(&Ray::new(Vec3::new(x, 0.25, 5.), Vec3::new(0., 0., -1.)), 0., 100.);
+        let rec = at(0.25).unwrap();
+        assert_approx_eq!(5.0, rec.t);
+        assert_approx_eq!(1.0, rec.normal.z());
+        let rec = at(2.5).unwrap();
+        assert_approx_eq!(6.0, rec.t);
+        assert!(rec.normal.x() > 0.);
+        assert!(at(0.9).is_none());
+
+        let triangle = |extra: &str| format!(
+            "{}[materials.m]\ntype = \"lambertian\"\nalbedo = [1, 1, 1]\n[[objects]]\ntype = \"triangle\"\nv0 = [0, 0, 0]\nv1 = [1, 0, 0]\nmaterial = \"m\"\n{}",
+            CAMERA, extra);
+        assert_eq!("objects[0].v2", error_key(&triangle("v2 = [2, 0, 0]\n")));
+        assert_eq!("objects[0].n2", error_key(&triangle("v2 = [0, 1, 0]\nn0 = [0, 0, 1]\nn1 = [0, 0, 1]\n")));
+    }
+
+    #[test]
+    fn test_analytic_shapes() {
+        let src = format!("{}{}", CAMERA, r#"
+            [materials.white]
+            type = "lambertian"
+            albedo = [0.7, 0.7, 0.7]
+
+            [[objects]]
+            type = "cylinder"
+            base = [0, -1, 0]
+            radius = 0.5
+            height = 2
+            material = "white"
+
+            [[objects]]
+            type = "torus"
+            center = [3, 0, 0]
+            major = 1
+            minor = 0.25
+            sweep = 180
+            material = "white"
+            rotate = [90, 0, 0]
+        "#);
+        let scene = parse(&src).unwrap();
+        let at = |x: f64, y: f64| scene.world.hit(&Ray::new(Vec3::new(x, y, 5.), Vec3::new(0., 0., -1.)), 0., 100.).map(|h| h.t);
+        assert_approx_eq!(4.5, at(0., 0.).unwrap());
+        // stood up to face the camera: the half kept had z >= 0, which now faces down
+        assert_approx_eq!(4.75, at(3., -1.).unwrap());
+        assert!(at(3., 1.).is_none());
+
+        let cylinder = |extra: &str| format!("{}[materials.m]\ntype = \"dielectric\"\nref_idx = 1.5\n[[objects]]\ntype = \"cylinder\"\nradius = 1\nmaterial = \"m\"\n{}\n", CAMERA, extra);
+        assert_eq!("objects[0].height", error_key(&cylinder("height = 0")));
+        assert_eq!("objects[0].capped", error_key(&cylinder("height = 1\ncapped = 1")));
+        assert_eq!("objects[0].sweep", error_key(&cylinder("height = 1\nsweep = 0")));
+        assert_eq!("objects[0].sweep", error_key(&format!(
+            "{}[materials.m]\ntype = \"lambertian\"\nalbedo = [1, 1, 1]\n[[objects]]\ntype = \"torus\"\nmajor = 1\nminor = 0.5\nsweep = -90\nmaterial = \"m\"\n", CAMERA)));
+    }
+
+    #[test]
+    fn test_csg() {
+        let src = format!("{}{}", CAMERA, r#"
+            [materials.glass]
+            type = "dielectric"
+            ref_idx = 1.5
+
+            [[objects]]
+            type = "csg"
+            operation = "difference"
+            left = { type = "box", corner0 = [-1, -1, -1], corner1 = [1, 1, 1], material = "glass" }
+            [objects.right]
+            type = "csg"
+            operation = "union"
+            left = { type = "sphere", center = [0, 0, 1], radius = 0.5, material = "glass" }
+            right = { type = "cylinder", base = [0, -2, 0], radius = 0.25, height = 4, material = "glass" }
+        "#);
+        let scene = parse(&src).unwrap();
+        let at = |x: f64| scene.world.hit(&Ray::new(Vec3::new(x, 0., 5.), Vec3::new(0., 0., -1.)), 0., 100.).unwrap().t;
+        assert_approx_eq!(4.0, at(0.8));
+        assert_approx_eq!(4.5, at(0.));
+        let down = Ray::new(Vec3::new(0., 5., -0.1), Vec3::new(0., -1., 0.));
+        assert!(scene.world.hit(&down, 0., 100.).is_none());
+
+        let csg = |op: &str| format!("{}[[objects]]\ntype = \"csg\"\noperation = \"{}\"\n", CAMERA, op);
+        assert_eq!("objects[0].operation", error_key(&csg("xor")));
+        assert_eq!("objects[0].left", error_key(&csg("union")));
+    }
+
+    #[test]
+    fn test_sdf() {
+        let src = format!("{}{}", CAMERA, r#"
+            [materials.grey]
+            type = "lambertian"
+            albedo = [0.5, 0.5, 0.5]
+
+            [[objects]]
+            type = "sdf"
+            material = "grey"
+            [objects.shape]
+            type = "smooth_union"
+            k = 0.5
+            shapes = [
+                { type = "sphere", radius = 1, translate = [-1.1, 0, 0] },
+                { type = "round", radius = 0.1, shape = { type = "box", size = [1.8, 1.8, 1.8] }, translate = [1.1, 0, 0] }
+            ]
+
+            [[objects]]
+            type = "sdf"
+            material = "grey"
+            bounds_min = [-10, -3.5, -10]
+            bounds_max = [10, -2.5, 10]
+            shape = { type = "repeat", period = [2, 0, 2], shape = { type = "sphere", radius = 0.5 }, translate = [0, -3, 0] }
+        "#);
+        let scene = parse(&src).unwrap();
+        let down = |x: f64| scene.world.hit(&Ray::new(Vec3::new(x, 5., 0.), Vec3::new(0., -1., 0.)), 0., 100.).map(|rec| rec.t);
+        assert_approx_eq!(4.0, down(-1.1).unwrap(), 1e-3);
+        assert_approx_eq!(4.0, down(1.1).unwrap(), 1e-3);
+        // filled in between the two
+        assert!(down(0.).unwrap() < 5.);
+        assert_approx_eq!(7.5, down(8.).unwrap(), 1e-3);
+        assert!(down(11.).is_none());
+
+        let sdf = |shape: &str| format!("{}[[objects]]\ntype = \"sdf\"\nshape = {}\n", CAMERA, shape);
+        assert_eq!("objects[0].shape.type", error_key(&sdf("{ type = \"cube\" }")));
+        assert_eq!("objects[0].shape.shapes[1].radius", error_key(&sdf("{ type = \"union\", shapes = [{ type = \"sphere\", radius = 1 }, { type = \"sphere\" }] }")));
+        assert_eq!("objects[0].shape.shape", error_key(&sdf("{ type = \"twist\", angle = 10 }")));
+    }
+
+    #[test]
+    fn test_microfacet() {
+        let src = format!("{}{}", CAMERA, r#"
+            [materials.gold]
+            type = "conductor"
+            metal = "gold"
+            roughness = 0.4
+
+            [materials.mirror]
+            type = "conductor"
+            eta = [0.2, 0.9, 1.1]
+            k = [3.9, 2.5, 2.1]
+
+            [materials.frosted]
+            type = "rough_dielectric"
+            ref_idx = 1.5
+            roughness = 0.2
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "gold"
+
+            [[objects]]
+            type = "sphere"
+            center = [3, 0, 0]
+            radius = 1
+            material = "mirror"
+
+            [[objects]]
+            type = "sphere"
+            center = [-3, 0, 0]
+            radius = 1
+            material = "frosted"
+        "#);
+        let scene = parse(&src).unwrap();
+        // rough surfaces take part in light sampling, smooth ones can't
+        let up = Vec3::new(0., 1., 0.);
+        for &(x, rough) in &[(0., true), (3., false), (-3., true)] {
+            let r = Ray::new(Vec3::new(x, 0., 5.), Vec3::new(0., 0., -1.));
+            let rec = scene.world.hit(&r, 0.001, 100.).unwrap();
+            assert_eq!(rough, rec.mat_opt.unwrap().eval(&r, &rec, &up).is_some());
+        }
+
+        let with = |extra: &str| format!("{}{}", CAMERA, extra);
+        assert_eq!("materials.m.metal", error_key(&with("[materials.m]\ntype = \"conductor\"\nmetal = \"tin\"\n")));
+        assert_eq!("materials.m.k", error_key(&with("[materials.m]\ntype = \"conductor\"\neta = [1, 1, 1]\n")));
+        assert_eq!("materials.m.roughness", error_key(&with("[materials.m]\ntype = \"rough_dielectric\"\nref_idx = 1.5\nroughness = 2\n")));
+    }
+
+    #[test]
+    fn test_principled() {
+        let src = format!("{}{}", CAMERA, r#"
+            [textures.flakes]
+            type = "checker"
+            odd = [0.2, 0.2, 0.2]
+            even = [0.9, 0.9, 0.9]
+            scale = 10
+
+            [materials.paint]
+            type = "principled"
+            base_color = [0.7, 0.1, 0.1]
+            metallic = "flakes"
+            roughness = 0.3
+            clearcoat = 1
+            anisotropic = 0.5
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "paint"
+        "#);
+        let scene = parse(&src).unwrap();
+        let r = Ray::new(Vec3::new(0.1, 0.2, 5.), Vec3::new(0., 0., -1.));
+        let rec = scene.world.hit(&r, 0.001, 100.).unwrap();
+        let (f, pdf) = rec.mat_opt.unwrap().eval(&r, &rec, &Vec3::new(0., 0., 1.)).unwrap();
+        assert!(f.x() > f.y() && pdf > 0.);
+
+        let with = |extra: &str| format!("{}{}", CAMERA, extra);
+        assert!(parse(&with("[materials.m]\ntype = \"principled\"\n")).is_ok());
+        assert_eq!("materials.m.metallic", error_key(&with("[materials.m]\ntype = \"principled\"\nmetallic = 1.5\n")));
+        assert_eq!("materials.m.sheen", error_key(&with("[materials.m]\ntype = \"principled\"\nsheen = \"velvet\"\n")));
+        assert_eq!("materials.m.ior", error_key(&with("[materials.m]\ntype = \"principled\"\nior = 0\n")));
+    }
+
+    #[test]
+    fn test_glass() {
+        let src = format!("{}{}", CAMERA, r#"
+            [materials.prism]
+            type = "dielectric"
+            glass = "sf11"
+
+            [materials.tinted]
+            type = "dielectric"
+            ref_idx = 1.5
+            absorption = [0.1, 0.5, 0.5]
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "prism"
+
+            [[objects]]
+            type = "sphere"
+            center = [3, 0, 0]
+            radius = 1
+            material = "tinted"
+        "#);
+        let scene = parse(&src).unwrap();
+        // dispersive glass carries one channel at a time
+        let r = Ray::new(Vec3::new(0.3, 0., 5.), Vec3::new(0., 0., -1.));
+        let rec = scene.world.hit(&r, 0.001, 100.).unwrap();
+        let (w, _) = rec.mat_opt.unwrap().scatter(&r, &rec, &mut Sampler::new(0)).unwrap();
+        assert_eq!(1, (0..3).filter(|&c| w[c] > 0.).count());
+        // tinted glass absorbs more green and blue on the way out
+        let r = Ray::new(Vec3::new(3., 0., 0.), Vec3::new(0., 0., -1.));
+        let rec = scene.world.hit(&r, 0.001, 100.).unwrap();
+        let (w, _) = rec.mat_opt.unwrap().scatter(&r, &rec, &mut Sampler::new(0)).unwrap();
+        assert_approx_eq!((-0.1f64).exp(), w.x());
+        assert_approx_eq!((-0.5f64).exp(), w.z());
+
+        let with = |extra: &str| format!("{}{}", CAMERA, extra);
+        assert!(parse(&with("[materials.m]\ntype = \"dielectric\"\ncauchy_a = 1.5\ncauchy_b = 0.004\n")).is_ok());
+        assert!(parse(&with("[materials.m]\ntype = \"dielectric\"\nsellmeier_b = [1, 0.2, 1]\nsellmeier_c = [0.006, 0.02, 100]\n")).is_ok());
+        assert_eq!("materials.m.glass", error_key(&with("[materials.m]\ntype = \"dielectric\"\nglass = \"flint\"\n")));
+        assert_eq!("materials.m.glass", error_key(&with("[materials.m]\ntype = \"dielectric\"\nref_idx = 1.5\nglass = \"bk7\"\n")));
+        assert_eq!("materials.m.ref_idx", error_key(&with("[materials.m]\ntype = \"dielectric\"\n")));
+        assert_eq!("materials.m.sellmeier_c", error_key(&with("[materials.m]\ntype = \"dielectric\"\nsellmeier_b = [1, 1, 1]\n")));
+        assert_eq!("materials.m.cauchy_b", error_key(&with("[materials.m]\ntype = \"dielectric\"\nref_idx = 1.5\ncauchy_b = 0.004\n")));
+        assert_eq!("materials.m.sellmeier_c", error_key(&with("[materials.m]\ntype = \"dielectric\"\nglass = \"bk7\"\nsellmeier_c = [0, 0, 0]\n")));
+        // poles right at the blue wavelength, and n^2 below 1 just past one
+        assert_eq!("materials.m.sellmeier_c", error_key(&with("[materials.m]\ntype = \"dielectric\"\nsellmeier_b = [1, 0, 0]\nsellmeier_c = [0.2116, 0, 0]\n")));
+        assert_eq!("materials.m.sellmeier_c", error_key(&with("[materials.m]\ntype = \"dielectric\"\nsellmeier_b = [0, 1, 0]\nsellmeier_c = [0.2116, 0, 0]\n")));
+        assert_eq!("materials.m.sellmeier_c", error_key(&with("[materials.m]\ntype = \"dielectric\"\nsellmeier_b = [1, 0, 0]\nsellmeier_c = [0.25, 0, 0]\n")));
+        assert_eq!("materials.m.absorption", error_key(&with("[materials.m]\ntype = \"dielectric\"\nref_idx = 1.5\nabsorption = -1\n")));
+    }
+
+    #[test]
+    fn test_media() {
+        let src = format!("{}{}", CAMERA, r#"
+            [atmosphere]
+            absorption = 0.01
+            scattering = [0.02, 0.03, 0.04]
+            g = 0.3
+
+            [materials.smoke]
+            type = "isotropic"
+            albedo = [0.8, 0.8, 0.8]
+
+            [materials.mist]
+            type = "henyey_greenstein"
+            albedo = [0.9, 0.9, 0.9]
+            g = -0.2
+
+            [[objects]]
+            type = "constant_medium"
+            density = 1000
+            material = "smoke"
+            boundary = { type = "box", corner0 = [-1, -1, -1], corner1 = [1, 1, 1], material = "smoke" }
+
+            [[objects]]
+            type = "constant_medium"
+            density = 0.5
+            material = "mist"
+            translate = [5, 0, 0]
+            boundary = { type = "sphere", center = [0, 0, 0], radius = 1, material = "mist" }
+        "#);
+        let scene = parse(&src).unwrap();
+        let air = scene.atmosphere.unwrap();
+        assert_approx_eq!((-0.05f64).exp(), air.transmittance(1.).z());
+        // so dense that rays stop right at the boundary
+        let rec = scene.world.hit(&Ray::new(Vec3::new(0., 0., 5.), Vec3::new(0., 0., -1.)), 0., 100.).unwrap();
+        assert_approx_eq!(4.0, rec.t, 0.05);
+        assert!(scene.world.hit(&Ray::new(Vec3::new(5., 2., 5.), Vec3::new(0., 0., -1.)), 0., 100.).is_none());
+
+        let with = |extra: &str| format!("{}{}", CAMERA, extra);
+        assert_eq!("atmosphere.g", error_key(&with("[atmosphere]\ng = 1\n")));
+        assert_eq!("atmosphere.absorption", error_key(&with("[atmosphere]\nabsorption = [0, -1, 0]\n")));
+        assert_eq!("materials.m.g", error_key(&with("[materials.m]\ntype = \"henyey_greenstein\"\nalbedo = [1, 1, 1]\ng = -2\n")));
+        assert_eq!("objects[0].boundary", error_key(&with("[materials.m]\ntype = \"isotropic\"\nalbedo = [1, 1, 1]\n[[objects]]\ntype = \"constant_medium\"\ndensity = 1\nmaterial = \"m\"\n")));
+    }
+
+    #[test]
+    fn test_volume() {
+        let dir = env::temp_dir().join(format!("scene_volume_test_{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("full.raw"), [255u8; 8]).unwrap();
+        fs::write(dir.join("full.grid"), "size = [2, 2, 2]\nformat = \"u8\"\ndata = \"full.raw\"\n").unwrap();
+        let parse_in = |src: &str| parse_scene::<f64>(&format!("{}{}", CAMERA, src), "test.toml", &dir);
+
+        let scene = parse_in(r#"
+            [[objects]]
+            type = "volume"
+            grid = "full.grid"
+            corner0 = [-1, -1, -1]
+            corner1 = [1, 1, 1]
+            density = 1000
+            albedo = [0.5, 0.5, 0.5]
+            emission_grid = "full.grid"
+            emission = [4, 2, 0]
+        "#).unwrap();
+        // so dense that rays stop right at the edge, where it glows
+        let r = Ray::new(Vec3::new(0., 0., 5.), Vec3::new(0., 0., -1.));
+        let rec = scene.world.hit(&r, 0., 100.).unwrap();
+        assert_approx_eq!(4.0, rec.t, 0.05);
+        assert_approx_eq!(1.0, rec.mat_opt.unwrap().emitted(&r, &rec).y());
+
+        let key = |src: &str| match parse_in(src) {
+            Err(SceneError::Key { key, .. }) => key,
+            _ => panic!("expected a key error")
+        };
+        let volume = "[[objects]]\ntype = \"volume\"\ngrid = \"full.grid\"\ncorner0 = [0, 0, 0]\ncorner1 = [1, 1, 1]\n";
+        assert_eq!("objects[0].albedo", key(&format!("{}albedo = [2, 0, 0]\n", volume)));
+        assert_eq!("objects[0].emission", key(&format!("{}emission_grid = \"full.grid\"\n", volume)));
+        assert_eq!("objects[0].emission_grid", key(&format!("{}emission = [1, 0.5, 0.1]\n", volume)));
+        match parse_in("[[objects]]\ntype = \"volume\"\ngrid = \"none.grid\"\ncorner0 = [0, 0, 0]\ncorner1 = [1, 1, 1]\n") {
+            Err(SceneError::Grid(_)) => (),
+            _ => panic!("expected a grid error")
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_background() {
+        let miss = Ray::new(Vec3::new(0., 0., 5.), Vec3::new(0., 1., 0.));
+        let scene = parse(CAMERA).unwrap();
+        assert_approx_eq!(0.5, scene.background.color(&miss).x());
+
+        let scene = parse(&format!("{}[background]\ntype = \"none\"\n", CAMERA)).unwrap();
+        assert_eq!(0.0, scene.background.color(&miss).length());
+
+        let scene = parse(&format!("{}[background]\ntype = \"constant\"\ncolor = [0.1, 0.2, 0.3]\n", CAMERA)).unwrap();
+        assert_approx_eq!(0.2, scene.background.color(&miss).y());
+    }
+
+    #[test]
+    fn test_errors_name_key() {
+        assert_eq!("camera", error_key(""));
+        assert_eq!("camera.lookat", error_key("[camera]\nlookfrom = [0, 0, 5]\n"));
+        assert_eq!("camera.vfov", error_key(&format!("{}vfov = \"wide\"\n", CAMERA)));
+        assert_eq!("camera.vfov", error_key(&format!("{}vfov = 0\n", CAMERA)));
+        assert_eq!("camera.vfov", error_key(&format!("{}vfov = 180\n", CAMERA)));
+        assert_eq!("camera.aperture", error_key(&format!("{}aperture = -0.1\n", CAMERA)));
+        assert_eq!("camera.focus_dist", error_key(&format!("{}focus_dist = 0\n", CAMERA)));
+        assert_eq!("render.samples", error_key(&format!("{}[render]\nsamples = 0\n", CAMERA)));
+        assert_eq!("materials.m.albedo",
+                   error_key(&format!("{}[materials.m]\ntype = \"lambertian\"\nalbedo = [1, 1]\n", CAMERA)));
+        assert_eq!("objects[0].material",
+                   error_key(&format!("{}[[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"nope\"\n", CAMERA)));
+        assert_eq!("objects[0].radus",
+                   error_key(&format!("{}[[objects]]\ntype = \"sphere\"\nradus = 1\n", CAMERA)));
+        assert_eq!("background.type", error_key(&format!("{}[background]\ntype = \"stars\"\n", CAMERA)));
+
+        // a camera needs a direction to look in and a way up across it
+        assert_eq!("camera.lookat", error_key("[camera]\nlookfrom = [1, 2, 3]\nlookat = [1, 2, 3]\n"));
+        assert_eq!("camera.vup", error_key("[camera]\nlookfrom = [0, 5, 0]\nlookat = [0, 0, 0]\n"));
+        assert_eq!("camera.vup", error_key(&format!("{}vup = [0, 0, -2]\n", CAMERA)));
+
+        let object = |src: &str| format!("{}[materials.m]\ntype = \"lambertian\"\nalbedo = [1, 1, 1]\n[[objects]]\nmaterial = \"m\"\n{}", CAMERA, src);
+        assert_eq!("objects[0].radius", error_key(&object("type = \"sphere\"\ncenter = [0, 0, 0]\nradius = 0\n")));
+        assert_eq!("objects[0].radius", error_key(&object("type = \"moving_sphere\"\ncenter0 = [0, 0, 0]\ncenter1 = [1, 0, 0]\nradius = 0\n")));
+        assert_eq!("objects[0].radius", error_key(&object("type = \"disk\"\ncenter = [0, 0, 0]\nnormal = [0, 0, 1]\nradius = -1\n")));
+        // a negative radius makes a hollow sphere
+        assert!(parse(&object("type = \"sphere\"\ncenter = [0, 0, 0]\nradius = -1\n")).is_ok());
+        assert_eq!("materials.m.ref_idx", error_key(&format!("{}[materials.m]\ntype = \"dielectric\"\nref_idx = 0\n", CAMERA)));
+    }
+}
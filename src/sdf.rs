@@ -0,0 +1,490 @@
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+use hitable::{HitRecord, Hitable};
+use material::Material;
+use aabb::{Aabb, surrounding_box};
+use sphere::sphere_uv;
+
+// A signed distance field: negative inside the shape and positive outside.
+// Its magnitude must never be more than the distance to the surface, so
+// that nothing is closer to p than distance(p). bounding_box is None if the
+// shape goes on for ever.
+pub trait Field<T: ElemT>: Send + Sync {
+    fn distance(&self, p: &Vec3<T>) -> T;
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        None
+    }
+}
+
+// Any function of the point will do, e.g. a closure
+impl<T: ElemT, F> Field<T> for F where F: Fn(&Vec3<T>) -> T + Send + Sync {
+    fn distance(&self, p: &Vec3<T>) -> T {
+        self(p)
+    }
+}
+
+fn f<T: ElemT>(x: f64) -> T {
+    T::from_f64(x).unwrap()
+}
+
+fn splat<T: ElemT>(x: T) -> Vec3<T> {
+    Vec3::new(x, x, x)
+}
+
+fn grown<T: ElemT>(b: &Aabb<T>, by: T) -> Aabb<T> {
+    Aabb::new(b.min() - &splat(by), b.max() + &splat(by))
+}
+
+pub struct SphereField<T: ElemT> {
+    pub radius: T
+}
+
+impl<T: ElemT> Field<T> for SphereField<T> {
+    fn distance(&self, p: &Vec3<T>) -> T {
+        p.length() - self.radius
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        Some(Aabb::new(splat(-self.radius), splat(self.radius)))
+    }
+}
+
+// A box from -half to half
+pub struct BoxField<T: ElemT> {
+    pub half: Vec3<T>
+}
+
+impl<T: ElemT> Field<T> for BoxField<T> {
+    fn distance(&self, p: &Vec3<T>) -> T {
+        let mut outside = Vec3::default();
+        let mut inside = -T::max_value();
+        for a in 0..3 {
+            let q = p[a].abs() - self.half[a];
+            outside[a] = q.max(T::zero());
+            inside = inside.max(q);
+        }
+        outside.length() + inside.min(T::zero())
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        Some(Aabb::new(&self.half * -T::one(), self.half.clone()))
+    }
+}
+
+// A torus lying in the xz plane, as in torus.rs
+pub struct TorusField<T: ElemT> {
+    pub major: T,
+    pub minor: T
+}
+
+impl<T: ElemT> Field<T> for TorusField<T> {
+    fn distance(&self, p: &Vec3<T>) -> T {
+        let ring = (p.x()*p.x() + p.z()*p.z()).sqrt() - self.major;
+        (ring*ring + p.y()*p.y()).sqrt() - self.minor
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        let r = self.major + self.minor;
+        Some(Aabb::new(Vec3::new(-r, -self.minor, -r), Vec3::new(r, self.minor, r)))
+    }
+}
+
+// The Mandelbulb fractal of the given power. Its distance is only an
+// estimate, from the running derivative of the iteration.
+pub struct Mandelbulb<T: ElemT> {
+    pub power: T,
+    pub iterations: usize
+}
+
+impl<T: ElemT> Field<T> for Mandelbulb<T> {
+    fn distance(&self, p: &Vec3<T>) -> T {
+        let n = self.power;
+        let mut z = p.clone();
+        let mut dr = T::one();
+        let mut r = z.length();
+        for _ in 0..self.iterations {
+            if r > f(2.) {
+                break;
+            }
+            if r == T::zero() {
+                return -T::one();
+            }
+            // raise to the nth power in spherical coordinates about y
+            let theta = (z.y() / r).acos() * n;
+            let phi = z.z().atan2(z.x()) * n;
+            let r_n1 = r.powf(n - T::one());
+            dr = r_n1 * n * dr + T::one();
+            let zr = r_n1 * r;
+            z = Vec3::new(theta.sin()*phi.cos(), theta.cos(), theta.sin()*phi.sin()) * zr + p;
+            r = z.length();
+        }
+        f::<T>(0.5) * r.ln() * r / dr
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        Some(Aabb::new(splat(f(-1.25)), splat(f(1.25))))
+    }
+}
+
+// The shape moved by `offset`
+pub struct Translate<T: ElemT> {
+    pub field: Box<dyn Field<T>>,
+    pub offset: Vec3<T>
+}
+
+impl<T: ElemT> Field<T> for Translate<T> {
+    fn distance(&self, p: &Vec3<T>) -> T {
+        self.field.distance(&(p - &self.offset))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        let b = self.field.bounding_box()?;
+        Some(Aabb::new(b.min() + &self.offset, b.max() + &self.offset))
+    }
+}
+
+// Everything inside any of the shapes
+pub struct Union<T: ElemT> {
+    pub fields: Vec<Box<dyn Field<T>>>
+}
+
+impl<T: ElemT> Field<T> for Union<T> {
+    fn distance(&self, p: &Vec3<T>) -> T {
+        self.fields.iter().map(|field| field.distance(p)).fold(T::max_value(), T::min)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        union_box(&self.fields)
+    }
+}
+
+fn union_box<T: ElemT>(fields: &[Box<dyn Field<T>>]) -> Option<Aabb<T>> {
+    let mut boxes = fields.iter().map(|field| field.bounding_box());
+    let first = boxes.next()??;
+    boxes.try_fold(first, |acc, b| Some(surrounding_box(&acc, &b?)))
+}
+
+// A union that blends the shapes together where they come within `k` of
+// each other, with the polynomial smooth minimum
+pub struct SmoothUnion<T: ElemT> {
+    pub fields: Vec<Box<dyn Field<T>>>,
+    pub k: T
+}
+
+fn smooth_min<T: ElemT>(a: T, b: T, k: T) -> T {
+    let h = (k - (a - b).abs()).max(T::zero()) / k;
+    a.min(b) - h*h*k / f(4.)
+}
+
+impl<T: ElemT> Field<T> for SmoothUnion<T> {
+    fn distance(&self, p: &Vec3<T>) -> T {
+        let mut distances = self.fields.iter().map(|field| field.distance(p));
+        let first = distances.next().unwrap_or_else(T::max_value);
+        distances.fold(first, |a, b| smooth_min(a, b, self.k))
+    }
+
+    // the blend fills in by at most k/4
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        Some(grown(&union_box(&self.fields)?, self.k / f(4.)))
+    }
+}
+
+// Copies of the shape every `period` along each axis, centred on the
+// origin. A zero period leaves that axis alone. The shape should fit in one
+// cell or the copies cut into each other.
+pub struct Repeat<T: ElemT> {
+    pub field: Box<dyn Field<T>>,
+    pub period: Vec3<T>
+}
+
+impl<T: ElemT> Field<T> for Repeat<T> {
+    fn distance(&self, p: &Vec3<T>) -> T {
+        let mut q = p.clone();
+        for a in 0..3 {
+            let c = self.period[a];
+            if c > T::zero() {
+                q[a] = p[a] - c * (p[a] / c).round();
+            }
+        }
+        self.field.distance(&q)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        if (0..3).all(|a| self.period[a] <= T::zero()) { self.field.bounding_box() } else { None }
+    }
+}
+
+// The shape twisted about the y axis by `rate` radians per unit of height.
+// Twisting stretches space further from the axis, so the distance is scaled
+// down to stay a safe bound.
+pub struct Twist<T: ElemT> {
+    pub field: Box<dyn Field<T>>,
+    pub rate: T
+}
+
+impl<T: ElemT> Field<T> for Twist<T> {
+    fn distance(&self, p: &Vec3<T>) -> T {
+        let angle = self.rate * p.y();
+        let (s, c) = angle.sin_cos();
+        let q = Vec3::new(c*p.x() + s*p.z(), p.y(), c*p.z() - s*p.x());
+        let stretch = self.rate * (p.x()*p.x() + p.z()*p.z()).sqrt();
+        self.field.distance(&q) / (T::one() + stretch*stretch).sqrt()
+    }
+
+    // whatever the angle, the shape stays within its reach from the axis
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        let b = self.field.bounding_box()?;
+        let mut reach = T::zero();
+        for &x in &[b.min().x(), b.max().x()] {
+            for &z in &[b.min().z(), b.max().z()] {
+                reach = reach.max((x*x + z*z).sqrt());
+            }
+        }
+        Some(Aabb::new(Vec3::new(-reach, b.min().y(), -reach), Vec3::new(reach, b.max().y(), reach)))
+    }
+}
+
+// The shape grown by `radius`, which rounds off its edges
+pub struct Round<T: ElemT> {
+    pub field: Box<dyn Field<T>>,
+    pub radius: T
+}
+
+impl<T: ElemT> Field<T> for Round<T> {
+    fn distance(&self, p: &Vec3<T>) -> T {
+        self.field.distance(p) - self.radius
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        Some(grown(&self.field.bounding_box()?, self.radius))
+    }
+}
+
+// How close to the surface counts as a hit, at least, and as a fraction of
+// the way travelled, and the most steps a ray takes. Further off, finer
+// detail than that can't be seen anyway. Hits are then polished to within
+// EPSILON in a few more steps if they can be.
+const EPSILON: f64 = 1e-4;
+const RELATIVE_EPSILON: f64 = 1e-3;
+const MAX_STEPS: usize = 1000;
+const POLISH_STEPS: usize = 8;
+
+// A shape given by a distance field, found by sphere tracing: the ray can
+// safely go as far as the distance from where it is, so it steps by that
+// until it gets close enough. Normals come from the gradient of the field.
+// A shape with no bounding box of its own can be given one with
+// with_bounds; without any, rays march until t_max or they run out of steps.
+pub struct Sdf<T: ElemT> {
+    field: Box<dyn Field<T>>,
+    bounds: Option<Aabb<T>>,
+    material: Box<dyn Material<T>>
+}
+
+impl<T: ElemT> Sdf<T> {
+    pub fn new(field: Box<dyn Field<T>>, mat: Box<dyn Material<T>>) -> Sdf<T> {
+        Sdf { bounds: field.bounding_box(), field, material: mat }
+    }
+
+    pub fn with_bounds(mut self, bounds: Aabb<T>) -> Sdf<T> {
+        self.bounds = Some(bounds);
+        self
+    }
+
+    // Central differences
+    fn normal(&self, p: &Vec3<T>) -> Vec3<T> {
+        let h = f::<T>(EPSILON);
+        let mut gradient = Vec3::default();
+        for a in 0..3 {
+            let mut step = Vec3::default();
+            step[a] = h;
+            gradient[a] = self.field.distance(&(p + &step)) - self.field.distance(&(p - &step));
+        }
+        gradient.unit_vector()
+    }
+
+    // The crossing between a and b, which are on opposite sides
+    fn bisect(&self, r: &Ray<T>, mut a: T, mut b: T, side: T) -> T {
+        for _ in 0..30 {
+            let mid = (a + b) / f(2.);
+            if self.field.distance(&r.point_at_parameter(mid)).signum() == side { a = mid } else { b = mid }
+        }
+        (a + b) / f(2.)
+    }
+
+    // Takes a few more steps from a hit found with the looser threshold,
+    // so that it lies on the surface as nearly as a close one would
+    fn polish(&self, r: &Ray<T>, mut t: T, mut d: T, speed: T, side: T) -> T {
+        for _ in 0..POLISH_STEPS {
+            if d.abs() < f(EPSILON) {
+                break;
+            }
+            let next = t + d.abs() / speed;
+            let next_d = self.field.distance(&r.point_at_parameter(next));
+            if next_d.signum() != side {
+                return self.bisect(r, t, next, side);
+            }
+            t = next;
+            d = next_d;
+        }
+        t
+    }
+
+    fn trace(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<T> {
+        let (mut t, t_end) = match self.bounds {
+            Some(ref b) => b.interval(r, t_min, t_max)?,
+            None => (t_min, t_max)
+        };
+        let speed = r.direction().length();
+        let eps = |t: T| f::<T>(EPSILON).max((t - t_min) * speed * f(RELATIVE_EPSILON));
+        let mut d = self.field.distance(&r.point_at_parameter(t));
+        // the side the ray starts on, which tells rays leaving the surface
+        // from rays arriving at it
+        let side = d.signum();
+        let mut approaching = false;
+        for _ in 0..MAX_STEPS {
+            if approaching && d.abs() < eps(t) {
+                return Some(self.polish(r, t, d, speed, side));
+            }
+            let next = t + d.abs().max(eps(t)) / speed;
+            if next >= t_end {
+                return None;
+            }
+            let next_d = self.field.distance(&r.point_at_parameter(next));
+            // estimated distances can overshoot
+            if next_d.signum() != side {
+                return Some(self.bisect(r, t, next, side));
+            }
+            approaching = next_d.abs() < d.abs();
+            t = next;
+            d = next_d;
+        }
+        None
+    }
+}
+
+impl<T: ElemT> Hitable<T> for Sdf<T> {
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        let t = self.trace(r, t_min, t_max)?;
+        let p = r.point_at_parameter(t);
+        let normal = self.normal(&p);
+        let (u, v) = sphere_uv(&normal);
+        Some(HitRecord { t, u, v, p, normal, mat_opt: Some(&*self.material) })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        self.bounds.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BoxField, Field, Mandelbulb, Repeat, Round, Sdf, SmoothUnion, SphereField, Translate, Twist, Union};
+    use vec3::Vec3;
+    use rng::Sampler;
+    use ray::Ray;
+    use hitable::Hitable;
+    use aabb::Aabb;
+    use lambertian::Lambertian;
+    use dielectric::Dielectric;
+
+    fn grey() -> Box<Lambertian<f64>> {
+        Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn sphere(x: f64, radius: f64) -> Box<dyn Field<f64>> {
+        Box::new(Translate { field: Box::new(SphereField { radius }), offset: Vec3::new(x, 0., 0.) })
+    }
+
+    fn along_z(x: f64, y: f64) -> Ray<f64> {
+        Ray::new(Vec3::new(x, y, 10.), Vec3::new(0., 0., -2.))
+    }
+
+    #[test]
+    fn test_primitives() {
+        let s = Sdf::new(sphere(0., 1.), grey());
+        let rec = s.hit(&along_z(0.6, 0.), 0., 100.).unwrap();
+        assert_approx_eq!(0.8, rec.p.z(), 1e-3);
+        assert_approx_eq!(0.6, rec.normal.x(), 1e-3);
+        assert!(s.hit(&along_z(1.1, 0.), 0., 100.).is_none());
+        assert!(s.hit(&along_z(0., 0.), 0., 4.).is_none());
+
+        let b = BoxField { half: Vec3::new(1., 2., 0.5) };
+        assert_approx_eq!(-0.5f64, b.distance(&Vec3::new(0., 0., 0.)));
+        assert_approx_eq!(2.0f64.sqrt(), b.distance(&Vec3::new(2., 3., 0.)));
+        let b = Sdf::new(Box::new(b), grey());
+        let rec = b.hit(&along_z(0.5, 1.5), 0., 100.).unwrap();
+        assert_approx_eq!(0.5, rec.p.z(), 1e-3);
+        assert_approx_eq!(1.0, rec.normal.z(), 1e-3);
+
+        // a closure needs its bounds given
+        let closure = |p: &Vec3<f64>| p.y() + 1.;
+        let floor = Sdf::new(Box::new(closure), grey());
+        assert!(floor.bounding_box().is_none());
+        let rec = floor.hit(&Ray::new(Vec3::new(0., 4., 0.), Vec3::new(0., -1., 1.)), 0., 100.).unwrap();
+        assert_approx_eq!(5.0, rec.t, 1e-3);
+        let bounded = Sdf::new(Box::new(closure), grey()).with_bounds(Aabb::new(Vec3::new(-1., -2., -1.), Vec3::new(1., 0., 1.)));
+        assert!(bounded.hit(&Ray::new(Vec3::new(3., 4., 0.), Vec3::new(0., -1., 0.)), 0., 100.).is_none());
+    }
+
+    #[test]
+    fn test_combinators() {
+        // two spheres 2.2 apart don't touch, but blended they're joined
+        let pair = || vec![sphere(-1.1, 1.), sphere(1.1, 1.)];
+        let middle = Vec3::new(0., 0., 0.);
+        assert!(Union { fields: pair() }.distance(&middle) > 0.);
+        let blend = SmoothUnion { fields: pair(), k: 1. };
+        assert!(blend.distance(&middle) < 0.);
+        assert!(blend.bounding_box().unwrap().max().x() > 2.1);
+
+        let grid = Repeat { field: Box::new(SphereField { radius: 0.5 }), period: Vec3::new(2., 0., 2.) };
+        assert_approx_eq!(-0.5f64, grid.distance(&Vec3::new(40., 0., -6.)));
+        assert_approx_eq!(0.5f64, grid.distance(&Vec3::new(41., 0., -6.)));
+        assert_approx_eq!(4.5f64, grid.distance(&Vec3::new(40., 5., -6.)));
+        assert!(grid.bounding_box().is_none());
+
+        let rounded = Round { field: Box::new(BoxField { half: Vec3::new(1., 1., 1.) }), radius: 0.25 };
+        assert_approx_eq!(0.25f64, rounded.distance(&Vec3::new(1.5, 0., 0.)));
+        // a corner is rounded off
+        assert_approx_eq!(3f64.sqrt()*0.5 - 0.25, rounded.distance(&Vec3::new(1.5, 1.5, 1.5)));
+
+        // a quarter turn at y = 1 brings the long side of a slab round to z
+        let slab = || Box::new(BoxField { half: Vec3::new(2., 2., 0.2) });
+        let twisted = Twist { field: slab(), rate: ::std::f64::consts::PI / 2. };
+        assert!(twisted.distance(&Vec3::new(1.5, 0., 0.)) < 0.);
+        assert!(twisted.distance(&Vec3::new(1.5, 1., 0.)) > 0.);
+        assert!(twisted.distance(&Vec3::new(0., 1., 1.5)) < 0.);
+        let b = twisted.bounding_box().unwrap();
+        assert_approx_eq!(-(4.04f64.sqrt()), b.min().z());
+        // the scaled distance is still a safe step
+        let s = Sdf::new(Box::new(twisted), grey());
+        let rec = s.hit(&Ray::new(Vec3::new(0., 1., 10.), Vec3::new(0., 0., -1.)), 0., 100.).unwrap();
+        assert_approx_eq!(2.0, rec.p.z(), 1e-3);
+    }
+
+    #[test]
+    fn test_fractal() {
+        let bulb = Sdf::new(Box::new(Mandelbulb { power: 8., iterations: 12 }), grey());
+        let rec = bulb.hit(&Ray::new(Vec3::new(0.1, 0.2, 3.), Vec3::new(0., 0., -1.)), 0., 100.).unwrap();
+        assert!(rec.p.length() < 1.25);
+        assert!(rec.normal.z() > 0.);
+        assert!(bulb.hit(&Ray::new(Vec3::new(0., 2., 3.), Vec3::new(0., 0., -1.)), 0., 100.).is_none());
+    }
+
+    #[test]
+    fn test_glass() {
+        // a refracted ray starts on the surface inside the shape and has to
+        // find the far side, not where it started
+        let ball = Sdf::new(Box::new(SmoothUnion { fields: vec![sphere(-0.3, 1.), sphere(0.3, 1.)], k: 0.5 }),
+                            Box::new(Dielectric::new(1.5)));
+        let r = Ray::new(Vec3::new(0.2, 0.3, 5.), Vec3::new(0., 0., -1.));
+        let rec = ball.hit(&r, 0.001, 100.).unwrap();
+        assert!(rec.normal.dot(&r.direction()) < 0.);
+        let mut sampler = Sampler::new(0);
+        let (_, inner) = (0..20).filter_map(|_| rec.mat_opt.unwrap().scatter(&r, &rec, &mut sampler))
+            .find(|(_, s)| s.direction().z() < -0.5).unwrap();
+        let exit = ball.hit(&inner, 0.001, 100.).unwrap();
+        assert!(exit.p.z() < -0.5);
+        assert!(exit.normal.dot(&inner.direction()) > 0.);
+    }
+}
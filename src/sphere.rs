@@ -1,16 +1,22 @@
+use std::f64::consts::PI;
+
 use vec3::{ElemT, Vec3};
 use ray::Ray;
 use hitable::{HitRecord, Hitable};
 use material::Material;
+use aabb::Aabb;
+use onb::Onb;
+use rng::Sampler;
+use sampling;
 
 pub struct Sphere<T: ElemT> {
     center: Vec3<T>,
     radius: T,
-    material: Box<Material<T>>
+    material: Box<dyn Material<T>>
 }
 
 impl<T: ElemT> Sphere<T> {
-    pub fn new(cen: Vec3<T>, r: T, mat: Box<Material<T>>) -> Sphere<T> {
+    pub fn new(cen: Vec3<T>, r: T, mat: Box<dyn Material<T>>) -> Sphere<T> {
         Sphere {
             center: cen,
             radius: r,
@@ -19,9 +25,39 @@ impl<T: ElemT> Sphere<T> {
     }
 }
 
+// Spherical coordinates of a point on the unit sphere mapped to [0, 1]: u
+// goes around the y axis from -x through +z, v from the bottom pole to the top
+pub fn sphere_uv<T: ElemT>(p: &Vec3<T>) -> (T, T) {
+    let pi = T::from_f64(PI).unwrap();
+    let theta = (-p.y()).max(-T::one()).min(T::one()).acos();
+    let phi = (-p.z()).atan2(p.x()) + pi;
+    (phi / (pi + pi), theta / pi)
+}
+
+impl<T: ElemT> Sphere<T> {
+    // Cosine of the half angle of the cone the sphere covers seen from
+    // `origin`, or None when `origin` is inside it
+    fn cos_theta_max(&self, origin: &Vec3<T>) -> Option<T> {
+        let dist_squared = (&self.center - origin).squared_length();
+        let r_squared = self.radius*self.radius;
+        if dist_squared <= r_squared {
+            return None;
+        }
+        Some((T::one() - r_squared/dist_squared).sqrt())
+    }
+
+    // Solid angle pdf of sampling directions uniformly inside that cone, or
+    // over the whole sphere of directions from inside
+    fn cone_pdf(cos_theta_max: Option<T>) -> T {
+        match cos_theta_max {
+            Some(cos_theta_max) => sampling::uniform_cone_pdf(cos_theta_max),
+            None => sampling::uniform_sphere_pdf()
+        }
+    }
+}
+
 impl<T: ElemT> Hitable<T> for Sphere<T> {
-    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<T>>
-{
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
         let oc = r.origin() - &self.center;
         let a = r.direction().dot(&r.direction());
         let b = oc.dot(&r.direction());
@@ -34,6 +70,9 @@ impl<T: ElemT> Hitable<T> for Sphere<T> {
                 rec.t = temp;
                 rec.p = r.point_at_parameter(rec.t);
                 rec.normal = &(&rec.p - &self.center) / self.radius;
+                let (u, v) = sphere_uv(&rec.normal);
+                rec.u = u;
+                rec.v = v;
                 rec.mat_opt = Some(&*self.material);
                 return Some(rec);
             }
@@ -43,10 +82,68 @@ impl<T: ElemT> Hitable<T> for Sphere<T> {
                 rec.t = temp;
                 rec.p = r.point_at_parameter(rec.t);
                 rec.normal = &(&rec.p - &self.center) / self.radius;
+                let (u, v) = sphere_uv(&rec.normal);
+                rec.u = u;
+                rec.v = v;
                 rec.mat_opt = Some(&*self.material);
                 return Some(rec);
             }
         }
         None
     }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        // a negative radius (a hollow sphere, normals pointing in) bounds
+        // the same space as a positive one
+        let r = Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs());
+        Some(Aabb::new(&self.center - &r, &self.center + &r))
+    }
+
+    // Samples the cone of directions the sphere subtends rather than its
+    // area, so no samples are wasted on the far side
+    fn sample(&self, origin: &Vec3<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, T)> {
+        let cos_theta_max = self.cos_theta_max(origin);
+        let t = |x: f64| T::from_f64(x).unwrap();
+        let (u1, u2) = (t(sampler.next_f64()), t(sampler.next_f64()));
+        let w = match cos_theta_max {
+            Some(cos_theta_max) => sampling::uniform_cone(u1, u2, cos_theta_max),
+            None => sampling::uniform_sphere(u1, u2)
+        };
+        let direction = Onb::from_w(&(&self.center - origin)).local(w.x(), w.y(), w.z());
+
+        let rec = self.hit(&Ray::new(origin.clone(), direction), T::zero(), T::max_value())?;
+        Some((rec.p, Sphere::cone_pdf(cos_theta_max)))
+    }
+
+    fn pdf(&self, origin: &Vec3<T>, direction: &Vec3<T>) -> T {
+        match self.hit(&Ray::new(origin.clone(), direction.clone()), T::zero(), T::max_value()) {
+            Some(_) => Sphere::cone_pdf(self.cos_theta_max(origin)),
+            None => T::zero()
+        }
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Hitable<T>>) {
+        if self.material.is_emissive() {
+            lights.push(self);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sphere_uv;
+    use vec3::Vec3;
+
+    #[test]
+    fn test_sphere_uv() {
+        let uv = |x: f64, y: f64, z: f64| sphere_uv(&Vec3::new(x, y, z));
+        let (u, v) = uv(1., 0., 0.);
+        assert_approx_eq!(0.5, u);
+        assert_approx_eq!(0.5, v);
+        assert_approx_eq!(0.25, uv(0., 0., 1.).0);
+        assert_approx_eq!(0.75, uv(0., 0., -1.).0);
+        assert_approx_eq!(0.0, uv(-1., 0., 1e-12).0);
+        assert_approx_eq!(1.0, uv(0., 1., 0.).1);
+        assert_approx_eq!(0.0, uv(0., -1., 0.).1);
+    }
 }
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use vec3::{ElemT, Vec3};
+
+// Colour as a function of surface coordinates (u, v) and/or the hit point.
+// Textures are shared between materials, hence Send + Sync and the Arcs.
+pub trait Texture<T: ElemT>: Send + Sync {
+    fn value(&self, u: T, v: T, p: &Vec3<T>) -> Vec3<T>;
+}
+
+pub struct ConstantTexture<T: ElemT> {
+    color: Vec3<T>
+}
+
+impl<T: ElemT> ConstantTexture<T> {
+    pub fn new(color: Vec3<T>) -> ConstantTexture<T> {
+        ConstantTexture { color }
+    }
+}
+
+impl<T: ElemT> Texture<T> for ConstantTexture<T> {
+    #[allow(unused_variables)]
+    fn value(&self, u: T, v: T, p: &Vec3<T>) -> Vec3<T> {
+        self.color.clone()
+    }
+}
+
+// Solid checkerboard filling space with cells pi/scale wide that alternate
+// between two textures
+pub struct CheckerTexture<T: ElemT> {
+    odd: Arc<dyn Texture<T>>,
+    even: Arc<dyn Texture<T>>,
+    scale: T
+}
+
+impl<T: ElemT> CheckerTexture<T> {
+    pub fn new(odd: Arc<dyn Texture<T>>, even: Arc<dyn Texture<T>>, scale: T) -> CheckerTexture<T> {
+        CheckerTexture { odd, even, scale }
+    }
+}
+
+impl<T: ElemT> Texture<T> for CheckerTexture<T> {
+    fn value(&self, u: T, v: T, p: &Vec3<T>) -> Vec3<T> {
+        let sines = (self.scale*p.x()).sin() * (self.scale*p.y()).sin() * (self.scale*p.z()).sin();
+        if sines < T::zero() { self.odd.value(u, v, p) } else { self.even.value(u, v, p) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CheckerTexture, ConstantTexture, Texture};
+    use std::sync::Arc;
+    use vec3::Vec3;
+
+    #[test]
+    fn test_checker() {
+        let black = Arc::new(ConstantTexture::new(Vec3::new(0., 0., 0.)));
+        let white = Arc::new(ConstantTexture::new(Vec3::new(1., 1., 1.)));
+        let checker = CheckerTexture::new(black, white, 10.);
+        let at = |x: f64, y: f64, z: f64| checker.value(0., 0., &Vec3::new(x, y, z)).x();
+        assert_eq!(1.0, at(0.05, 0.05, 0.05));
+        assert_eq!(0.0, at(0.4, 0.05, 0.05));
+        assert_eq!(1.0, at(0.4, 0.4, 0.05));
+        assert_eq!(0.0, at(-0.05, 0.05, 0.05));
+    }
+}
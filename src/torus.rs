@@ -0,0 +1,107 @@
+use std::f64::consts::PI;
+
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+use hitable::{HitRecord, Hitable};
+use material::Material;
+use aabb::Aabb;
+use quadric::{phi, sweep_radians, LocalHit, LocalRay};
+use roots;
+
+// A torus lying in the xz plane around `center`: a tube of radius `minor`
+// whose centre line is a circle of radius `major`. Like the quadrics it can
+// be cut to a partial sweep around y. u goes around the y axis, v around the
+// tube starting from its outer equator.
+pub struct Torus<T: ElemT> {
+    center: Vec3<T>,
+    major: T,
+    minor: T,
+    sweep: T,
+    material: Box<dyn Material<T>>
+}
+
+impl<T: ElemT> Torus<T> {
+    pub fn new(center: Vec3<T>, major: T, minor: T, sweep: T, mat: Box<dyn Material<T>>) -> Torus<T> {
+        Torus { center, major, minor, sweep: sweep_radians(sweep), material: mat }
+    }
+}
+
+impl<T: ElemT> Hitable<T> for Torus<T> {
+    // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2), a quartic in t. It's solved
+    // for a unit direction from the point on the ray nearest the centre,
+    // which keeps the coefficients small for rays from far away.
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        let two = T::from_f64(2.).unwrap();
+        let four = two + two;
+        let l = LocalRay::new(r, &self.center);
+        let scale = l.d.length();
+        let d = &l.d / scale;
+        let shift = -l.o.dot(&d);
+        let o = &l.o + &d*shift;
+
+        let r2 = self.major*self.major;
+        let od = o.dot(&d);
+        let e = o.dot(&o) + r2 - self.minor*self.minor;
+        let roots = roots::quartic(e*e - four*r2*(o.x()*o.x() + o.z()*o.z()),
+                                   four*od*e - four*two*r2*(o.x()*d.x() + o.z()*d.z()),
+                                   four*od*od + two*e - four*r2*(d.x()*d.x() + d.z()*d.z()),
+                                   four*od,
+                                   T::one());
+
+        let (t, p) = roots.into_iter()
+            .map(|t| (t + shift) / scale)
+            .filter(|&t| t > t_min && t < t_max)
+            .map(|t| (t, l.at(t)))
+            .find(|(_, p)| phi(p) <= self.sweep)?;
+
+        // the normal points away from the nearest point on the centre circle
+        let rho = (p.x()*p.x() + p.z()*p.z()).sqrt();
+        let ring = if rho > T::zero() { Vec3::new(p.x(), T::zero(), p.z()) * (self.major / rho) } else { Vec3::default() };
+        let normal = &p - &ring;
+        let pi = T::from_f64(PI).unwrap();
+        let theta = p.y().atan2(rho - self.major);
+        let theta = if theta < T::zero() { theta + pi + pi } else { theta };
+        Some(LocalHit { t, normal, u: phi(&p) / self.sweep, v: theta / (pi + pi) }.record(r, &*self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        let outer = self.major + self.minor;
+        let extent = Vec3::new(outer, self.minor, outer);
+        Some(Aabb::new(&self.center - &extent, &self.center + &extent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Torus;
+    use vec3::Vec3;
+    use ray::Ray;
+    use hitable::Hitable;
+    use lambertian::Lambertian;
+    use quadric::{self, tests::compare};
+
+    fn torus_f(p: &Vec3<f64>, major: f64, minor: f64) -> f64 {
+        let k = p.squared_length() + major*major - minor*minor;
+        k*k - 4.*major*major*(p.x()*p.x() + p.z()*p.z())
+    }
+
+    #[test]
+    fn test_torus() {
+        let grey = || Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+        let torus = Torus::new(Vec3::new(0., 0., 0.), 0.8, 0.3, 360., grey());
+        compare(&torus, &|p| torus_f(p, 0.8, 0.3), &|_| true, 11);
+
+        // through the hole and out the other side
+        let r = Ray::new(Vec3::new(-5., 0., 0.), Vec3::new(1., 0., 0.));
+        let rec = torus.hit(&r, 0., 100.).unwrap();
+        assert_approx_eq!(3.9, rec.t);
+        assert_approx_eq!(-1.0, rec.normal.x());
+        assert_approx_eq!(4.5, torus.hit(&r, 4., 100.).unwrap().t);
+        // far away, where a naive quartic loses its precision
+        let far = Ray::new(Vec3::new(0., 0.1, -1e4), Vec3::new(0., 0., 1.));
+        assert_approx_eq!(1e4 - 0.8 - (0.09f64 - 0.01).sqrt(), torus.hit(&far, 0., 2e4).unwrap().t, 1e-6);
+
+        let partial = Torus::new(Vec3::new(0., 0., 0.), 0.8, 0.3, 90., grey());
+        compare(&partial, &|p| torus_f(p, 0.8, 0.3), &|p| quadric::phi(p) <= 90f64.to_radians(), 12);
+    }
+}
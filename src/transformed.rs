@@ -0,0 +1,234 @@
+use std::sync::Arc;
+
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+use hitable::{HitRecord, Hitable};
+use aabb::Aabb;
+use mat4::Mat4;
+use rng::Sampler;
+
+fn ray_to_object<T: ElemT>(to_object: &Mat4<T>, r: &Ray<T>) -> Ray<T> {
+    Ray::with_time(to_object.transform_point(&r.origin()), to_object.transform_vector(&r.direction()), r.time())
+}
+
+// Hits `object` as seen through the inverse of `to_object`, with the hit
+// moved back into world space
+pub fn hit_transformed<'a, T: ElemT>(object: &'a dyn Hitable<T>, to_object: &Mat4<T>,
+                                     r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'a, T>> {
+    let rec = object.hit(&ray_to_object(to_object, r), t_min, t_max)?;
+    Some(to_world(to_object, r, rec))
+}
+
+// The same for hit_surface
+pub fn hit_surface_transformed<'a, T: ElemT>(object: &'a dyn Hitable<T>, to_object: &Mat4<T>,
+                                             r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'a, T>> {
+    let rec = object.hit_surface(&ray_to_object(to_object, r), t_min, t_max)?;
+    Some(to_world(to_object, r, rec))
+}
+
+pub fn transmittance_transformed<T: ElemT>(object: &dyn Hitable<T>, to_object: &Mat4<T>, r: &Ray<T>, t_min: T, t_max: T) -> T {
+    object.transmittance(&ray_to_object(to_object, r), t_min, t_max)
+}
+
+fn to_world<'a, T: ElemT>(to_object: &Mat4<T>, r: &Ray<T>, mut rec: HitRecord<'a, T>) -> HitRecord<'a, T> {
+    rec.p = r.point_at_parameter(rec.t);
+    rec.normal = to_object.transform_normal_by_inverse(&rec.normal).unit_vector();
+    rec
+}
+
+// An instance of a shared object under an affine transform. Rays are moved
+// into object space (without renormalising the direction, so t carries over
+// unchanged) and hits are moved back out. Any number of instances can share
+// one object, e.g. a mesh, without copying it.
+pub struct Transformed<T: ElemT> {
+    object: Arc<dyn Hitable<T>>,
+    to_world: Mat4<T>,
+    to_object: Mat4<T>,
+    bbox: Option<Aabb<T>>
+}
+
+impl<T: ElemT> Transformed<T> {
+    // Panics if `to_world` isn't invertible
+    pub fn new(object: Arc<dyn Hitable<T>>, to_world: Mat4<T>) -> Transformed<T> {
+        let to_object = to_world.inverse().expect("transform must be invertible");
+        let bbox = object.bounding_box().map(|b| {
+            let corners = (0..8).map(|i| {
+                let pick = |bit: usize, a: usize| if i & bit == 0 { b.min()[a] } else { b.max()[a] };
+                to_world.transform_point(&Vec3::new(pick(1, 0), pick(2, 1), pick(4, 2)))
+            }).collect::<Vec<_>>();
+            let mut min = corners[0].clone();
+            let mut max = corners[0].clone();
+            for c in &corners[1..] {
+                for a in 0..3 {
+                    min[a] = min[a].min(c[a]);
+                    max[a] = max[a].max(c[a]);
+                }
+            }
+            Aabb::new(min, max)
+        });
+        Transformed { object, to_world, to_object, bbox }
+    }
+
+    fn ray_to_object(&self, r: &Ray<T>) -> Ray<T> {
+        ray_to_object(&self.to_object, r)
+    }
+
+    // A transform changes solid angles as well: a unit world direction `d`
+    // maps to M d / |M d|, with M the linear part of `to_object`, and the
+    // solid angle around it scales by |det M| / |M d|^3
+    fn solid_angle_scale(&self, direction: &Vec3<T>) -> T {
+        let m_d = self.to_object.transform_vector(&direction.unit_vector());
+        self.to_object.det3().abs() / m_d.length().powi(3)
+    }
+
+    // Light sampling has to see all of the object's emitters at once; they're
+    // gathered on demand since the object is shared and can't hold references
+    // into itself
+    fn lights(&self) -> Vec<&dyn Hitable<T>> {
+        let mut lights = Vec::new();
+        self.object.collect_lights(&mut lights);
+        lights
+    }
+
+    fn object_pdf(lights: &[&dyn Hitable<T>], origin: &Vec3<T>, direction: &Vec3<T>) -> T {
+        let sum = lights.iter().fold(T::zero(), |sum, l| sum + l.pdf(origin, direction));
+        sum / T::from_usize(lights.len()).unwrap()
+    }
+}
+
+impl<T: ElemT> Hitable<T> for Transformed<T> {
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        hit_transformed(&*self.object, &self.to_object, r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        self.bbox.clone()
+    }
+
+    fn hit_all(&self, r: &Ray<T>, t_min: T, t_max: T) -> Vec<HitRecord<'_, T>> {
+        self.object.hit_all(&self.ray_to_object(r), t_min, t_max).into_iter()
+            .map(|rec| to_world(&self.to_object, r, rec))
+            .collect()
+    }
+
+    fn hit_surface(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        hit_surface_transformed(&*self.object, &self.to_object, r, t_min, t_max)
+    }
+
+    fn transmittance(&self, r: &Ray<T>, t_min: T, t_max: T) -> T {
+        transmittance_transformed(&*self.object, &self.to_object, r, t_min, t_max)
+    }
+
+    fn sample(&self, origin: &Vec3<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, T)> {
+        let lights = self.lights();
+        if lights.is_empty() {
+            return None;
+        }
+        let local_origin = self.to_object.transform_point(origin);
+        let n = lights.len();
+        let i = ((sampler.next_f64() * n as f64) as usize).min(n - 1);
+        let (p, pdf) = lights[i].sample(&local_origin, sampler)?;
+        let pdf = if n == 1 { pdf } else { Transformed::object_pdf(&lights, &local_origin, &(&p - &local_origin)) };
+
+        let p = self.to_world.transform_point(&p);
+        let scale = self.solid_angle_scale(&(&p - origin));
+        Some((p, pdf * scale))
+    }
+
+    fn pdf(&self, origin: &Vec3<T>, direction: &Vec3<T>) -> T {
+        let lights = self.lights();
+        if lights.is_empty() {
+            return T::zero();
+        }
+        let r = self.ray_to_object(&Ray::new(origin.clone(), direction.clone()));
+        Transformed::object_pdf(&lights, &r.origin(), &r.direction()) * self.solid_angle_scale(direction)
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Hitable<T>>) {
+        if !self.lights().is_empty() {
+            lights.push(self);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Transformed;
+    use std::sync::Arc;
+    use vec3::Vec3;
+    use ray::Ray;
+    use hitable::Hitable;
+    use mat4::Mat4;
+    use sphere::Sphere;
+    use lambertian::Lambertian;
+    use diffuselight::DiffuseLight;
+    use lightlist::LightList;
+    use rng::Sampler;
+    use sampling;
+
+    fn unit_sphere() -> Arc<dyn Hitable<f64>> {
+        Arc::new(Sphere::new(Vec3::new(0., 0., 0.), 1., Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))))
+    }
+
+    #[test]
+    fn test_hit_scaled_and_moved() {
+        // an ellipsoid with semi-axes 2, 1, 1 centred on (0, 0, -5)
+        let m = &Mat4::translation(&Vec3::new(0., 0., -5.)) * &Mat4::scaling(&Vec3::new(2., 1., 1.));
+        let ellipsoid = Transformed::new(unit_sphere(), m);
+
+        let rec = ellipsoid.hit(&Ray::new(Vec3::new(10., 0., -5.), Vec3::new(-1., 0., 0.)), 0., 100.).unwrap();
+        assert_approx_eq!(8.0, rec.t);
+        assert_approx_eq!(2.0, rec.p.x());
+        assert_approx_eq!(1.0, rec.normal.x());
+
+        // off-axis the normal is the inverse transpose one, not just scaled
+        // (1.2, 0.48, 0.64) is (0.6, 0.48, 0.64) on the unit sphere stretched in x
+        let p = Vec3::new(1.2, 0.48, -4.36);
+        let rec = ellipsoid.hit(&Ray::new(&p + &Vec3::new(0., 0., 3.), Vec3::new(0., 0., -1.)), 0., 100.).unwrap();
+        assert_approx_eq!(3.0, rec.t);
+        let expected = Vec3::new(0.3, 0.48, 0.64).unit_vector();
+        assert_approx_eq!(expected.x(), rec.normal.x());
+        assert_approx_eq!(expected.y(), rec.normal.y());
+        assert_approx_eq!(expected.z(), rec.normal.z());
+
+        let bbox = ellipsoid.bounding_box().unwrap();
+        assert_approx_eq!(-2.0, bbox.min().x());
+        assert_approx_eq!(-6.0, bbox.min().z());
+    }
+
+    #[test]
+    fn test_shared_instances() {
+        let sphere = unit_sphere();
+        let a = Transformed::new(sphere.clone(), Mat4::translation(&Vec3::new(-3., 0., 0.)));
+        let b = Transformed::new(sphere.clone(), Mat4::rotation(&Vec3::new(0., 1., 0.), 45.));
+        let r = Ray::new(Vec3::new(-3., 0., 5.), Vec3::new(0., 0., -1.));
+        assert_approx_eq!(4.0, a.hit(&r, 0., 100.).unwrap().t);
+        assert!(b.hit(&r, 0., 100.).is_none());
+        assert_eq!(3, Arc::strong_count(&sphere));
+    }
+
+    #[test]
+    fn test_light_pdf() {
+        let light: Arc<dyn Hitable<f64>> = Arc::new(Sphere::new(Vec3::new(0., 0., 0.), 1., Box::new(DiffuseLight::new(Vec3::new(1., 1., 1.)))));
+        let m = &Mat4::translation(&Vec3::new(0., 0., -4.)) * &Mat4::scaling(&Vec3::new(2., 0.5, 1.));
+        let ellipsoid = Transformed::new(light, m);
+        let lights = LightList::new(&ellipsoid);
+        let origin = Vec3::new(0.3, 0.2, 0.);
+
+        let mut sampler = Sampler::new(3);
+        for _ in 0..50 {
+            let (d, pdf) = lights.sample(&origin, &mut sampler).unwrap();
+            assert_approx_eq!(pdf, lights.pdf(&origin, &d), 1e-9);
+        }
+
+        // the pdf still integrates to one over the sphere of directions
+        let n = 200000;
+        let mut sum = 0.;
+        for _ in 0..n {
+            let d = sampling::uniform_sphere(sampler.next_f64(), sampler.next_f64());
+            sum += lights.pdf(&origin, &d);
+        }
+        let integral = sum * 4.*::std::f64::consts::PI / n as f64;
+        assert!((integral - 1.).abs() < 0.03, "integral {}", integral);
+    }
+}
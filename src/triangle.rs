@@ -0,0 +1,194 @@
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+use hitable::{area_to_solid_angle, HitRecord, Hitable};
+use material::Material;
+use aabb::Aabb;
+use rng::Sampler;
+use sampling;
+
+// Möller–Trumbore ray/triangle intersection. Returns the ray parameter and the
+// barycentric coordinates (b1, b2) of the hit relative to v1 and v2.
+pub fn intersect<T: ElemT>(r: &Ray<T>, v0: &Vec3<T>, v1: &Vec3<T>, v2: &Vec3<T>, t_min: T, t_max: T) -> Option<(T, T, T)> {
+    let direction = r.direction();
+    let e1 = v1 - v0;
+    let e2 = v2 - v0;
+    let pvec = direction.cross(&e2);
+    let det = e1.dot(&pvec);
+    if det.abs() < T::epsilon() { // ray is parallel to the triangle
+        return None;
+    }
+    let inv_det = T::one() / det;
+
+    let tvec = r.origin() - v0;
+    let b1 = tvec.dot(&pvec) * inv_det;
+    if b1 < T::zero() || b1 > T::one() {
+        return None;
+    }
+    let qvec = tvec.cross(&e1);
+    let b2 = direction.dot(&qvec) * inv_det;
+    if b2 < T::zero() || b1 + b2 > T::one() {
+        return None;
+    }
+
+    let t = e2.dot(&qvec) * inv_det;
+    if t < t_max && t > t_min { Some((t, b1, b2)) } else { None }
+}
+
+pub fn bounding_box<T: ElemT>(v0: &Vec3<T>, v1: &Vec3<T>, v2: &Vec3<T>) -> Aabb<T> {
+    let min = Vec3::new(v0.x().min(v1.x()).min(v2.x()),
+                        v0.y().min(v1.y()).min(v2.y()),
+                        v0.z().min(v1.z()).min(v2.z()));
+    let max = Vec3::new(v0.x().max(v1.x()).max(v2.x()),
+                        v0.y().max(v1.y()).max(v2.y()),
+                        v0.z().max(v1.z()).max(v2.z()));
+    Aabb::new(min, max)
+}
+
+// Picks a point uniformly over the triangle's area. Returns it with its
+// solid angle pdf seen from `origin`.
+pub fn sample<T: ElemT>(v0: &Vec3<T>, v1: &Vec3<T>, v2: &Vec3<T>, origin: &Vec3<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, T)> {
+    let (b0, b1, b2) = sampling::uniform_triangle(T::from_f64(sampler.next_f64()).unwrap(), T::from_f64(sampler.next_f64()).unwrap());
+    let p = v0*b0 + v1*b1 + v2*b2;
+    let pdf = solid_angle_pdf(v0, v1, v2, origin, &p);
+    if pdf > T::zero() { Some((p, pdf)) } else { None }
+}
+
+// Density of sample() producing `direction` from `origin`
+pub fn pdf<T: ElemT>(v0: &Vec3<T>, v1: &Vec3<T>, v2: &Vec3<T>, origin: &Vec3<T>, direction: &Vec3<T>) -> T {
+    let r = Ray::new(origin.clone(), direction.clone());
+    match intersect(&r, v0, v1, v2, T::zero(), T::max_value()) {
+        Some((t, _, _)) => solid_angle_pdf(v0, v1, v2, origin, &r.point_at_parameter(t)),
+        None => T::zero()
+    }
+}
+
+fn solid_angle_pdf<T: ElemT>(v0: &Vec3<T>, v1: &Vec3<T>, v2: &Vec3<T>, origin: &Vec3<T>, p: &Vec3<T>) -> T {
+    let cross = (v1 - v0).cross(&(v2 - v0));
+    let area = cross.length() / T::from_f64(2.).unwrap();
+    if area <= T::zero() {
+        return T::zero();
+    }
+    area_to_solid_angle(sampling::uniform_triangle_pdf(area), origin, p, &cross)
+}
+
+// Interpolates per-vertex normals at barycentric (b1, b2)
+pub fn shading_normal<T: ElemT>(n0: &Vec3<T>, n1: &Vec3<T>, n2: &Vec3<T>, b1: T, b2: T) -> Vec3<T> {
+    (n0*(T::one() - b1 - b2) + n1*b1 + n2*b2).unit_vector()
+}
+
+pub struct Triangle<T: ElemT> {
+    v0: Vec3<T>,
+    v1: Vec3<T>,
+    v2: Vec3<T>,
+    normals: Option<[Vec3<T>; 3]>,
+    material: Box<dyn Material<T>>
+}
+
+impl<T: ElemT> Triangle<T> {
+    pub fn new(v0: Vec3<T>, v1: Vec3<T>, v2: Vec3<T>, mat: Box<dyn Material<T>>) -> Triangle<T> {
+        Triangle {
+            v0,
+            v1,
+            v2,
+            normals: None,
+            material: mat
+        }
+    }
+
+    pub fn with_normals(v0: Vec3<T>, v1: Vec3<T>, v2: Vec3<T>, normals: [Vec3<T>; 3], mat: Box<dyn Material<T>>) -> Triangle<T> {
+        Triangle {
+            v0,
+            v1,
+            v2,
+            normals: Some(normals),
+            material: mat
+        }
+    }
+}
+
+impl<T: ElemT> Hitable<T> for Triangle<T> {
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        let (t, b1, b2) = intersect(r, &self.v0, &self.v1, &self.v2, t_min, t_max)?;
+        let mut rec = HitRecord::<T>::default();
+        rec.t = t;
+        // without texture coordinates of its own a triangle is parameterised
+        // by its barycentrics
+        rec.u = b1;
+        rec.v = b2;
+        rec.p = r.point_at_parameter(rec.t);
+        rec.normal = match self.normals {
+            Some([ref n0, ref n1, ref n2]) => shading_normal(n0, n1, n2, b1, b2),
+            None => (&self.v1 - &self.v0).cross(&(&self.v2 - &self.v0)).unit_vector()
+        };
+        rec.mat_opt = Some(&*self.material);
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        Some(bounding_box(&self.v0, &self.v1, &self.v2))
+    }
+
+    fn sample(&self, origin: &Vec3<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, T)> {
+        sample(&self.v0, &self.v1, &self.v2, origin, sampler)
+    }
+
+    fn pdf(&self, origin: &Vec3<T>, direction: &Vec3<T>) -> T {
+        pdf(&self.v0, &self.v1, &self.v2, origin, direction)
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Hitable<T>>) {
+        if self.material.is_emissive() {
+            lights.push(self);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{intersect, Triangle};
+    use vec3::Vec3;
+    use ray::Ray;
+    use hitable::Hitable;
+    use lambertian::Lambertian;
+
+    fn unit_triangle() -> (Vec3<f64>, Vec3<f64>, Vec3<f64>) {
+        (Vec3::new(0., 0., 0.), Vec3::new(1., 0., 0.), Vec3::new(0., 1., 0.))
+    }
+
+    #[test]
+    fn test_intersect() {
+        let (v0, v1, v2) = unit_triangle();
+        let r = Ray::new(Vec3::new(0.25, 0.5, 2.), Vec3::new(0., 0., -1.));
+        let (t, b1, b2) = intersect(&r, &v0, &v1, &v2, 0., 100.).unwrap();
+        assert_approx_eq!(2.0, t);
+        assert_approx_eq!(0.25, b1);
+        assert_approx_eq!(0.5, b2);
+    }
+
+    #[test]
+    fn test_miss() {
+        let (v0, v1, v2) = unit_triangle();
+        // outside the hypotenuse
+        let r = Ray::new(Vec3::new(0.6, 0.6, 2.), Vec3::new(0., 0., -1.));
+        assert!(intersect(&r, &v0, &v1, &v2, 0., 100.).is_none());
+        // parallel to the plane
+        let r = Ray::new(Vec3::new(0.2, 0.2, 1.), Vec3::new(1., 0., 0.));
+        assert!(intersect(&r, &v0, &v1, &v2, 0., 100.).is_none());
+        // behind the origin
+        let r = Ray::new(Vec3::new(0.2, 0.2, -1.), Vec3::new(0., 0., -1.));
+        assert!(intersect(&r, &v0, &v1, &v2, 0., 100.).is_none());
+    }
+
+    #[test]
+    fn test_interpolated_normal() {
+        let (v0, v1, v2) = unit_triangle();
+        let normals = [Vec3::new(0., 0., 1.), Vec3::new(1., 0., 0.), Vec3::new(0., 0., 1.)];
+        let tri = Triangle::with_normals(v0, v1, v2, normals, Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))));
+        let r = Ray::new(Vec3::new(0.5, 0., 2.), Vec3::new(0., 0., -1.));
+        let rec = tri.hit(&r, 0., 100.).unwrap();
+        let s = 0.5f64.sqrt();
+        assert_approx_eq!(s, rec.normal.x());
+        assert_approx_eq!(0.0, rec.normal.y());
+        assert_approx_eq!(s, rec.normal.z());
+    }
+}
@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+use rng::Sampler;
+use hitable::{HitRecord, Hitable};
+use material::Material;
+use aabb::Aabb;
+use bvh::BvhNode;
+use triangle;
+
+// Vertex data shared by every triangle of a mesh
+struct MeshData<T: ElemT> {
+    positions: Vec<Vec3<T>>,
+    normals: Option<Vec<Vec3<T>>>,
+    uvs: Option<Vec<(T, T)>>,
+    indices: Vec<[usize; 3]>,
+    material: Box<dyn Material<T>>
+}
+
+// A single face of a mesh. Only holds an index so a face costs a pointer and
+// a usize no matter how much per-vertex data the mesh has.
+struct MeshTriangle<T: ElemT> {
+    mesh: Arc<MeshData<T>>,
+    face: usize
+}
+
+impl<T: ElemT> MeshTriangle<T> {
+    fn vertices(&self) -> (&Vec3<T>, &Vec3<T>, &Vec3<T>) {
+        let [i0, i1, i2] = self.mesh.indices[self.face];
+        (&self.mesh.positions[i0], &self.mesh.positions[i1], &self.mesh.positions[i2])
+    }
+}
+
+impl<T: ElemT> Hitable<T> for MeshTriangle<T> {
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        let (v0, v1, v2) = self.vertices();
+        let (t, b1, b2) = triangle::intersect(r, v0, v1, v2, t_min, t_max)?;
+        let [i0, i1, i2] = self.mesh.indices[self.face];
+        let (u, v) = match self.mesh.uvs {
+            Some(ref uvs) => {
+                let b0 = T::one() - b1 - b2;
+                (uvs[i0].0*b0 + uvs[i1].0*b1 + uvs[i2].0*b2,
+                 uvs[i0].1*b0 + uvs[i1].1*b1 + uvs[i2].1*b2)
+            }
+            None => (b1, b2)
+        };
+        let mut rec = HitRecord::<T>::default();
+        rec.t = t;
+        rec.u = u;
+        rec.v = v;
+        rec.p = r.point_at_parameter(rec.t);
+        rec.normal = match self.mesh.normals {
+            Some(ref normals) => {
+                triangle::shading_normal(&normals[i0], &normals[i1], &normals[i2], b1, b2)
+            }
+            None => (v1 - v0).cross(&(v2 - v0)).unit_vector()
+        };
+        rec.mat_opt = Some(&*self.mesh.material);
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        let (v0, v1, v2) = self.vertices();
+        Some(triangle::bounding_box(v0, v1, v2))
+    }
+
+    fn sample(&self, origin: &Vec3<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, T)> {
+        let (v0, v1, v2) = self.vertices();
+        triangle::sample(v0, v1, v2, origin, sampler)
+    }
+
+    fn pdf(&self, origin: &Vec3<T>, direction: &Vec3<T>) -> T {
+        let (v0, v1, v2) = self.vertices();
+        triangle::pdf(v0, v1, v2, origin, direction)
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Hitable<T>>) {
+        if self.mesh.material.is_emissive() {
+            lights.push(self);
+        }
+    }
+}
+
+// Indexed triangle mesh. `normals` and `uvs`, when given, are per-vertex and
+// indexed by the same `indices` as `positions`.
+pub struct TriangleMesh<T: ElemT> {
+    bvh: BvhNode<T>
+}
+
+impl<T: ElemT> TriangleMesh<T> {
+    pub fn new(positions: Vec<Vec3<T>>,
+               indices: Vec<[usize; 3]>,
+               normals: Option<Vec<Vec3<T>>>,
+               uvs: Option<Vec<(T, T)>>,
+               mat: Box<dyn Material<T>>) -> TriangleMesh<T> {
+        let nverts = positions.len();
+        assert!(indices.iter().all(|f| f.iter().all(|&i| i < nverts)), "mesh index out of range");
+        assert!(normals.as_ref().is_none_or(|n| n.len() == nverts), "mesh needs one normal per vertex");
+        assert!(uvs.as_ref().is_none_or(|uv| uv.len() == nverts), "mesh needs one uv per vertex");
+
+        let nfaces = indices.len();
+        let mesh = Arc::new(MeshData {
+            positions,
+            normals,
+            uvs,
+            indices,
+            material: mat
+        });
+        let faces = (0..nfaces)
+            .map(|face| Box::new(MeshTriangle { mesh: mesh.clone(), face }) as Box<dyn Hitable<T>>)
+            .collect();
+        TriangleMesh::<T> {
+            bvh: BvhNode::new(faces)
+        }
+    }
+}
+
+impl<T: ElemT> Hitable<T> for TriangleMesh<T> {
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        self.bvh.hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        self.bvh.bounding_box()
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Hitable<T>>) {
+        self.bvh.collect_lights(lights);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TriangleMesh;
+    use vec3::Vec3;
+    use ray::Ray;
+    use hitable::Hitable;
+    use lambertian::Lambertian;
+
+    #[test]
+    fn test_quad() {
+        // unit quad in the z=0 plane made of two triangles sharing an edge
+        let positions = vec![Vec3::<f64>::new(0., 0., 0.), Vec3::new(1., 0., 0.),
+                             Vec3::new(1., 1., 0.), Vec3::new(0., 1., 0.)];
+        let mesh = TriangleMesh::new(positions, vec![[0, 1, 2], [0, 2, 3]], None, None,
+                                     Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))));
+
+        for &(x, y) in &[(0.75, 0.25), (0.25, 0.75)] {
+            let rec = mesh.hit(&Ray::new(Vec3::new(x, y, 1.), Vec3::new(0., 0., -1.)), 0., 100.).unwrap();
+            assert_approx_eq!(1.0, rec.t);
+            assert_approx_eq!(1.0, rec.normal.z());
+        }
+        assert!(mesh.hit(&Ray::new(Vec3::new(1.5, 0.5, 1.), Vec3::new(0., 0., -1.)), 0., 100.).is_none());
+    }
+
+    #[test]
+    fn test_uvs_interpolated() {
+        let positions = vec![Vec3::<f64>::new(0., 0., 0.), Vec3::new(2., 0., 0.),
+                             Vec3::new(2., 2., 0.), Vec3::new(0., 2., 0.)];
+        let uvs = vec![(0., 0.), (1., 0.), (1., 1.), (0., 1.)];
+        let mesh = TriangleMesh::new(positions, vec![[0, 1, 2], [0, 2, 3]], None, Some(uvs),
+                                     Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))));
+
+        for &(x, y) in &[(1.5, 0.5), (0.5, 1.5)] {
+            let rec = mesh.hit(&Ray::new(Vec3::new(x, y, 1.), Vec3::new(0., 0., -1.)), 0., 100.).unwrap();
+            assert_approx_eq!(x/2., rec.u);
+            assert_approx_eq!(y/2., rec.v);
+        }
+    }
+}
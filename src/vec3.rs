@@ -15,7 +15,10 @@ pub trait ElemT : Float
                 + SubAssign
                 + MulAssign
                 + DivAssign
-                + Display {}
+                + Display
+                + Send
+                + Sync
+                + 'static {}
 impl<T: Float
       + Default
       + FromPrimitive
@@ -24,6 +27,9 @@ impl<T: Float
       + MulAssign
       + DivAssign
       + Display
+      + Send
+      + Sync
+      + 'static
       > ElemT for T {}
 
 #[derive(Clone)]
@@ -51,7 +57,7 @@ impl<T: ElemT> Vec3<T> {
     //     Some(Vec3::new(x, 0.0, 0.0))
     // }
 
-    pub fn from_vector(v: &Vec<T>) -> Vec3<T> {
+    pub fn from_vector(v: &[T]) -> Vec3<T> {
         Vec3::new(v[0], v[1], v[2])
     }
 
@@ -76,13 +82,13 @@ impl<T: ElemT> Vec3<T> {
 
     pub fn cross(&self, rhs: &Vec3<T>) -> Vec3<T> {
         Vec3::<T>::new(self.y() * rhs.z() - self.z() * rhs.y(),
-                    (-(self.x() * rhs.z() - self.z() * rhs.x())),
+                     -(self.x() * rhs.z() - self.z() * rhs.x()),
                        self.x() * rhs.y() - self.y() * rhs.x())
     }
 
     pub fn unit_vector(&self) -> Vec3<T> {
         let vnew = Vec3::new(self.x(), self.y(), self.z());
-        (vnew / self.length())
+        vnew / self.length()
     }
 
     pub fn make_unit_vector(&mut self) {
@@ -208,7 +214,7 @@ impl<T: ElemT> Add<Vec3<T>> for Vec3<T> {
     }
 }
 
-impl<'a, T: ElemT> Add<Vec3<T>> for &'a Vec3<T> {
+impl<T: ElemT> Add<Vec3<T>> for &Vec3<T> {
     type Output = Vec3<T>;
     fn add(self, rhs: Vec3<T>) -> Vec3<T> {
         Vec3::new(self.x() + rhs.x(),
@@ -217,18 +223,18 @@ impl<'a, T: ElemT> Add<Vec3<T>> for &'a Vec3<T> {
     }
 }
 
-impl<'a, T: ElemT> Add<&'a Vec3<T>> for Vec3<T> {
+impl<T: ElemT> Add<&Vec3<T>> for Vec3<T> {
     type Output = Vec3<T>;
-    fn add(self, rhs: &'a Vec3<T>) -> Vec3<T> {
+    fn add(self, rhs: &Vec3<T>) -> Vec3<T> {
         Vec3::new(self.x() + rhs.x(),
                   self.y() + rhs.y(),
                   self.z() + rhs.z())
     }
 }
 
-impl<'a, 'b, T: ElemT> Add<&'b Vec3<T>> for &'a Vec3<T> {
+impl<T: ElemT> Add<&Vec3<T>> for &Vec3<T> {
     type Output = Vec3<T>;
-    fn add(self, rhs: &'b Vec3<T>) -> Vec3<T> {
+    fn add(self, rhs: &Vec3<T>) -> Vec3<T> {
         Vec3::new(self.x() + rhs.x(),
                   self.y() + rhs.y(),
                   self.z() + rhs.z())
@@ -244,7 +250,7 @@ impl<T: ElemT> Sub for Vec3<T> {
     }
 }
 
-impl<'a, T: ElemT> Sub<Vec3<T>> for &'a Vec3<T> {
+impl<T: ElemT> Sub<Vec3<T>> for &Vec3<T> {
     type Output = Vec3<T>;
     fn sub(self, rhs: Vec3<T>) -> Vec3<T> {
         Vec3::new(self.x() - rhs.x(),
@@ -253,18 +259,18 @@ impl<'a, T: ElemT> Sub<Vec3<T>> for &'a Vec3<T> {
     }
 }
 
-impl<'a, T: ElemT> Sub<&'a Vec3<T>> for Vec3<T> {
+impl<T: ElemT> Sub<&Vec3<T>> for Vec3<T> {
     type Output = Vec3<T>;
-    fn sub(self, rhs: &'a Vec3<T>) -> Vec3<T> {
+    fn sub(self, rhs: &Vec3<T>) -> Vec3<T> {
         Vec3::new(self.x() - rhs.x(),
                   self.y() - rhs.y(),
                   self.z() - rhs.z())
     }
 }
 
-impl<'a, 'b, T: ElemT> Sub<&'b Vec3<T>> for &'a Vec3<T> {
+impl<T: ElemT> Sub<&Vec3<T>> for &Vec3<T> {
     type Output = Vec3<T>;
-    fn sub(self, rhs: &'b Vec3<T>) -> Vec3<T> {
+    fn sub(self, rhs: &Vec3<T>) -> Vec3<T> {
         Vec3::new(self.x() - rhs.x(),
                   self.y() - rhs.y(),
                   self.z() - rhs.z())
@@ -282,9 +288,9 @@ impl<T: ElemT> Mul for Vec3<T> {
 
 // TODO: other Mul's
 
-impl<'a, 'b, T: ElemT> Mul<&'b Vec3<T>> for &'a Vec3<T> {
+impl<T: ElemT> Mul<&Vec3<T>> for &Vec3<T> {
     type Output = Vec3<T>;
-    fn mul(self, rhs: &'b Vec3<T>) -> Vec3<T> {
+    fn mul(self, rhs: &Vec3<T>) -> Vec3<T> {
         Vec3::new(self.x() * rhs.x(),
                   self.y() * rhs.y(),
                   self.z() * rhs.z())
@@ -309,7 +315,7 @@ impl<T: ElemT> Mul<T> for Vec3<T> {
     }
 }
 
-impl<'a, T: ElemT> Mul<T> for &'a Vec3<T> {
+impl<T: ElemT> Mul<T> for &Vec3<T> {
     type Output = Vec3<T>;
     fn mul(self, rhs: T) -> Vec3<T> {
         Vec3::new(self.x() * rhs,
@@ -327,7 +333,7 @@ impl<T: ElemT> Div<T> for Vec3<T> {
     }
 }
 
-impl<'a, T: ElemT> Div<T> for &'a Vec3<T> {
+impl<T: ElemT> Div<T> for &Vec3<T> {
     type Output = Vec3<T>;
     fn div(self, rhs: T) -> Vec3<T> {
         Vec3::new(self.x() / rhs,
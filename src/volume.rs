@@ -0,0 +1,250 @@
+use std::sync::Arc;
+
+use vec3::{ElemT, Vec3};
+use ray::Ray;
+use rng::{self, Sampler};
+use hitable::{HitRecord, Hitable};
+use material::Material;
+use phase::HenyeyGreenstein;
+use aabb::Aabb;
+use voxelgrid::VoxelGrid;
+
+// The particles of a volume. Of the light meeting them `albedo` is scattered
+// by the phase function and the rest absorbed, which is also how much of the
+// emission gets out: a collision is an emission event with probability
+// 1 - albedo. The emission grid's value is passed in rec.u, since it's looked
+// up in the volume's own space and the material only sees the world.
+struct VolumeMaterial<T: ElemT> {
+    phase: HenyeyGreenstein<T>,
+    absorbed: Vec3<T>,
+    emission: Vec3<T>
+}
+
+impl<T: ElemT> Material<T> for VolumeMaterial<T> {
+    fn scatter(&self, r_in: &Ray<T>, rec: &HitRecord<T>, sampler: &mut Sampler) -> Option<(Vec3<T>, Ray<T>)> {
+        self.phase.scatter(r_in, rec, sampler)
+    }
+
+    #[allow(unused_variables)]
+    fn emitted(&self, r_in: &Ray<T>, rec: &HitRecord<T>) -> Vec3<T> {
+        &self.emission * &self.absorbed * rec.u
+    }
+
+    fn eval(&self, r_in: &Ray<T>, rec: &HitRecord<T>, direction: &Vec3<T>) -> Option<(Vec3<T>, T)> {
+        self.phase.eval(r_in, rec, direction)
+    }
+}
+
+// Smoke, clouds or fire whose density varies through a box, read from a
+// voxel grid stretched over it. Density is the grid value times `scale`,
+// per unit length. Rays are tracked through it by delta tracking against
+// the grid's largest density, and shadow rays get their transmittance by
+// ratio tracking, both of which are unbiased however the density varies.
+// Like ConstantMedium, both draw from the thread's generator in rng.
+pub struct Volume<T: ElemT> {
+    density: Arc<VoxelGrid>,
+    emission: Option<Arc<VoxelGrid>>,
+    corner: Vec3<T>,
+    extent: Vec3<T>,
+    bounds: Aabb<T>,
+    scale: T,
+    majorant: T,
+    material: VolumeMaterial<T>
+}
+
+impl<T: ElemT> Volume<T> {
+    pub fn new(density: Arc<VoxelGrid>, corner0: Vec3<T>, corner1: Vec3<T>, scale: T, albedo: Vec3<T>, g: T) -> Volume<T> {
+        let mut min = Vec3::default();
+        let mut max = Vec3::default();
+        for a in 0..3 {
+            min[a] = corner0[a].min(corner1[a]);
+            max[a] = corner0[a].max(corner1[a]);
+        }
+        let one = Vec3::new(T::one(), T::one(), T::one());
+        Volume {
+            majorant: T::from_f32(density.max()).unwrap() * scale,
+            density,
+            emission: None,
+            extent: &max - &min,
+            corner: min.clone(),
+            bounds: Aabb::new(min, max),
+            scale,
+            material: VolumeMaterial {
+                phase: HenyeyGreenstein::new(albedo.clone(), g),
+                absorbed: one - albedo,
+                emission: Vec3::default()
+            }
+        }
+    }
+
+    // Makes it glow with `color` times the grid's value, on the same box
+    pub fn with_emission(mut self, grid: Arc<VoxelGrid>, color: Vec3<T>) -> Volume<T> {
+        self.emission = Some(grid);
+        self.material.emission = color;
+        self
+    }
+
+    // Where `p` falls in the grid, [0, 1] on each axis inside the box
+    fn grid_point(&self, p: &Vec3<T>) -> Vec3<T> {
+        let mut q = Vec3::default();
+        for a in 0..3 {
+            q[a] = (p[a] - self.corner[a]) / self.extent[a];
+        }
+        q
+    }
+
+    fn density_at(&self, p: &Vec3<T>) -> T {
+        self.density.lookup(&self.grid_point(p)) * self.scale
+    }
+
+    // The next place along the ray, in ray parameter, that the majorant
+    // density would collide after `t`
+    fn step(&self, t: T, speed: T) -> T {
+        t - T::from_f64((1. - rng::next_f64()).ln()).unwrap() / (self.majorant * speed)
+    }
+}
+
+impl<T: ElemT> Hitable<T> for Volume<T> {
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> Option<HitRecord<'_, T>> {
+        if self.majorant <= T::zero() {
+            return None;
+        }
+        let (a, b) = self.bounds.interval(r, t_min, t_max)?;
+        let speed = r.direction().length();
+        let mut t = a;
+        loop {
+            t = self.step(t, speed);
+            if t >= b {
+                return None;
+            }
+            let p = r.point_at_parameter(t);
+            // a real collision rather than a null one
+            if T::from_f64(rng::next_f64()).unwrap() * self.majorant < self.density_at(&p) {
+                let q = self.grid_point(&p);
+                let glow = self.emission.as_ref().map_or(T::zero(), |grid| grid.lookup(&q));
+                return Some(HitRecord {
+                    t,
+                    u: glow,
+                    v: T::zero(),
+                    p,
+                    // arbitrary, phase functions don't use it
+                    normal: Vec3::new(T::one(), T::zero(), T::zero()),
+                    mat_opt: Some(&self.material)
+                });
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        Some(self.bounds.clone())
+    }
+
+    fn hit_surface(&self, _r: &Ray<T>, _t_min: T, _t_max: T) -> Option<HitRecord<'_, T>> {
+        None
+    }
+
+    fn transmittance(&self, r: &Ray<T>, t_min: T, t_max: T) -> T {
+        if self.majorant <= T::zero() {
+            return T::one();
+        }
+        let (a, b) = match self.bounds.interval(r, t_min, t_max) {
+            Some(interval) => interval,
+            None => return T::one()
+        };
+        let speed = r.direction().length();
+        let mut tr = T::one();
+        let mut t = a;
+        loop {
+            t = self.step(t, speed);
+            if t >= b {
+                return tr;
+            }
+            tr *= T::one() - self.density_at(&r.point_at_parameter(t)) / self.majorant;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::Volume;
+    use vec3::Vec3;
+    use ray::Ray;
+    use hitable::Hitable;
+    use hitablelist::HitableList;
+    use sphere::Sphere;
+    use lambertian::Lambertian;
+    use voxelgrid::VoxelGrid;
+    use rng;
+
+    fn white() -> Vec3<f64> {
+        Vec3::new(1., 1., 1.)
+    }
+
+    // Half the voxels full and half empty, split at x = 0.5
+    fn half() -> Arc<VoxelGrid> {
+        Arc::new(VoxelGrid::dense([4, 1, 1], vec![1., 1., 0., 0.]))
+    }
+
+    #[test]
+    fn test_tracking() {
+        rng::seed(5);
+        let n = 100000;
+        // a uniform cube 2 across of density 0.5
+        let grid = Arc::new(VoxelGrid::dense([2, 2, 2], vec![1.; 8]));
+        let cube = Volume::new(grid, Vec3::new(-1., -1., -1.), Vec3::new(1., 1., 1.), 0.5, white(), 0.);
+        let r = Ray::new(Vec3::new(0., 0., 5.), Vec3::new(0., 0., -2.));
+        let passed = (0..n).filter(|_| cube.hit(&r, 0.001, 100.).is_none()).count() as f64 / n as f64;
+        assert_approx_eq!((-1f64).exp(), passed, 0.01);
+        let tr = (0..n).map(|_| cube.transmittance(&r, 0.001, 100.)).sum::<f64>() / n as f64;
+        assert_approx_eq!((-1f64).exp(), tr, 0.01);
+        // shadow rays see through it to what's behind
+        assert!(cube.hit_surface(&r, 0.001, 100.).is_none());
+
+        // along x through the half that's full and the half that's empty,
+        // with the interpolated ramp of width one voxel between them
+        let slab = Volume::new(half(), Vec3::new(0., 0., 0.), Vec3::new(4., 1., 1.), 1., white(), 0.);
+        let r = Ray::new(Vec3::new(-1., 0.5, 0.5), Vec3::new(1., 0., 0.));
+        let passed = (0..n).filter(|_| slab.hit(&r, 0.001, 100.).is_none()).count() as f64 / n as f64;
+        assert_approx_eq!((-2f64).exp(), passed, 0.01);
+        for _ in 0..1000 {
+            if let Some(rec) = slab.hit(&r, 0.001, 100.) {
+                assert!(rec.p.x() < 2.5);
+            }
+        }
+        let tr = (0..n).map(|_| slab.transmittance(&r, 0.001, 100.)).sum::<f64>() / n as f64;
+        assert_approx_eq!((-2f64).exp(), tr, 0.01);
+        // starting at the far end there's nothing to get through
+        assert_approx_eq!(1.0, slab.transmittance(&r, 4., 100.));
+    }
+
+    #[test]
+    fn test_in_list() {
+        rng::seed(6);
+        let grid = Arc::new(VoxelGrid::dense([2, 2, 2], vec![1.; 8]));
+        let glow = Arc::new(VoxelGrid::dense([1, 1, 1], vec![0.5]));
+        let cloud = Volume::new(grid, Vec3::new(-1., -1., -1.), Vec3::new(1., 1., 1.), 100., Vec3::new(0.5, 0.5, 0.5), 0.)
+            .with_emission(glow, Vec3::new(2., 4., 6.));
+        let bbox = cloud.bounding_box().unwrap();
+        assert_approx_eq!(-1f64, bbox.min().x());
+        assert_approx_eq!(1f64, bbox.max().z());
+
+        let ball = Sphere::new(Vec3::new(0., 0., -5.), 1., Box::new(Lambertian::new(white())));
+        let world = HitableList::new(vec![Box::new(cloud), Box::new(ball)]);
+        // so dense it's hit almost at once, and glows with half its emission
+        let r = Ray::new(Vec3::new(0., 0., 5.), Vec3::new(0., 0., -1.));
+        let rec = world.hit(&r, 0.001, 100.).unwrap();
+        assert!(rec.t > 4. && rec.t < 4.2);
+        let le = rec.mat_opt.unwrap().emitted(&r, &rec);
+        assert_approx_eq!(0.5, le.x());
+        assert_approx_eq!(1.5, le.z());
+        // shadow rays go on to the ball, and hardly any light gets through
+        assert_approx_eq!(9.0, world.hit_surface(&r, 0.001, 100.).unwrap().t);
+        assert!(world.transmittance(&r, 0.001, 100.) < 1e-6);
+        // a ray beside the cloud is untouched
+        let r = Ray::new(Vec3::new(3., 0., 5.), Vec3::new(0., 0., -1.));
+        assert!(world.hit(&r, 0.001, 100.).is_none());
+        assert_approx_eq!(1.0, world.transmittance(&r, 0.001, 100.));
+    }
+}
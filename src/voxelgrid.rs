@@ -0,0 +1,338 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use toml::Value;
+
+use vec3::{ElemT, Vec3};
+
+#[derive(Debug)]
+pub enum GridError {
+    Io(String, io::Error),
+    Format(String, String)
+}
+
+impl fmt::Display for GridError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GridError::Io(ref file, ref err) => write!(f, "{}: {}", file, err),
+            GridError::Format(ref file, ref msg) => write!(f, "{}: {}", file, msg)
+        }
+    }
+}
+
+fn format_error<R>(file: &str, msg: &str) -> Result<R, GridError> {
+    Err(GridError::Format(file.to_string(), msg.to_string()))
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, GridError> {
+    fs::read(path).map_err(|e| GridError::Io(path.display().to_string(), e))
+}
+
+// How values are stored in the data files
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+enum ValueFormat {
+    // little endian 32-bit floats
+    F32,
+    // bytes, 255 standing for 1
+    U8
+}
+
+impl ValueFormat {
+    fn from_name(name: &str) -> Option<ValueFormat> {
+        match name {
+            "f32" => Some(ValueFormat::F32),
+            "u8" => Some(ValueFormat::U8),
+            _ => None
+        }
+    }
+
+    fn width(self) -> usize {
+        match self {
+            ValueFormat::F32 => 4,
+            ValueFormat::U8 => 1
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> Vec<f32> {
+        match self {
+            ValueFormat::F32 => bytes.chunks(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect(),
+            ValueFormat::U8 => bytes.iter().map(|&b| b as f32 / 255.).collect()
+        }
+    }
+}
+
+enum Storage {
+    // every voxel, x varying fastest, then y, then z
+    Dense(Vec<f32>),
+    // cubes of block_size voxels a side, laid out like a dense grid of
+    // blocks. Only blocks with something in them are kept, like the leaf
+    // nodes of OpenVDB; the rest read as zero.
+    Sparse { block_size: usize, blocks_across: [usize; 3], blocks: Vec<Option<Vec<f32>>> }
+}
+
+// A grid of values, e.g. smoke density or flame temperature, stored as f32
+// whatever the float type of the render. Values are taken to sit at the
+// centres of the voxels.
+pub struct VoxelGrid {
+    size: [usize; 3],
+    storage: Storage,
+    max: f32
+}
+
+fn largest(values: &[f32]) -> f32 {
+    values.iter().cloned().fold(0., f32::max)
+}
+
+impl VoxelGrid {
+    pub fn dense(size: [usize; 3], values: Vec<f32>) -> VoxelGrid {
+        assert_eq!(size[0]*size[1]*size[2], values.len());
+        VoxelGrid { size, max: largest(&values), storage: Storage::Dense(values) }
+    }
+
+    // `blocks` are the block coordinates (in units of blocks) and values of
+    // each block present
+    pub fn sparse(size: [usize; 3], block_size: usize, blocks: Vec<([usize; 3], Vec<f32>)>) -> VoxelGrid {
+        let across = [0, 1, 2].map(|a| size[a].div_ceil(block_size));
+        let mut table = vec![None; across[0]*across[1]*across[2]];
+        let mut max = 0f32;
+        for (b, values) in blocks {
+            assert_eq!(block_size*block_size*block_size, values.len());
+            max = max.max(largest(&values));
+            table[(b[2]*across[1] + b[1])*across[0] + b[0]] = Some(values);
+        }
+        VoxelGrid { size, max, storage: Storage::Sparse { block_size, blocks_across: across, blocks: table } }
+    }
+
+    // Reads a grid described by a small TOML header:
+    //
+    //   size = [64, 64, 64]    voxels along x, y and z
+    //   format = "f32"         or "u8", for the data files
+    //   data = "smoke.raw"     a dense grid, x varying fastest, then y, then z
+    //
+    // or, in place of `data`, sparse blocks:
+    //
+    //   blocks = "smoke.blocks"
+    //   block_size = 8
+    //
+    // where the blocks file holds any number of blocks, each three little
+    // endian u32 block coordinates followed by its block_size^3 values in
+    // the same order as a dense grid. File names are relative to the header.
+    pub fn load(path: &Path) -> Result<VoxelGrid, GridError> {
+        let name = path.display().to_string();
+        let text = String::from_utf8(read(path)?).or_else(|_| format_error(&name, "header isn't text"))?;
+        let header = match text.parse::<Value>() {
+            Ok(header) => header,
+            Err(e) => return format_error(&name, &e.to_string())
+        };
+        let get = |key: &str| header.get(key);
+
+        let size = match get("size").and_then(|v| v.as_array()) {
+            Some(xs) if xs.len() == 3 => {
+                let mut size = [0; 3];
+                for (a, x) in xs.iter().enumerate() {
+                    size[a] = match x.as_integer() {
+                        Some(n) if n > 0 => n as usize,
+                        _ => return format_error(&name, "size should be 3 positive integers")
+                    };
+                }
+                size
+            }
+            _ => return format_error(&name, "size should be 3 positive integers")
+        };
+        let format = match get("format") {
+            None => ValueFormat::F32,
+            Some(v) => match v.as_str().and_then(ValueFormat::from_name) {
+                Some(format) => format,
+                None => return format_error(&name, "format should be \"f32\" or \"u8\"")
+            }
+        };
+        let file = |key: &str| -> Result<Option<Vec<u8>>, GridError> {
+            match get(key) {
+                None => Ok(None),
+                Some(v) => match v.as_str() {
+                    Some(file) => read(&path.parent().unwrap_or(Path::new("")).join(file)).map(Some),
+                    None => format_error(&name, &format!("{} should be a file name", key))
+                }
+            }
+        };
+
+        // densities and the like, which can't go below zero
+        let decode = |bytes: &[u8]| -> Result<Vec<f32>, GridError> {
+            let values = format.decode(bytes);
+            if values.iter().any(|x| !(x.is_finite() && *x >= 0.)) {
+                return format_error(&name, "values should be finite and non-negative");
+            }
+            Ok(values)
+        };
+
+        match (file("data")?, file("blocks")?) {
+            (Some(bytes), None) => {
+                let n = size[0]*size[1]*size[2];
+                if bytes.len() != n * format.width() {
+                    return format_error(&name, &format!("expected {} values in the data file, found {} bytes", n, bytes.len()));
+                }
+                Ok(VoxelGrid::dense(size, decode(&bytes)?))
+            }
+            (None, Some(bytes)) => {
+                let block_size = match get("block_size").map(|v| v.as_integer()) {
+                    None => 8,
+                    Some(Some(n)) if n > 0 => n as usize,
+                    Some(_) => return format_error(&name, "block_size should be a positive integer")
+                };
+                let across = [0, 1, 2].map(|a| size[a].div_ceil(block_size));
+                let record = 12 + block_size*block_size*block_size*format.width();
+                if bytes.len() % record != 0 {
+                    return format_error(&name, "the blocks file doesn't hold a whole number of blocks");
+                }
+                let mut blocks = Vec::new();
+                for chunk in bytes.chunks(record) {
+                    let mut b = [0; 3];
+                    for (a, coord) in chunk[..12].chunks(4).enumerate() {
+                        b[a] = u32::from_le_bytes([coord[0], coord[1], coord[2], coord[3]]) as usize;
+                        if b[a] >= across[a] {
+                            return format_error(&name, &format!("block {:?} is outside the grid", b));
+                        }
+                    }
+                    blocks.push((b, decode(&chunk[12..])?));
+                }
+                Ok(VoxelGrid::sparse(size, block_size, blocks))
+            }
+            _ => format_error(&name, "expected one of data or blocks")
+        }
+    }
+
+    // The largest value anywhere, which no lookup can exceed
+    pub fn max(&self) -> f32 {
+        self.max
+    }
+
+    pub fn voxel(&self, i: usize, j: usize, k: usize) -> f32 {
+        match self.storage {
+            Storage::Dense(ref values) => values[(k*self.size[1] + j)*self.size[0] + i],
+            Storage::Sparse { block_size, blocks_across, ref blocks } => {
+                let (bi, bj, bk) = (i / block_size, j / block_size, k / block_size);
+                match blocks[(bk*blocks_across[1] + bj)*blocks_across[0] + bi] {
+                    Some(ref values) => {
+                        let (x, y, z) = (i % block_size, j % block_size, k % block_size);
+                        values[(z*block_size + y)*block_size + x]
+                    }
+                    None => 0.
+                }
+            }
+        }
+    }
+
+    // Trilinear interpolation at `p`, with the grid spanning [0, 1] on each
+    // axis. Zero outside it.
+    pub fn lookup<T: ElemT>(&self, p: &Vec3<T>) -> T {
+        let mut lo = [0; 3];
+        let mut hi = [0; 3];
+        let mut frac = [0.; 3];
+        for a in 0..3 {
+            let x = p[a].to_f64().unwrap();
+            if !(0. ..=1.).contains(&x) {
+                return T::zero();
+            }
+            let n = self.size[a];
+            let x = x * n as f64 - 0.5;
+            let i = x.floor();
+            frac[a] = x - i;
+            // clamped at the edges, where there's no voxel further out
+            lo[a] = (i.max(0.) as usize).min(n - 1);
+            hi[a] = ((i + 1.).max(0.) as usize).min(n - 1);
+        }
+        let mut value = 0.;
+        for corner in 0..8 {
+            let mut weight = 1.;
+            let mut idx = [0; 3];
+            for a in 0..3 {
+                let upper = corner >> a & 1 == 1;
+                idx[a] = if upper { hi[a] } else { lo[a] };
+                weight *= if upper { frac[a] } else { 1. - frac[a] };
+            }
+            if weight > 0. {
+                value += weight * self.voxel(idx[0], idx[1], idx[2]) as f64;
+            }
+        }
+        T::from_f64(value).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use super::VoxelGrid;
+    use vec3::Vec3;
+
+    // 2x2x2 with the value i + 2j + 4k
+    fn ramp() -> Vec<f32> {
+        (0..8).map(|n| n as f32).collect()
+    }
+
+    #[test]
+    fn test_lookup() {
+        let grid = VoxelGrid::dense([2, 2, 2], ramp());
+        assert_eq!(7., grid.max());
+        assert_eq!(6., grid.voxel(0, 1, 1));
+        // voxel centres are at 1/4 and 3/4
+        assert_approx_eq!(0.0f64, grid.lookup(&Vec3::new(0.25, 0.25, 0.25)));
+        assert_approx_eq!(3.5f64, grid.lookup(&Vec3::new(0.5, 0.5, 0.5)));
+        assert_approx_eq!(1.0f64, grid.lookup(&Vec3::new(0.75, 0.1, 0.2)));
+        assert_approx_eq!(0.0f64, grid.lookup(&Vec3::new(0.5, 1.5, 0.5)));
+    }
+
+    #[test]
+    fn test_sparse() {
+        // 16 voxels a side in blocks of 8, with just one block filled
+        let block: Vec<f32> = (0..512).map(|n| (n % 8) as f32).collect();
+        let grid = VoxelGrid::sparse([16, 16, 16], 8, vec![([1, 0, 1], block)]);
+        assert_eq!(7., grid.max());
+        assert_eq!(3., grid.voxel(11, 2, 9));
+        assert_eq!(0., grid.voxel(3, 2, 9));
+        assert_approx_eq!(0f64, grid.lookup(&Vec3::new(0.2, 0.2, 0.2)));
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = env::temp_dir().join(format!("voxelgrid_test_{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let bytes: Vec<u8> = ramp().iter().flat_map(|x| x.to_le_bytes().to_vec()).collect();
+        fs::write(dir.join("ramp.raw"), bytes).unwrap();
+        fs::write(dir.join("ramp.grid"), "size = [2, 2, 2]\ndata = \"ramp.raw\"\n").unwrap();
+        let grid = VoxelGrid::load(&dir.join("ramp.grid")).unwrap();
+        assert_eq!(5., grid.voxel(1, 0, 1));
+
+        // one 2x2x2 block of bytes in the corner of a 4x4x4 grid
+        let mut bytes = Vec::new();
+        for coord in &[1u32, 0, 1] {
+            bytes.extend_from_slice(&coord.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[0, 51, 102, 153, 204, 255, 0, 0]);
+        fs::write(dir.join("corner.blocks"), bytes).unwrap();
+        fs::write(dir.join("corner.grid"), "size = [4, 4, 4]\nformat = \"u8\"\nblocks = \"corner.blocks\"\nblock_size = 2\n").unwrap();
+        let grid = VoxelGrid::load(&dir.join("corner.grid")).unwrap();
+        assert_approx_eq!(1.0, grid.voxel(3, 0, 3));
+        assert_approx_eq!(0.2, grid.voxel(3, 0, 2));
+        assert_eq!(0., grid.voxel(0, 0, 0));
+
+        // too little data
+        fs::write(dir.join("short.grid"), "size = [3, 2, 2]\ndata = \"ramp.raw\"\n").unwrap();
+        assert!(VoxelGrid::load(&dir.join("short.grid")).is_err());
+        fs::write(dir.join("both.grid"), "size = [2, 2, 2]\n").unwrap();
+        assert!(VoxelGrid::load(&dir.join("both.grid")).is_err());
+        // negative densities
+        let bytes: Vec<u8> = ramp().iter().flat_map(|x| (x - 1.).to_le_bytes().to_vec()).collect();
+        fs::write(dir.join("negative.raw"), bytes).unwrap();
+        fs::write(dir.join("negative.grid"), "size = [2, 2, 2]\ndata = \"negative.raw\"\n").unwrap();
+        assert!(VoxelGrid::load(&dir.join("negative.grid")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}